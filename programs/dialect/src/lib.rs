@@ -2,10 +2,14 @@
 //!
 //! Dialect uses the publish-subscribe messaging pattern. In this case, Dialect "decorates" a resource on chain with messaging capabilities. It does this by creating a "Dialect" - or a message thread - as a PDA whose seeds are an address, or (sorted) set of addresses. E.g. for one-on-one messaging between two wallets, the Dialect PDA's seeds would be the two participatns' wallet addresses, sorted alphabetically.
 //!
-//! The entrypoints and data structures below implement the one-on-one messaging thread use case, as well as associated authentication and management of such threads.
+//! The entrypoints and data structures below implement one-on-one messaging threads & group messaging threads, whose PDA's seeds are the hash of their sorted members' addresses, as well as associated authentication and management of such threads.
 
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::MAX_PERMITTED_DATA_INCREASE;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::system_program;
 use solana_program::entrypoint::ProgramResult;
+use std::cell::{Ref, RefMut};
 
 declare_id!("CeNUxGUsSeb5RuAGvaMLNx3tEZrpBwQqA7Gs99vMPCAb");
 
@@ -57,6 +61,105 @@ pub mod dialect {
         Ok(())
     }
 
+    /// This function creates a metadata policy account for the signing user, which holds the user's
+    /// subscription policy & pending invites. Users without a metadata policy account have the
+    /// MembersOnly policy.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * policy: The user's subscription policy.
+    ///
+    /// See the CreateMetadataPolicy context, MetadataPolicyAccount & SubscriptionPolicy below for
+    /// more information.
+    pub fn create_metadata_policy(
+        ctx: Context<CreateMetadataPolicy>,
+        policy: SubscriptionPolicy,
+    ) -> Result<()> {
+        let metadata_policy = &mut ctx.accounts.metadata_policy.load_init()?;
+        metadata_policy.user = ctx.accounts.user.key();
+        metadata_policy.subscription_policy = policy as u8;
+        metadata_policy.invites = [Pubkey::default(); 16];
+        Ok(())
+    }
+
+    /// This function closes a metadata policy account and recovers its rent for the signing user,
+    /// who then has the MembersOnly policy. Pending invites are lost.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    ///
+    /// See the CloseMetadataPolicy context & MetadataPolicyAccount structs below for more information.
+    pub fn close_metadata_policy(_ctx: Context<CloseMetadataPolicy>) -> Result<()> {
+        Ok(())
+    }
+
+    /// This function sets the policy deciding whether other users can subscribe the signing user to
+    /// dialects directly, or only invite them to dialects.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * policy: The new subscription policy.
+    ///
+    /// See the SetSubscriptionPolicy context, MetadataPolicyAccount & SubscriptionPolicy below for
+    /// more information.
+    pub fn set_subscription_policy(
+        ctx: Context<SetSubscriptionPolicy>,
+        policy: SubscriptionPolicy,
+    ) -> Result<()> {
+        let metadata_policy = &mut ctx.accounts.metadata_policy.load_mut()?;
+        metadata_policy.subscription_policy = policy as u8;
+        Ok(())
+    }
+
+    /// This function creates a metadata page for the signing user, which holds subscriptions in
+    /// addition to those in the user's metadata account.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * page_index: The index of the metadata page, used as a seed of the page account.
+    ///
+    /// See the CreateMetadataPage context & MetadataPage structs below for more information.
+    pub fn create_metadata_page(ctx: Context<CreateMetadataPage>, page_index: u8) -> Result<()> {
+        {
+            let metadata_page = &mut ctx.accounts.metadata_page.load_init()?;
+            metadata_page.user = ctx.accounts.user.key();
+            metadata_page.page_index = page_index;
+            metadata_page.subscriptions = [Subscription::default(); 32];
+        }
+        // Metadata accounts get their bitmap of metadata pages along with their first page.
+        let metadata = &ctx.accounts.metadata.to_account_info();
+        let original_len = metadata.data_len();
+        let new_len = METADATA_ACCOUNT_SIZE + METADATA_PAGES_LENGTH;
+        if original_len < new_len {
+            top_up_rent(
+                metadata,
+                &ctx.accounts.user.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+                &ctx.accounts.rent,
+                new_len,
+            )?;
+            realloc_account(metadata, original_len, new_len)?;
+        }
+        set_metadata_page_exists(metadata, page_index, true)
+    }
+
+    /// This function closes a metadata page and recovers its rent for the signing user, who must
+    /// be the metadata page owner. All subscriptions stored in the page are lost.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * page_index: The index of the metadata page.
+    ///
+    /// See the CloseMetadataPage context & MetadataPage structs below for more information.
+    pub fn close_metadata_page(ctx: Context<CloseMetadataPage>, page_index: u8) -> Result<()> {
+        set_metadata_page_exists(&ctx.accounts.metadata.to_account_info(), page_index, false)
+    }
+
     // Dialects
 
     /// This function creates a dialect account for one-on-one messaging between two users.
@@ -66,6 +169,8 @@ pub mod dialect {
     /// * dialect_nonce: The nonce for the Dialect account.
     /// * encrypted: Whether or not to encrypt the dialect.
     /// * scopes: The scopes for the dialect's members, implicitly ordered.
+    /// * capacity: The capacity of the dialect's message buffer, between MIN_MESSAGE_BUFFER_LENGTH
+    ///   and MAX_INITIAL_MESSAGE_BUFFER_LENGTH bytes.
     ///
     /// See the CreateDialect context & DialectAccount structs below for more information.
     pub fn create_dialect(
@@ -73,48 +178,105 @@ pub mod dialect {
         _dialect_nonce: u8,
         encrypted: bool,
         scopes: [[bool; 2]; 2],
+        capacity: u16,
     ) -> Result<()> {
+        if !(MIN_MESSAGE_BUFFER_LENGTH..=MAX_INITIAL_MESSAGE_BUFFER_LENGTH).contains(&capacity) {
+            return err!(ErrorCode::InvalidMessageBufferCapacity);
+        }
         let dialect_loader = &ctx.accounts.dialect;
-        let mut dialect = dialect_loader.load_init()?;
-        let _owner = &mut ctx.accounts.owner;
-        let members = [&mut ctx.accounts.member0, &mut ctx.accounts.member1];
-
-        dialect.members = [
-            Member {
-                public_key: *members[0].key,
-                scopes: scopes[0],
-            },
-            Member {
-                public_key: *members[1].key,
-                scopes: scopes[1],
-            },
-        ];
+        let owner = &ctx.accounts.owner;
+        let member_keys = vec![ctx.accounts.member0.key(), ctx.accounts.member1.key()];
 
-        if !dialect
-            .members
-            .iter()
-            .any(|member| member.is_admin() && member.public_key == _owner.key())
+        init_dialect(
+            dialect_loader,
+            owner.key,
+            &member_keys,
+            &scopes,
+            2,
+            encrypted,
+            capacity,
+        )?;
+
+        emit!(DialectCreatedEvent {
+            dialect: dialect_loader.key(),
+            members: member_keys,
+        });
+
+        Ok(())
+    }
+
+    /// This function creates a dialect account for group messaging, with a member slot for each
+    /// member.
+    ///
+    /// The members are passed, sorted alphabetically, as the instruction's remaining accounts.
+    ///
+    /// ### Arguments
+    ///
+    /// * members_hash: The hash of the sorted members' public keys, from which the dialect's PDA is derived.
+    /// * encrypted: Whether or not to encrypt the dialect.
+    /// * scopes: The scopes for the dialect's members, in the same order as the members.
+    /// * capacity: The capacity of the dialect's message buffer, between MIN_MESSAGE_BUFFER_LENGTH
+    ///   and MAX_INITIAL_MESSAGE_BUFFER_LENGTH bytes. The members' slots take up some of the
+    ///   10240 bytes of the dialect account at creation as well.
+    /// * max_members: The maximum number of members of the dialect, see set_max_members.
+    ///
+    /// See the CreateGroupDialect context & DialectAccount structs below for more information.
+    pub fn create_group_dialect(
+        ctx: Context<CreateGroupDialect>,
+        members_hash: [u8; 32],
+        encrypted: bool,
+        scopes: Vec<[bool; 2]>,
+        capacity: u16,
+        max_members: u8,
+    ) -> Result<()> {
+        let members = ctx.remaining_accounts;
+        if capacity < MIN_MESSAGE_BUFFER_LENGTH
+            || DIALECT_ACCOUNT_SIZE + members.len() * MEMBER_SIZE + capacity as usize
+                > MAX_PERMITTED_DATA_INCREASE
         {
-            return err!(ErrorCode::DialectOwnerIsNotAdmin);
+            return err!(ErrorCode::InvalidMessageBufferCapacity);
         }
+        let dialect_loader = &ctx.accounts.dialect;
+        let owner = &ctx.accounts.owner;
 
-        let now = Clock::get()?.unix_timestamp as u32;
-        dialect.messages.read_offset = 0;
-        dialect.messages.write_offset = 0;
-        dialect.messages.items_count = 0;
-        dialect.last_message_timestamp = now;
-        dialect.encrypted = encrypted;
+        if members.len() < 2 || members.len() > max_members as usize {
+            return err!(ErrorCode::InvalidNumberOfMembers);
+        }
+        if scopes.len() != members.len() {
+            return err!(ErrorCode::MemberScopesMismatch);
+        }
+        // Assert that the members are sorted alphabetically, & unique
+        if members
+            .windows(2)
+            .any(|pair| pair[0].key().cmp(&pair[1].key()) != std::cmp::Ordering::Less)
+        {
+            return err!(ErrorCode::MembersNotSorted);
+        }
+        let member_keys: Vec<Pubkey> = members.iter().map(|m| m.key()).collect();
+        if hash_members(&member_keys) != members_hash {
+            return err!(ErrorCode::MembersHashMismatch);
+        }
+
+        init_dialect(
+            dialect_loader,
+            owner.key,
+            &member_keys,
+            &scopes,
+            max_members,
+            encrypted,
+            capacity,
+        )?;
 
         emit!(DialectCreatedEvent {
             dialect: dialect_loader.key(),
-            members: [*members[0].key, *members[1].key],
+            members: member_keys,
         });
 
         Ok(())
     }
 
-    /// This function closes a dialect account and recovers its rent for the signing user,
-    /// who must be the dialect account owner.
+    /// This function closes a dialect account and refunds its rent to the dialect's recorded
+    /// owner. The signing user must be a member with admin privileges.
     ///
     /// ### Arguments
     ///
@@ -124,19 +286,443 @@ pub mod dialect {
     /// See the CloseDialect context & DialectAccount structs below for more information.
     pub fn close_dialect(ctx: Context<CloseDialect>, _dialect_nonce: u8) -> ProgramResult {
         let dialect_loader = &ctx.accounts.dialect;
-        let dialect = dialect_loader.load()?;
+        let members = dialect_members(dialect_loader)?;
 
         emit!(DialectDeletedEvent {
             dialect: dialect_loader.key(),
-            members: [dialect.members[0].public_key, dialect.members[1].public_key],
+            members: members.iter().map(|m| m.public_key).collect(),
+        });
+
+        Ok(())
+    }
+
+    /// This function transfers the ownership of a dialect account, along with the right to
+    /// its rent when the dialect is closed. The signing user must be the dialect's recorded owner.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    ///
+    /// See the TransferDialectOwnership context & DialectAccount structs below for more information.
+    pub fn transfer_dialect_ownership(ctx: Context<TransferDialectOwnership>) -> Result<()> {
+        let dialect_loader = &ctx.accounts.dialect;
+        let mut dialect = dialect_loader.load_mut()?;
+        let owner = &ctx.accounts.owner;
+        let new_owner = &ctx.accounts.new_owner;
+        dialect.owner = new_owner.key();
+        // Emit an event for monitoring services.
+        emit!(DialectOwnershipTransferredEvent {
+            dialect: dialect_loader.key(),
+            previous_owner: owner.key(),
+            new_owner: new_owner.key(),
+        });
+        Ok(())
+    }
+
+    /// This function migrates a dialect account created before DialectAccount.version was
+    /// introduced to the current layout, in place. Legacy dialect accounts can't be used by other
+    /// instructions until they are migrated. Only a member with admin privileges can migrate a
+    /// dialect: they become its recorded owner, and top up its rent.
+    ///
+    /// The members keep their slots, and the messages get ids in the order they were sent. The
+    /// newest messages that fit in the message buffer are kept, since stored messages grow by their
+    /// header & envelope.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    ///
+    /// See the MigrateDialect context & LegacyDialectAccount structs below for more information.
+    pub fn migrate_dialect(ctx: Context<MigrateDialect>) -> Result<()> {
+        let dialect_loader = &ctx.accounts.dialect;
+        let dialect_info = dialect_loader.to_account_info();
+        let original_len = dialect_info.data_len();
+        let admin = &ctx.accounts.admin;
+        if original_len != LEGACY_DIALECT_ACCOUNT_SIZE
+            || dialect_loader.load()?.version >= DIALECT_ACCOUNT_VERSION
+        {
+            return err!(ErrorCode::DialectAlreadyMigrated);
+        }
+        let (member_slots, items, last_message_timestamp, encrypted) = {
+            let data = dialect_info.try_borrow_data()?;
+            let legacy_dialect: &LegacyDialectAccount = bytemuck::from_bytes(&data[8..]);
+            (
+                legacy_dialect.member_slots(),
+                legacy_dialect.messages(),
+                legacy_dialect.last_message_timestamp,
+                legacy_dialect.encrypted,
+            )
+        };
+        if !member_slots
+            .iter()
+            .any(|m| m.public_key == admin.key() && m.is_admin())
+        {
+            return err!(ErrorCode::DialectOwnerIsNotAdmin);
+        }
+
+        let new_len = DIALECT_ACCOUNT_SIZE
+            + member_slots.len() * MEMBER_SIZE
+            + LEGACY_MESSAGE_BUFFER_LENGTH as usize;
+        top_up_rent(
+            &dialect_info,
+            &admin.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.rent,
+            new_len,
+        )?;
+        realloc_account(&dialect_info, original_len, new_len)?;
+        dialect_info.try_borrow_mut_data()?[8..].fill(0);
+
+        {
+            let mut dialect = dialect_loader.load_mut()?;
+            dialect.owner = admin.key();
+            dialect.version = DIALECT_ACCOUNT_VERSION;
+            dialect.max_members = member_slots.len() as u8;
+            dialect.members_count = member_slots.len() as u8;
+            dialect.messages.capacity = LEGACY_MESSAGE_BUFFER_LENGTH;
+            dialect.last_message_timestamp = last_message_timestamp;
+            dialect.encrypted = encrypted;
+            dialect.max_message_length = dialect.max_message_length_limit();
+            dialect.next_message_id = items.len() as u64;
+            dialect.edit_window = DEFAULT_EDIT_WINDOW;
+        }
+        let (mut dialect, mut dialect_member_slots, _, mut buffer) =
+            load_dialect_mut(dialect_loader)?;
+        dialect_member_slots.copy_from_slice(&member_slots);
+        dialect.messages.reset(&mut buffer, items)?;
+        Ok(())
+    }
+
+    /// This function sets the maximum length of the messages that can be sent in a dialect.
+    /// Only a member with admin privileges can set it.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * max_message_length: The maximum length of a message's text in bytes, up to the length
+    ///   of the largest message that fits in the dialect's message buffer.
+    ///
+    /// See the SetMaxMessageLength context & DialectAccount structs below for more information.
+    pub fn set_max_message_length(
+        ctx: Context<SetMaxMessageLength>,
+        max_message_length: u16,
+    ) -> Result<()> {
+        let mut dialect = ctx.accounts.dialect.load_mut()?;
+        if max_message_length == 0 || max_message_length > dialect.max_message_length_limit() {
+            return err!(ErrorCode::InvalidMaxMessageLength);
+        }
+        dialect.max_message_length = max_message_length;
+        Ok(())
+    }
+
+    /// This function sets the time window in which the sender of a message can edit it, see
+    /// edit_message. Only a member with admin privileges can set it.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * edit_window: The time window in seconds after a message is sent, 0 to disable edits.
+    ///
+    /// See the SetEditWindow context & DialectAccount structs below for more information.
+    pub fn set_edit_window(ctx: Context<SetEditWindow>, edit_window: u32) -> Result<()> {
+        let mut dialect = ctx.accounts.dialect.load_mut()?;
+        dialect.edit_window = edit_window;
+        Ok(())
+    }
+
+    /// This function resizes the message buffer of a dialect. Only a member with admin privileges
+    /// can resize it.
+    ///
+    /// The signing admin tops up the dialect account's rent when it grows. When it shrinks, the
+    /// excess rent is kept in the account and refunded to the dialect's owner when it is closed.
+    /// The newest messages that fit in the new capacity are kept. If archival is enabled for the
+    /// dialect, the dropped messages are archived in its last message history pages, which must
+    /// then be passed as the remaining accounts, see archive_messages. Shrinking the buffer by more
+    /// than a page's worth of messages requires enough pages to be created beforehand.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * capacity: The new capacity of the message buffer, between MIN_MESSAGE_BUFFER_LENGTH and
+    ///   MAX_MESSAGE_BUFFER_LENGTH bytes.
+    ///
+    /// See the ResizeDialect context & DialectAccount structs below for more information.
+    pub fn resize_dialect(ctx: Context<ResizeDialect>, capacity: u16) -> Result<()> {
+        if !(MIN_MESSAGE_BUFFER_LENGTH..=MAX_MESSAGE_BUFFER_LENGTH).contains(&capacity) {
+            return err!(ErrorCode::InvalidMessageBufferCapacity);
+        }
+        let dialect_loader = &ctx.accounts.dialect;
+        let dialect_info = dialect_loader.to_account_info();
+        let original_len = dialect_info.data_len();
+        let (items, buffer_offset) = {
+            let (dialect, _, _, buffer) = load_dialect_mut(dialect_loader)?;
+            (dialect.messages.items(&buffer), dialect.buffer_offset())
+        };
+
+        // The message buffer is the last region of the account's data.
+        let new_len = buffer_offset + capacity as usize;
+        top_up_rent(
+            &dialect_info,
+            &ctx.accounts.admin.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.rent,
+            new_len,
+        )?;
+        realloc_account(&dialect_info, original_len, new_len)?;
+
+        let (mut dialect, _, _, mut buffer) = load_dialect_mut(dialect_loader)?;
+        let dropped_items = dialect.messages.reset(&mut buffer, items)?;
+        archive_messages(
+            &dialect,
+            dialect_loader.key(),
+            ctx.remaining_accounts,
+            dropped_items,
+        )?;
+        let max_message_length_limit = dialect.max_message_length_limit();
+        if dialect.max_message_length > max_message_length_limit {
+            dialect.max_message_length = max_message_length_limit;
+        }
+        // Emit an event for monitoring services.
+        emit!(DialectResizedEvent {
+            dialect: dialect_loader.key(),
+            capacity,
+        });
+        Ok(())
+    }
+
+    /// This function sets whether the MessageSentEvents of a dialect include the message's payload,
+    /// i.e. its serialized text, so that log subscribers can reconstruct the dialect's messages
+    /// without reading the dialect account. Only a member with admin privileges can set it.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * enabled: Whether the payloads are included in the events.
+    ///
+    /// See the SetEmitMessagePayloads context & MessageSentEvent structs below for more information.
+    pub fn set_emit_message_payloads(
+        ctx: Context<SetEmitMessagePayloads>,
+        enabled: bool,
+    ) -> Result<()> {
+        let mut dialect = ctx.accounts.dialect.load_mut()?;
+        dialect.emit_message_payloads = enabled;
+        Ok(())
+    }
+
+    /// This function enables or disables the archival of a dialect's messages. When enabled, the
+    /// messages evicted from the dialect's message buffer are archived in its message history
+    /// pages instead of being lost. Only a member with admin privileges can enable archival.
+    ///
+    /// Enabling archival lowers the dialect's max message length, if needed, so that messages fit
+    /// in a message history page.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * enabled: Whether archival is enabled.
+    ///
+    /// See the SetArchiveEnabled context & MessageHistoryPage structs below for more information.
+    pub fn set_archive_enabled(ctx: Context<SetArchiveEnabled>, enabled: bool) -> Result<()> {
+        let (mut dialect, _, _, buffer) = load_dialect_mut(&ctx.accounts.dialect)?;
+        dialect.archive_enabled = enabled;
+        let max_message_length_limit = dialect.max_message_length_limit();
+        // Messages sent before archival was enabled must fit in a message history page as well.
+        let too_large = |item: &Vec<u8>| {
+            ITEM_METADATA_OVERHEAD as usize + item.len() > MESSAGE_HISTORY_PAGE_LENGTH
+        };
+        if enabled && dialect.messages.items(&buffer).iter().any(too_large) {
+            return err!(ErrorCode::MessageTooLarge);
+        }
+        if dialect.max_message_length > max_message_length_limit {
+            dialect.max_message_length = max_message_length_limit;
+        }
+        Ok(())
+    }
+
+    /// This function creates the next message history page of a dialect, in which evicted messages
+    /// are archived once the previous page is full. Any member of the dialect can pay for it.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * page_index: The index of the page, which must be the dialect's history_pages_count. Used
+    ///   as a seed of the page account.
+    ///
+    /// See the CreateMessageHistoryPage context & MessageHistoryPage structs below for more information.
+    pub fn create_message_history_page(
+        ctx: Context<CreateMessageHistoryPage>,
+        page_index: u16,
+    ) -> Result<()> {
+        let dialect_loader = &ctx.accounts.dialect;
+        let mut dialect = dialect_loader.load_mut()?;
+        if page_index != dialect.history_pages_count {
+            return err!(ErrorCode::InvalidMessageHistoryPageIndex);
+        }
+        let mut history_page = ctx.accounts.history_page.load_init()?;
+        history_page.dialect = dialect_loader.key();
+        history_page.page_index = page_index;
+        history_page.items_count = 0;
+        history_page.length = 0;
+        dialect.history_pages_count += 1;
+        Ok(())
+    }
+
+    /// This function adds a member to a dialect. Only a member with admin privileges can add members.
+    ///
+    /// A removed member who is added again gets their previous member slot back. Otherwise, a new
+    /// slot is added if the dialect has fewer slots than its max number of members, growing the
+    /// dialect account, whose rent the signing admin tops up. Otherwise, the slot of a removed
+    /// member none of the stored messages are attributed to is taken.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * scopes: The scopes for the new member.
+    ///
+    /// See the AddMember context & DialectAccount structs below for more information.
+    pub fn add_member(ctx: Context<AddMember>, scopes: [bool; 2]) -> Result<()> {
+        let dialect_loader = &ctx.accounts.dialect;
+        let dialect_info = dialect_loader.to_account_info();
+        let original_len = dialect_info.data_len();
+        let member = &ctx.accounts.member;
+        let (slot_idx, members_count) = {
+            let (dialect, member_slots, pinned_message, buffer) = load_dialect_mut(dialect_loader)?;
+            let slot_idx = dialect.find_slot_for_new_member(
+                &member_slots,
+                &pinned_message,
+                &buffer,
+                member.key,
+            )?;
+            (slot_idx, dialect.members_count as usize)
+        };
+        if slot_idx == members_count {
+            top_up_rent(
+                &dialect_info,
+                &ctx.accounts.admin.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+                &ctx.accounts.rent,
+                original_len + MEMBER_SIZE,
+            )?;
+            resize_dialect_region(
+                &dialect_info,
+                original_len,
+                DIALECT_ACCOUNT_SIZE,
+                members_count * MEMBER_SIZE,
+                (members_count + 1) * MEMBER_SIZE,
+            )?;
+            dialect_loader.load_mut()?.members_count += 1;
+        }
+        let (dialect, mut member_slots, _, _) = load_dialect_mut(dialect_loader)?;
+        // New members have no unread messages, and a reused slot's read pointer belongs to its
+        // previous member.
+        let read_until = match member_slots[slot_idx].public_key == member.key() {
+            true => member_slots[slot_idx].read_until,
+            false => dialect.next_message_id,
+        };
+        member_slots[slot_idx] = Member {
+            public_key: member.key(),
+            scopes,
+            removed: false,
+            read_until,
+        };
+        // Emit an event for monitoring services.
+        emit!(MemberAddedEvent {
+            dialect: dialect_loader.key(),
+            member: member.key(),
+            scopes,
+        });
+        Ok(())
+    }
+
+    /// This function removes a member from a dialect. Only a member with admin privileges can
+    /// remove members, and the last admin cannot be removed.
+    ///
+    /// The removed member's slot is retained, with no scopes, so that the messages they sent are
+    /// still attributed to them.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    ///
+    /// See the RemoveMember context & DialectAccount structs below for more information.
+    pub fn remove_member(ctx: Context<RemoveMember>) -> Result<()> {
+        let dialect_loader = &ctx.accounts.dialect;
+        let (_, mut member_slots, _, _) = load_dialect_mut(dialect_loader)?;
+        let member = &ctx.accounts.member;
+        let slot_idx = find_member_slot(&member_slots, member.key)?;
+        if is_last_admin(&member_slots, slot_idx) {
+            return err!(ErrorCode::LastAdminCannotBeRemoved);
+        }
+        member_slots[slot_idx].scopes = [false, false];
+        member_slots[slot_idx].removed = true;
+        // Emit an event for monitoring services.
+        emit!(MemberRemovedEvent {
+            dialect: dialect_loader.key(),
+            member: member.key(),
+        });
+        Ok(())
+    }
+
+    /// This function updates the scopes of a dialect member. Only a member with admin privileges
+    /// can update scopes, and the last admin cannot be demoted.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * scopes: The new scopes for the member.
+    ///
+    /// See the UpdateMemberScopes context & Member structs below for more information.
+    pub fn update_member_scopes(ctx: Context<UpdateMemberScopes>, scopes: [bool; 2]) -> Result<()> {
+        let dialect_loader = &ctx.accounts.dialect;
+        let (_, mut member_slots, _, _) = load_dialect_mut(dialect_loader)?;
+        let member = &ctx.accounts.member;
+        let slot_idx = find_member_slot(&member_slots, member.key)?;
+        if !scopes[0] && is_last_admin(&member_slots, slot_idx) {
+            return err!(ErrorCode::LastAdminCannotBeRemoved);
+        }
+        member_slots[slot_idx].scopes = scopes;
+        // Emit an event for monitoring services.
+        emit!(MemberScopesUpdatedEvent {
+            dialect: dialect_loader.key(),
+            member: member.key(),
+            scopes,
         });
+        Ok(())
+    }
 
+    /// This function sets the maximum number of members of a dialect, i.e. of its member slots,
+    /// see add_member. Only a member with admin privileges can set it.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * max_members: The maximum number of members, at least 2 and the number of member slots
+    ///   in use, including the slots of removed members.
+    ///
+    /// See the SetMaxMembers context & DialectAccount structs below for more information.
+    pub fn set_max_members(ctx: Context<SetMaxMembers>, max_members: u8) -> Result<()> {
+        let mut dialect = ctx.accounts.dialect.load_mut()?;
+        if max_members < 2 || max_members < dialect.members_count {
+            return err!(ErrorCode::InvalidMaxMembers);
+        }
+        dialect.max_members = max_members;
         Ok(())
     }
 
     /// This function subscribes a user to a dialect by adding the dialect's public key to
     /// the subscriptions in the user's metadata account.
     ///
+    /// If the signer is not the user, and the user's subscription policy does not allow the signer
+    /// to subscribe them to the dialect directly, the dialect is added to the invites in the user's
+    /// metadata policy account instead, which the user can then accept or reject. Only members of
+    /// the dialect can invite users to it, and only users with a metadata policy account can be
+    /// invited. A direct subscription consumes the user's invite to the
+    /// dialect, if any.
+    ///
+    /// The user's existing metadata pages must be passed as the remaining accounts, in index order,
+    /// so that the user can't be subscribed to the dialect twice. Users without metadata pages
+    /// don't pass any.
+    ///
     /// ### Arguments
     ///
     /// * ctx: The context.
@@ -149,231 +735,1894 @@ pub mod dialect {
         ctx: Context<SubscribeUser>,
         _dialect_nonce: u8,
         _metadata_nonce: u8,
-    ) -> ProgramResult {
+    ) -> Result<()> {
         let dialect = &mut ctx.accounts.dialect;
-        let metadata_loader = &mut ctx.accounts.metadata;
-        let metadata = &mut metadata_loader.load_mut()?;
-        let num_subscriptions = metadata
-            .subscriptions
-            .iter()
-            .filter(|s| is_present(s))
-            .count();
-        if num_subscriptions < 32 {
-            metadata.subscriptions[num_subscriptions] = Subscription {
-                pubkey: dialect.key(),
-                enabled: true,
-            };
+        let signer = &ctx.accounts.signer;
+        let user = &ctx.accounts.user;
+        let metadata_policy = &ctx.accounts.metadata_policy;
+        check_not_subscribed_in_pages(
+            user.key,
+            &ctx.accounts.metadata.to_account_info(),
+            &dialect.key(),
+            ctx.remaining_accounts,
+        )?;
+        let members = dialect_members(dialect)?;
+        if !load_subscription_policy(metadata_policy)?
+            .allows_subscription_by(user.key, signer.key, &members)
+        {
+            if !members.iter().any(|m| m.public_key == signer.key()) {
+                return err!(ErrorCode::InviterNotMember);
+            }
+            // Users without a metadata policy account have nowhere to store invites.
+            if metadata_policy.data_is_empty() {
+                return err!(ErrorCode::SubscriptionNotAllowed);
+            }
+            let metadata_policy_loader =
+                AccountLoader::<MetadataPolicyAccount>::try_from(metadata_policy)?;
+            let mut metadata_policy_account = metadata_policy_loader.load_mut()?;
+            if metadata_policy_account.invites.contains(&dialect.key()) {
+                return err!(ErrorCode::AlreadyInvited);
+            }
+            add_invite(&mut metadata_policy_account.invites, dialect.key())?;
             // Emit an event for monitoring services.
-            emit!(UserSubscribedEvent {
-                metadata: metadata_loader.key(),
-                dialect: dialect.key()
+            emit!(UserInvitedEvent {
+                metadata_policy: metadata_policy.key(),
+                dialect: dialect.key(),
+                inviter: signer.key(),
             });
-        } else {
-            // Handle max subscriptions
-            msg!("User already subscribed to 32 dialects");
+            return Ok(());
         }
+        consume_invite(metadata_policy, &dialect.key())?;
+        let metadata_loader = &mut ctx.accounts.metadata;
+        let metadata = &mut metadata_loader.load_mut()?;
+        add_subscription(&mut metadata.subscriptions, dialect.key())?;
+        // Emit an event for monitoring services.
+        emit!(UserSubscribedEvent {
+            metadata: metadata_loader.key(),
+            dialect: dialect.key()
+        });
         Ok(())
     }
 
-    /// This function lets a member of a dialect with write privileges send a message in the dialect.
+    /// This function accepts an invite to a dialect by moving the dialect's public key from the
+    /// invites in the user's metadata policy account to the subscriptions in the user's metadata
+    /// account.
+    ///
+    /// The user's metadata pages must be passed as the remaining accounts, as in subscribe_user.
     ///
     /// ### Arguments
     ///
     /// * ctx: The context.
-    /// * _dialect_nonce: The seed associated with the dialect account.
-    /// * text: The message to send, encoded in u8 vec.
+    /// * _metadata_nonce: The seed associated with the metadata account.
     ///
-    /// See the SendMessage context & DialectAccount structs below for more information.
-    pub fn send_message(
-        ctx: Context<SendMessage>,
-        _dialect_nonce: u8,
-        text: Vec<u8>,
-    ) -> ProgramResult {
-        let dialect_loader = &ctx.accounts.dialect;
-        let mut dialect = dialect_loader.load_mut()?;
-        let sender = &mut ctx.accounts.sender.to_account_info();
-        dialect.append(text, sender);
-        // Emit an event for monitoring services.
+    /// See the AcceptInvite context, MetadataAccount & MetadataPolicyAccount structs below for more
+    /// information.
+    pub fn accept_invite(ctx: Context<AcceptInvite>, _metadata_nonce: u8) -> Result<()> {
+        let dialect = &ctx.accounts.dialect;
+        check_not_subscribed_in_pages(
+            ctx.accounts.user.key,
+            &ctx.accounts.metadata.to_account_info(),
+            &dialect.key(),
+            ctx.remaining_accounts,
+        )?;
+        let metadata_policy = &mut ctx.accounts.metadata_policy.load_mut()?;
+        remove_invite(&mut metadata_policy.invites, &dialect.key())?;
+        let metadata_loader = &ctx.accounts.metadata;
+        let metadata = &mut metadata_loader.load_mut()?;
+        add_subscription(&mut metadata.subscriptions, dialect.key())?;
+        // Emit an event for monitoring services.
+        emit!(UserSubscribedEvent {
+            metadata: metadata_loader.key(),
+            dialect: dialect.key()
+        });
+        Ok(())
+    }
+
+    /// This function rejects an invite to a dialect by removing the dialect's public key from the
+    /// invites in the user's metadata policy account.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    ///
+    /// See the RejectInvite context & MetadataPolicyAccount structs below for more information.
+    pub fn reject_invite(ctx: Context<RejectInvite>) -> Result<()> {
+        let dialect = &ctx.accounts.dialect;
+        let metadata_policy_loader = &ctx.accounts.metadata_policy;
+        let metadata_policy = &mut metadata_policy_loader.load_mut()?;
+        remove_invite(&mut metadata_policy.invites, &dialect.key())?;
+        // Emit an event for monitoring services.
+        emit!(InviteRejectedEvent {
+            metadata_policy: metadata_policy_loader.key(),
+            dialect: dialect.key()
+        });
+        Ok(())
+    }
+
+    /// This function unsubscribes a user from a dialect by removing the dialect's public key from
+    /// the subscriptions in the user's metadata account. The remaining subscriptions are compacted,
+    /// so that present subscriptions always occupy the first slots.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * _metadata_nonce: The seed associated with the metadata account.
+    ///
+    /// See the UnsubscribeUser context & MetadataAccount structs below for more information.
+    pub fn unsubscribe_user(ctx: Context<UnsubscribeUser>, _metadata_nonce: u8) -> Result<()> {
+        let dialect = &ctx.accounts.dialect;
+        let metadata_loader = &ctx.accounts.metadata;
+        let metadata = &mut metadata_loader.load_mut()?;
+        remove_subscription(&mut metadata.subscriptions, &dialect.key())?;
+        // Emit an event for monitoring services.
+        emit!(UserUnsubscribedEvent {
+            metadata: metadata_loader.key(),
+            dialect: dialect.key()
+        });
+        Ok(())
+    }
+
+    /// This function enables or disables a user's subscription to a dialect, e.g. to mute a dialect
+    /// without removing it from the subscriptions in the user's metadata account.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * _metadata_nonce: The seed associated with the metadata account.
+    /// * enabled: Whether the subscription should be enabled.
+    ///
+    /// See the SetSubscriptionEnabled context & MetadataAccount structs below for more information.
+    pub fn set_subscription_enabled(
+        ctx: Context<SetSubscriptionEnabled>,
+        _metadata_nonce: u8,
+        enabled: bool,
+    ) -> Result<()> {
+        let dialect = &ctx.accounts.dialect;
+        let metadata_loader = &ctx.accounts.metadata;
+        let metadata = &mut metadata_loader.load_mut()?;
+        find_subscription(&mut metadata.subscriptions, &dialect.key())?.enabled = enabled;
+        // Emit an event for monitoring services.
+        emit!(SubscriptionEnabledUpdatedEvent {
+            metadata: metadata_loader.key(),
+            dialect: dialect.key(),
+            enabled,
+        });
+        Ok(())
+    }
+
+    /// This function subscribes a user to a dialect by adding the dialect's public key to
+    /// the subscriptions in one of the user's metadata pages. Pages are only used once the
+    /// subscriptions in the user's metadata account are full.
+    ///
+    /// Pages don't hold invites, so the user's subscription policy must allow the signer to
+    /// subscribe them to the dialect directly. The subscription consumes the user's invite to the
+    /// dialect, if any.
+    ///
+    /// The user's metadata pages must be passed as the remaining accounts, as in subscribe_user.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * _metadata_nonce: The seed associated with the metadata account.
+    /// * _page_index: The index of the metadata page.
+    ///
+    /// See the SubscribeUserToPage context, DialectAccount & MetadataPage structs below for
+    /// more information.
+    pub fn subscribe_user_to_page(
+        ctx: Context<SubscribeUserToPage>,
+        _metadata_nonce: u8,
+        _page_index: u8,
+    ) -> Result<()> {
+        let dialect = &ctx.accounts.dialect;
+        check_not_subscribed_in_pages(
+            ctx.accounts.user.key,
+            &ctx.accounts.metadata.to_account_info(),
+            &dialect.key(),
+            ctx.remaining_accounts,
+        )?;
+        consume_invite(&ctx.accounts.metadata_policy, &dialect.key())?;
+        let metadata_page_loader = &ctx.accounts.metadata_page;
+        let metadata_page = &mut metadata_page_loader.load_mut()?;
+        add_subscription(&mut metadata_page.subscriptions, dialect.key())?;
+        // Emit an event for monitoring services.
+        emit!(UserSubscribedEvent {
+            metadata: metadata_page_loader.key(),
+            dialect: dialect.key()
+        });
+        Ok(())
+    }
+
+    /// This function unsubscribes a user from a dialect by removing the dialect's public key from
+    /// the subscriptions in one of the user's metadata pages.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * _page_index: The index of the metadata page.
+    ///
+    /// See the UnsubscribeUserFromPage context & MetadataPage structs below for more information.
+    pub fn unsubscribe_user_from_page(
+        ctx: Context<UnsubscribeUserFromPage>,
+        _page_index: u8,
+    ) -> Result<()> {
+        let dialect = &ctx.accounts.dialect;
+        let metadata_page_loader = &ctx.accounts.metadata_page;
+        let metadata_page = &mut metadata_page_loader.load_mut()?;
+        remove_subscription(&mut metadata_page.subscriptions, &dialect.key())?;
+        // Emit an event for monitoring services.
+        emit!(UserUnsubscribedEvent {
+            metadata: metadata_page_loader.key(),
+            dialect: dialect.key()
+        });
+        Ok(())
+    }
+
+    /// This function enables or disables a user's subscription to a dialect stored in one of the
+    /// user's metadata pages.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * _page_index: The index of the metadata page.
+    /// * enabled: Whether the subscription should be enabled.
+    ///
+    /// See the SetPageSubscriptionEnabled context & MetadataPage structs below for more information.
+    pub fn set_page_subscription_enabled(
+        ctx: Context<SetPageSubscriptionEnabled>,
+        _page_index: u8,
+        enabled: bool,
+    ) -> Result<()> {
+        let dialect = &ctx.accounts.dialect;
+        let metadata_page_loader = &ctx.accounts.metadata_page;
+        let metadata_page = &mut metadata_page_loader.load_mut()?;
+        find_subscription(&mut metadata_page.subscriptions, &dialect.key())?.enabled = enabled;
+        // Emit an event for monitoring services.
+        emit!(SubscriptionEnabledUpdatedEvent {
+            metadata: metadata_page_loader.key(),
+            dialect: dialect.key(),
+            enabled,
+        });
+        Ok(())
+    }
+
+    /// This function lets a member of a dialect with write privileges send a message in the dialect.
+    ///
+    /// If archival is enabled for the dialect, the messages evicted from its message buffer to make
+    /// room for the new message are archived in its last message history pages, which must then be
+    /// passed as the remaining accounts, see archive_messages.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * _dialect_nonce: The seed associated with the dialect account.
+    /// * text: The message to send, encoded in u8 vec.
+    /// * reply_to: The id of the message replied to, if the message is a reply. The message replied
+    ///   to may have been evicted from the message buffer, but must have been sent.
+    /// * content_type: The content type of the text, see ContentType.
+    /// * ttl: The time-to-live of the message in seconds, if it should expire, e.g. a one-time
+    ///   code. Must be positive. Expired messages can be erased by anyone, see prune_expired, and
+    ///   are never archived.
+    ///
+    /// See the SendMessage context & DialectAccount structs below for more information.
+    pub fn send_message(
+        ctx: Context<SendMessage>,
+        _dialect_nonce: u8,
+        text: Vec<u8>,
+        reply_to: Option<u64>,
+        content_type: ContentType,
+        ttl: Option<u32>,
+    ) -> Result<()> {
+        if ttl == Some(0) {
+            return err!(ErrorCode::InvalidTtl);
+        }
+        let dialect_loader = &ctx.accounts.dialect;
+        let (mut dialect, mut member_slots, _, mut buffer) = load_dialect_mut(dialect_loader)?;
+        let sender = &mut ctx.accounts.sender.to_account_info();
+        let sender_member_idx = find_sender_slot(&member_slots, sender.key)?;
+        let message_id = dialect.next_message_id;
+        let length = text.len() as u16;
+        let payload_hash = hashv(&[&text]).to_bytes();
+        let payload = match dialect.emit_message_payloads {
+            true => Some(text.clone()),
+            false => None,
+        };
+        if let Some(reply_to) = reply_to {
+            if reply_to >= message_id {
+                return err!(ErrorCode::MessageNotFound);
+            }
+        }
+        let reference = reply_to.map(|reply_to| (MESSAGE_FLAG_REPLY, reply_to));
+        let evicted_items = dialect.append(
+            &mut buffer,
+            text,
+            content_type,
+            sender_member_idx,
+            reference,
+            ttl,
+        )?;
+        // Senders have read their own messages.
+        member_slots[sender_member_idx as usize].read_until = dialect.next_message_id;
+        archive_messages(
+            &dialect,
+            dialect_loader.key(),
+            ctx.remaining_accounts,
+            evicted_items,
+        )?;
+        // Emit an event for monitoring services.
         emit!(MessageSentEvent {
             dialect: dialect_loader.key(),
             sender: *sender.key,
+            id: message_id,
+            timestamp: dialect.last_message_timestamp,
+            length,
+            payload_hash,
+            payload,
+            reply_to,
+            content_type: content_type as u8,
+            ttl,
+        });
+        Ok(())
+    }
+
+    /// This function lets the sender of a message edit it, within the dialect's edit window after
+    /// the message was sent. The edit is appended as a new message, flagged as an edit and
+    /// referencing the id of the edited message, which readers fold into the edited message. If
+    /// the message is pinned, its pinned copy is updated, so the edit must fit in the pinned region.
+    /// If the message expires, the edit expires along with it.
+    ///
+    /// As for send_message, if archival is enabled for the dialect, its last message history pages
+    /// must be passed as the remaining accounts.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * message_id: The id of the message to edit, which must still be in the message buffer.
+    /// * text: The replacement text, encoded in u8 vec.
+    /// * content_type: The content type of the replacement text, see ContentType.
+    ///
+    /// See the EditMessage context & DialectAccount structs below for more information.
+    pub fn edit_message(
+        ctx: Context<EditMessage>,
+        message_id: u64,
+        text: Vec<u8>,
+        content_type: ContentType,
+    ) -> Result<()> {
+        let dialect_loader = &ctx.accounts.dialect;
+        let dialect_info = dialect_loader.to_account_info();
+        let original_len = dialect_info.data_len();
+        let (mut dialect, mut member_slots, pinned_message, mut buffer) =
+            load_dialect_mut(dialect_loader)?;
+        let sender = &mut ctx.accounts.sender.to_account_info();
+        let (_, message) = dialect.find_message(&buffer, message_id)?;
+        if message[2] & MESSAGE_FLAG_EDIT != 0 {
+            return err!(ErrorCode::MessageNotEditable);
+        }
+        if member_slots[message[1] as usize].public_key != *sender.key {
+            return err!(ErrorCode::NotMessageSender);
+        }
+        let now = match Clock::get() {
+            Ok(clock) => clock.unix_timestamp as u32,
+            Err(_) => return err!(ErrorCode::ClockUnavailable),
+        };
+        let sent_at = u32::from_be_bytes([message[11], message[12], message[13], message[14]]);
+        if now.saturating_sub(sent_at) > dialect.edit_window {
+            return err!(ErrorCode::EditWindowExpired);
+        }
+        let ttl = match message_ttl(&message) {
+            Some(ttl) => match (sent_at as u64 + ttl as u64).checked_sub(now as u64) {
+                Some(remaining_ttl) if remaining_ttl > 0 => Some(remaining_ttl as u32),
+                _ => return err!(ErrorCode::MessageExpired),
+            },
+            None => None,
+        };
+        // The pinned copy of the message, if any, is kept up to date with its latest text.
+        let new_pinned_message = match pinned_message_id(&pinned_message) == Some(message_id) {
+            true => {
+                let mut new_pinned_message = message[..envelope_offset(&message)].to_vec();
+                new_pinned_message.extend([MESSAGE_ENVELOPE_VERSION, content_type as u8]);
+                new_pinned_message.extend(&text);
+                Some(new_pinned_message)
+            }
+            false => None,
+        };
+        let old_pinned_message_length = pinned_message.len();
+        let edit_id = dialect.next_message_id;
+        let sender_member_idx = message[1];
+        let evicted_items = dialect.append(
+            &mut buffer,
+            text,
+            content_type,
+            sender_member_idx,
+            Some((MESSAGE_FLAG_EDIT, message_id)),
+            ttl,
+        )?;
+        // Senders have read their own edits.
+        member_slots[sender_member_idx as usize].read_until = dialect.next_message_id;
+        archive_messages(
+            &dialect,
+            dialect_loader.key(),
+            ctx.remaining_accounts,
+            evicted_items,
+        )?;
+        drop((dialect, member_slots, pinned_message, buffer));
+        if let Some(new_pinned_message) = new_pinned_message {
+            top_up_rent(
+                &dialect_info,
+                sender,
+                &ctx.accounts.system_program.to_account_info(),
+                &ctx.accounts.rent,
+                original_len - old_pinned_message_length + new_pinned_message.len(),
+            )?;
+            set_pinned_message(dialect_loader, original_len, &new_pinned_message)?;
+        }
+        // Emit an event for monitoring services.
+        emit!(MessageEditedEvent {
+            dialect: dialect_loader.key(),
+            sender: *sender.key,
+            id: edit_id,
+            edited_message_id: message_id,
+        });
+        Ok(())
+    }
+
+    /// This function lets a member of a dialect with write privileges react to a message, e.g. with
+    /// an emoji. Reactions are stored as compact items in the message buffer.
+    ///
+    /// As for send_message, if archival is enabled for the dialect, its last message history pages
+    /// must be passed as the remaining accounts.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * message_id: The id of the message reacted to, which must have been sent.
+    /// * reaction: The reaction code, e.g. an emoji's Unicode code point.
+    ///
+    /// See the React context & DialectAccount structs below for more information.
+    pub fn react(ctx: Context<React>, message_id: u64, reaction: u32) -> Result<()> {
+        let dialect_loader = &ctx.accounts.dialect;
+        let (mut dialect, member_slots, _, mut buffer) = load_dialect_mut(dialect_loader)?;
+        let sender = &mut ctx.accounts.sender.to_account_info();
+        let sender_member_idx = find_sender_slot(&member_slots, sender.key)?;
+        let evicted_items = dialect.react(&mut buffer, message_id, reaction, sender_member_idx)?;
+        archive_messages(
+            &dialect,
+            dialect_loader.key(),
+            ctx.remaining_accounts,
+            evicted_items,
+        )?;
+        // Emit an event for monitoring services.
+        emit!(MessageReactedEvent {
+            dialect: dialect_loader.key(),
+            sender: *sender.key,
+            message_id,
+            reaction,
+        });
+        Ok(())
+    }
+
+    /// This function lets a member of a dialect mark the messages up to a message as read, so that
+    /// all clients agree on the member's unread messages. The member's read pointer never moves
+    /// backwards.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * message_id: The id of the last message read, which must have been sent.
+    ///
+    /// See the MarkRead context & DialectAccount structs below for more information.
+    pub fn mark_read(ctx: Context<MarkRead>, message_id: u64) -> Result<()> {
+        let dialect_loader = &ctx.accounts.dialect;
+        let (dialect, mut member_slots, _, _) = load_dialect_mut(dialect_loader)?;
+        let member = &ctx.accounts.member;
+        if message_id >= dialect.next_message_id {
+            return err!(ErrorCode::MessageNotFound);
+        }
+        let slot_idx = find_member_slot(&member_slots, member.key)?;
+        if member_slots[slot_idx].read_until <= message_id {
+            member_slots[slot_idx].read_until = message_id + 1;
+        }
+        // Emit an event for monitoring services.
+        emit!(MessagesReadEvent {
+            dialect: dialect_loader.key(),
+            member: member.key(),
+            read_until: member_slots[slot_idx].read_until,
+        });
+        Ok(())
+    }
+
+    /// This function deletes a message from a dialect's message buffer. The message is replaced
+    /// in place by a tombstone, i.e. its text is zeroed and its item type is changed, so that the
+    /// other messages are not moved. The message's edits are replaced by tombstones too, so that
+    /// its text can't be recovered from them. Only the message's sender or a member with admin
+    /// privileges can delete it. Deleting the pinned message unpins it.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * message_id: The id of the message to delete.
+    ///
+    /// See the DeleteMessage context & DialectAccount structs below for more information.
+    pub fn delete_message(ctx: Context<DeleteMessage>, message_id: u64) -> Result<()> {
+        let dialect_loader = &ctx.accounts.dialect;
+        let original_len = dialect_loader.to_account_info().data_len();
+        let (dialect, member_slots, pinned_message, mut buffer) = load_dialect_mut(dialect_loader)?;
+        let signer = ctx.accounts.signer.key();
+        let (offset, message) = dialect.find_message(&buffer, message_id)?;
+        let message_sender = member_slots[message[1] as usize].public_key;
+        let is_admin =
+            current_members(&member_slots).any(|m| m.public_key == signer && m.is_admin());
+        if message_sender != signer && !is_admin {
+            return err!(ErrorCode::NotMessageSender);
+        }
+        let edits = dialect
+            .messages
+            .items_with_offsets(&buffer)
+            .into_iter()
+            .filter(|(_, item)| {
+                item[0] == ITEM_TYPE_TEXT
+                    && item[2] & MESSAGE_FLAG_EDIT != 0
+                    && item[MESSAGE_HEADER_LENGTH as usize..][..8] == message_id.to_be_bytes()
+            });
+        for (offset, mut item) in std::iter::once((offset, message)).chain(edits) {
+            tombstone(&mut item);
+            dialect.messages.write(&mut buffer, &mut item, offset);
+        }
+        let is_pinned = pinned_message_id(&pinned_message) == Some(message_id);
+        drop((dialect, member_slots, pinned_message, buffer));
+        if is_pinned {
+            set_pinned_message(dialect_loader, original_len, &[])?;
+        }
+        // Emit an event for monitoring services.
+        emit!(MessageDeletedEvent {
+            dialect: dialect_loader.key(),
+            id: message_id,
+            deleted_by: signer,
+        });
+        Ok(())
+    }
+
+    /// This function pins a message in a dialect, e.g. the dialect's rules or an announcement, by
+    /// copying it into the dialect's pinned region, outside the message buffer, so that it is never
+    /// evicted. The copy holds the message's latest text, and replaces the previously pinned
+    /// message, if any. Expiring messages cannot be pinned. Only a member with admin privileges
+    /// can pin a message.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * message_id: The id of the message to pin, which must still be in the message buffer.
+    ///
+    /// See the PinMessage context & DialectAccount structs below for more information.
+    pub fn pin_message(ctx: Context<PinMessage>, message_id: u64) -> Result<()> {
+        let dialect_loader = &ctx.accounts.dialect;
+        let dialect_info = dialect_loader.to_account_info();
+        let original_len = dialect_info.data_len();
+        let (dialect, _, pinned_message, buffer) = load_dialect_mut(dialect_loader)?;
+        let (_, mut message) = dialect.find_message(&buffer, message_id)?;
+        if message[2] & (MESSAGE_FLAG_EDIT | MESSAGE_FLAG_TTL) != 0 {
+            return err!(ErrorCode::MessageNotPinnable);
+        }
+        // Fold the latest edit of the message, if any, into the pinned copy.
+        let items = dialect.messages.items(&buffer);
+        let latest_edit = items.iter().rev().find(|item| {
+            item[0] == ITEM_TYPE_TEXT
+                && item[2] & MESSAGE_FLAG_EDIT != 0
+                && item[MESSAGE_HEADER_LENGTH as usize..][..8] == message_id.to_be_bytes()
+        });
+        if let Some(edit) = latest_edit {
+            message.truncate(envelope_offset(&message));
+            message.extend(&edit[envelope_offset(edit)..]);
+        }
+        let old_pinned_message_length = pinned_message.len();
+        drop((dialect, pinned_message, buffer));
+        top_up_rent(
+            &dialect_info,
+            &ctx.accounts.admin.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.rent,
+            original_len - old_pinned_message_length + message.len(),
+        )?;
+        set_pinned_message(dialect_loader, original_len, &message)?;
+        // Emit an event for monitoring services.
+        emit!(MessagePinnedEvent {
+            dialect: dialect_loader.key(),
+            message_id,
+            pinned_by: ctx.accounts.admin.key(),
+        });
+        Ok(())
+    }
+
+    /// This function unpins a dialect's pinned message. The message itself is left as is in the
+    /// message buffer, if it was not evicted yet. Only a member with admin privileges can unpin it.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    ///
+    /// See the UnpinMessage context & DialectAccount structs below for more information.
+    pub fn unpin_message(ctx: Context<UnpinMessage>) -> Result<()> {
+        let dialect_loader = &ctx.accounts.dialect;
+        let original_len = dialect_loader.to_account_info().data_len();
+        let message_id = {
+            let (_, _, pinned_message, _) = load_dialect_mut(dialect_loader)?;
+            match pinned_message_id(&pinned_message) {
+                Some(message_id) => message_id,
+                None => return err!(ErrorCode::NoPinnedMessage),
+            }
+        };
+        set_pinned_message(dialect_loader, original_len, &[])?;
+        // Emit an event for monitoring services.
+        emit!(MessageUnpinnedEvent {
+            dialect: dialect_loader.key(),
+            message_id,
+            unpinned_by: ctx.accounts.admin.key(),
+        });
+        Ok(())
+    }
+
+    /// This function erases the expired messages from the front of a dialect's message buffer, to
+    /// reclaim their space, along with the reactions to them. Pruning stops at the first item that
+    /// has not expired, so expired messages sent after it are erased later. Anyone can prune
+    /// expired messages.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    ///
+    /// See the PruneExpired context & DialectAccount structs below for more information.
+    pub fn prune_expired(ctx: Context<PruneExpired>) -> Result<()> {
+        let dialect_loader = &ctx.accounts.dialect;
+        let (mut dialect, _, _, mut buffer) = load_dialect_mut(dialect_loader)?;
+        let now = match Clock::get() {
+            Ok(clock) => clock.unix_timestamp as u32,
+            Err(_) => return err!(ErrorCode::ClockUnavailable),
+        };
+        let pruned_items_count = dialect.prune_expired(&mut buffer, now);
+        // Emit an event for monitoring services.
+        emit!(MessagesPrunedEvent {
+            dialect: dialect_loader.key(),
+            pruned_items_count,
+        });
+        Ok(())
+    }
+
+    // Broadcast channels
+
+    /// This function creates a broadcast channel, in which the signing publisher, e.g. a dapp,
+    /// broadcasts messages to any number of users, who subscribe to it themselves. A publisher has
+    /// a single broadcast channel.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * _channel_nonce: The seed associated with the broadcast channel account.
+    /// * capacity: The capacity of the channel's message buffer, between MIN_MESSAGE_BUFFER_LENGTH
+    ///   and MAX_INITIAL_BROADCAST_BUFFER_LENGTH bytes.
+    ///
+    /// See the CreateBroadcastChannel context & BroadcastChannel structs below for more information.
+    pub fn create_broadcast_channel(
+        ctx: Context<CreateBroadcastChannel>,
+        _channel_nonce: u8,
+        capacity: u16,
+    ) -> Result<()> {
+        if !(MIN_MESSAGE_BUFFER_LENGTH..=MAX_INITIAL_BROADCAST_BUFFER_LENGTH).contains(&capacity) {
+            return err!(ErrorCode::InvalidMessageBufferCapacity);
+        }
+        let channel_loader = &ctx.accounts.channel;
+        let mut channel = channel_loader.load_init()?;
+        let publisher = &ctx.accounts.publisher;
+        channel.publisher = publisher.key();
+        channel.messages.read_offset = 0;
+        channel.messages.write_offset = 0;
+        channel.messages.items_count = 0;
+        channel.messages.capacity = capacity;
+        channel.last_message_timestamp = Clock::get()?.unix_timestamp as u32;
+        channel.next_message_id = 0;
+        // Emit an event for monitoring services.
+        emit!(BroadcastChannelCreatedEvent {
+            channel: channel_loader.key(),
+            publisher: publisher.key(),
+        });
+        Ok(())
+    }
+
+    /// This function closes a broadcast channel and refunds its rent to its publisher. Users
+    /// subscribed to the channel can still unsubscribe from it, see unsubscribe_user.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * _channel_nonce: The seed associated with the broadcast channel account.
+    ///
+    /// See the CloseBroadcastChannel context & BroadcastChannel structs below for more information.
+    pub fn close_broadcast_channel(
+        ctx: Context<CloseBroadcastChannel>,
+        _channel_nonce: u8,
+    ) -> Result<()> {
+        // Emit an event for monitoring services.
+        emit!(BroadcastChannelDeletedEvent {
+            channel: ctx.accounts.channel.key(),
+            publisher: ctx.accounts.publisher.key(),
+        });
+        Ok(())
+    }
+
+    /// This function lets the publisher of a broadcast channel broadcast a message to the channel's
+    /// subscribers. Broadcast messages are public, so their payload is always emitted.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * _channel_nonce: The seed associated with the broadcast channel account.
+    /// * text: The message to broadcast, encoded in u8 vec.
+    /// * content_type: The content type of the text, see ContentType.
+    /// * topic: The id of the message's topic, lower than MAX_TOPICS, if any. Messages with a topic
+    ///   are only delivered to the subscribers to the topic, see SubscriptionTopics.topics.
+    ///
+    /// See the BroadcastMessage context & BroadcastChannel structs below for more information.
+    pub fn broadcast_message(
+        ctx: Context<BroadcastMessage>,
+        _channel_nonce: u8,
+        text: Vec<u8>,
+        content_type: ContentType,
+        topic: Option<u8>,
+    ) -> Result<()> {
+        let channel_loader = &ctx.accounts.channel;
+        let (mut channel, mut buffer) = load_broadcast_channel_mut(channel_loader)?;
+        let message_id = channel.next_message_id;
+        let length = text.len() as u16;
+        let payload_hash = hashv(&[&text]).to_bytes();
+        let payload = text.clone();
+        channel.append(&mut buffer, text, content_type, topic)?;
+        // Emit an event for monitoring services.
+        emit!(BroadcastMessageSentEvent {
+            channel: channel_loader.key(),
+            publisher: ctx.accounts.publisher.key(),
+            id: message_id,
+            timestamp: channel.last_message_timestamp,
+            length,
+            payload_hash,
+            payload,
+            content_type: content_type as u8,
+            topic,
         });
         Ok(())
     }
+
+    /// This function subscribes the signing user to a broadcast channel by adding the channel's
+    /// address to the subscriptions in the user's metadata account, alongside dialects. Only users
+    /// can subscribe themselves to broadcast channels, and they unsubscribe from them as from
+    /// dialects, see unsubscribe_user.
+    ///
+    /// The user's metadata pages must be passed as the remaining accounts, as in subscribe_user.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * _metadata_nonce: The seed associated with the metadata account.
+    ///
+    /// See the SubscribeToBroadcastChannel context, BroadcastChannel & MetadataAccount structs
+    /// below for more information.
+    pub fn subscribe_to_broadcast_channel(
+        ctx: Context<SubscribeToBroadcastChannel>,
+        _metadata_nonce: u8,
+    ) -> Result<()> {
+        let channel = &ctx.accounts.channel;
+        let metadata_loader = &ctx.accounts.metadata;
+        check_not_subscribed_in_pages(
+            ctx.accounts.user.key,
+            &metadata_loader.to_account_info(),
+            &channel.key(),
+            ctx.remaining_accounts,
+        )?;
+        let metadata = &mut metadata_loader.load_mut()?;
+        add_subscription(&mut metadata.subscriptions, channel.key())?;
+        // Emit an event for monitoring services.
+        emit!(UserSubscribedEvent {
+            metadata: metadata_loader.key(),
+            dialect: channel.key()
+        });
+        Ok(())
+    }
+
+    /// This function creates the account holding the topics of the signing user's subscription to
+    /// a broadcast channel, e.g. to receive liquidation warnings but not marketing messages. Relays
+    /// & on-chain readers filter the channel's messages for the user accordingly, see
+    /// SubscriptionTopics.includes_topic. Subscriptions without a SubscriptionTopics account
+    /// include all topics.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * topics: The topics subscribed to, as a bitmask, see SubscriptionTopics.topics.
+    ///
+    /// See the CreateSubscriptionTopics context & SubscriptionTopics structs below for more
+    /// information.
+    pub fn create_subscription_topics(
+        ctx: Context<CreateSubscriptionTopics>,
+        topics: u64,
+    ) -> Result<()> {
+        let channel = &ctx.accounts.channel;
+        let subscription_topics_loader = &ctx.accounts.subscription_topics;
+        let subscription_topics = &mut subscription_topics_loader.load_init()?;
+        subscription_topics.user = ctx.accounts.user.key();
+        subscription_topics.channel = channel.key();
+        subscription_topics.topics = topics;
+        // Emit an event for monitoring services.
+        emit!(SubscriptionTopicsUpdatedEvent {
+            subscription_topics: subscription_topics_loader.key(),
+            channel: channel.key(),
+            topics,
+        });
+        Ok(())
+    }
+
+    /// This function sets the topics of the signing user's subscription to a broadcast channel.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    /// * topics: The topics subscribed to, as a bitmask, see SubscriptionTopics.topics.
+    ///
+    /// See the SetSubscriptionTopics context & SubscriptionTopics structs below for more
+    /// information.
+    pub fn set_subscription_topics(ctx: Context<SetSubscriptionTopics>, topics: u64) -> Result<()> {
+        let channel = &ctx.accounts.channel;
+        let subscription_topics_loader = &ctx.accounts.subscription_topics;
+        let subscription_topics = &mut subscription_topics_loader.load_mut()?;
+        subscription_topics.topics = topics;
+        // Emit an event for monitoring services.
+        emit!(SubscriptionTopicsUpdatedEvent {
+            subscription_topics: subscription_topics_loader.key(),
+            channel: channel.key(),
+            topics,
+        });
+        Ok(())
+    }
+
+    /// This function closes the account holding the topics of the signing user's subscription to a
+    /// broadcast channel and recovers its rent, so that the subscription includes all topics again.
+    ///
+    /// ### Arguments
+    ///
+    /// * ctx: The context.
+    ///
+    /// See the CloseSubscriptionTopics context & SubscriptionTopics structs below for more
+    /// information.
+    pub fn close_subscription_topics(_ctx: Context<CloseSubscriptionTopics>) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Contexts
+
+/// Context to create a metadata account for a user, created by the user.
+#[derive(Accounts)]
+pub struct CreateMetadata<'info> {
+    // The metadata owner and the signer for this transaction.
+    #[account(mut)]
+    pub user: Signer<'info>,
+    // The metadata account being created
+    #[account(
+        init,
+        seeds = [
+            b"metadata".as_ref(),
+            user.key.as_ref(),
+        ],
+        bump,
+        payer = user,
+        // discriminator (8) + user + 32 x (subscription) = 1096
+        space = 8 + 32 + (32 * 33),
+    )]
+    pub metadata: AccountLoader<'info, MetadataAccount>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context to close a metadata account and recover its rent. This action is permanent, and all data is lost.
+///
+/// Only the owner of a metadata account can close it.
+#[derive(Accounts)]
+#[instruction(metadata_nonce: u8)]
+pub struct CloseMetadata<'info> {
+    // The metadata owner and the signer for this transaction.
+    #[account(mut)]
+    pub user: Signer<'info>,
+    // The metadata account being closed.
+    #[account(
+        mut,
+        close = user,
+        // The user closing the metadata account must be its owner.
+        seeds = [
+            b"metadata".as_ref(),
+            user.key.as_ref(),
+        ],
+        has_one = user, // TODO: Does seeds address this, is this redundant?
+        bump = metadata_nonce,
+    )]
+    pub metadata: AccountLoader<'info, MetadataAccount>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context to create a metadata policy account for a user, created by the user.
+#[derive(Accounts)]
+pub struct CreateMetadataPolicy<'info> {
+    // The metadata policy owner and the signer for this transaction.
+    #[account(mut)]
+    pub user: Signer<'info>,
+    // The metadata policy account being created
+    #[account(
+        init,
+        seeds = [
+            b"metadata_policy".as_ref(),
+            user.key.as_ref(),
+        ],
+        bump,
+        payer = user,
+        // discriminator (8) + user + subscription policy + 16 x (invite) = 553
+        space = 8 + 32 + 1 + (16 * 32),
+    )]
+    pub metadata_policy: AccountLoader<'info, MetadataPolicyAccount>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context to close a metadata policy account and recover its rent. This action is permanent, and
+/// all data is lost.
+///
+/// Only the owner of a metadata policy account can close it.
+#[derive(Accounts)]
+pub struct CloseMetadataPolicy<'info> {
+    // The metadata policy owner and the signer for this transaction.
+    #[account(mut)]
+    pub user: Signer<'info>,
+    // The metadata policy account being closed.
+    #[account(
+        mut,
+        close = user,
+        seeds = [
+            b"metadata_policy".as_ref(),
+            user.key.as_ref(),
+        ],
+        has_one = user,
+        bump,
+    )]
+    pub metadata_policy: AccountLoader<'info, MetadataPolicyAccount>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context to set the subscription policy in a user's metadata policy account.
+///
+/// Only the owner of a metadata policy account can set its subscription policy.
+#[derive(Accounts)]
+pub struct SetSubscriptionPolicy<'info> {
+    // The metadata policy owner and the signer for this transaction.
+    pub user: Signer<'info>,
+    // The metadata policy account whose policy is being set.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata_policy".as_ref(),
+            user.key.as_ref(),
+        ],
+        has_one = user,
+        bump,
+    )]
+    pub metadata_policy: AccountLoader<'info, MetadataPolicyAccount>,
+}
+
+/// Context to create a metadata page for a user, created by the user.
+#[derive(Accounts)]
+#[instruction(page_index: u8)]
+pub struct CreateMetadataPage<'info> {
+    // The metadata page owner and the signer for this transaction.
+    #[account(mut)]
+    pub user: Signer<'info>,
+    // The metadata account belonging to the user, in which the page is recorded.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata".as_ref(),
+            user.key.as_ref(),
+        ],
+        has_one = user,
+        bump,
+    )]
+    pub metadata: AccountLoader<'info, MetadataAccount>,
+    // The metadata page being created
+    #[account(
+        init,
+        seeds = [
+            b"metadata".as_ref(),
+            user.key.as_ref(),
+            &[page_index],
+        ],
+        bump,
+        payer = user,
+        // discriminator (8) + user + page_index + 32 x (subscription) = 1097
+        space = 8 + 32 + 1 + (32 * 33),
+    )]
+    pub metadata_page: AccountLoader<'info, MetadataPage>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context to close a metadata page and recover its rent. This action is permanent, and all data is lost.
+///
+/// Only the owner of a metadata page can close it.
+#[derive(Accounts)]
+#[instruction(page_index: u8)]
+pub struct CloseMetadataPage<'info> {
+    // The metadata page owner and the signer for this transaction.
+    #[account(mut)]
+    pub user: Signer<'info>,
+    // The metadata account belonging to the user, in which the page is recorded.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata".as_ref(),
+            user.key.as_ref(),
+        ],
+        has_one = user,
+        bump,
+    )]
+    pub metadata: AccountLoader<'info, MetadataAccount>,
+    // The metadata page being closed.
+    #[account(
+        mut,
+        close = user,
+        seeds = [
+            b"metadata".as_ref(),
+            user.key.as_ref(),
+            &[page_index],
+        ],
+        has_one = user,
+        bump,
+    )]
+    pub metadata_page: AccountLoader<'info, MetadataPage>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for subscribing a user to a dialect, which adds the dialect's address to the user's
+/// metadata's list of subscriptions.
+#[derive(Accounts)]
+#[instruction(dialect_nonce: u8, metadata_nonce: u8)] // metadata0_nonce: u8, metadata1_nonce: u8)]
+pub struct SubscribeUser<'info> {
+    // The signing key, a.k.a. the user taking action to subscribe the other user to a new dialect.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    /// CHECK: N.b. we do not enforce that user is the signer. Meaning, users can subscribe other users
+    // to dialects.
+    pub user: AccountInfo<'info>,
+    // The metadata account belonging to the user, & to whose subscriptions the dialect will be added.
+    #[account(
+        mut,
+        // Enforce the same constraint on the metadata account to ensure it belongs to the user.
+        seeds = [
+            b"metadata".as_ref(),
+            user.key().as_ref(),
+        ],
+        bump = metadata_nonce,
+        // Enforce no duplicate subscriptions.
+        constraint = metadata
+            .load()?
+            .subscriptions
+            .iter()
+            .filter(|s| s.pubkey == dialect.key())
+            .count() < 1,
+    )]
+    pub metadata: AccountLoader<'info, MetadataAccount>,
+    /// CHECK: The user's metadata policy account, which doesn't exist for users who never set their
+    // policy. Its address is enforced by the seeds, and its owner when it is loaded.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata_policy".as_ref(),
+            user.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub metadata_policy: AccountInfo<'info>,
+    pub dialect: AccountLoader<'info, DialectAccount>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for accepting an invite to a dialect, which moves the dialect's address from the user's
+/// metadata policy's list of invites to the user's metadata's list of subscriptions.
+///
+/// Only the owner of a metadata account can accept invites.
+#[derive(Accounts)]
+#[instruction(metadata_nonce: u8)]
+pub struct AcceptInvite<'info> {
+    // The metadata owner and the signer for this transaction.
+    pub user: Signer<'info>,
+    // The metadata account belonging to the user, & to whose subscriptions the dialect will be added.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata".as_ref(),
+            user.key.as_ref(),
+        ],
+        has_one = user,
+        bump = metadata_nonce,
+    )]
+    pub metadata: AccountLoader<'info, MetadataAccount>,
+    // The metadata policy account belonging to the user, & in which the invite is stored.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata_policy".as_ref(),
+            user.key.as_ref(),
+        ],
+        has_one = user,
+        bump,
+    )]
+    pub metadata_policy: AccountLoader<'info, MetadataPolicyAccount>,
+    pub dialect: AccountLoader<'info, DialectAccount>,
+}
+
+/// Context for rejecting an invite to a dialect, which removes the dialect's address from the
+/// user's metadata policy's list of invites.
+///
+/// Only the owner of a metadata policy account can reject invites.
+#[derive(Accounts)]
+pub struct RejectInvite<'info> {
+    // The metadata policy owner and the signer for this transaction.
+    pub user: Signer<'info>,
+    // The metadata policy account belonging to the user, & in which the invite is stored.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata_policy".as_ref(),
+            user.key.as_ref(),
+        ],
+        has_one = user,
+        bump,
+    )]
+    pub metadata_policy: AccountLoader<'info, MetadataPolicyAccount>,
+    /// CHECK: Only the dialect's address is used, so that users can also reject invites to
+    // dialects which have already been closed.
+    pub dialect: AccountInfo<'info>,
+}
+
+/// Context for unsubscribing a user from a dialect, which removes the dialect's address from the
+/// user's metadata's list of subscriptions.
+///
+/// Only the owner of a metadata account can unsubscribe from a dialect.
+#[derive(Accounts)]
+#[instruction(metadata_nonce: u8)]
+pub struct UnsubscribeUser<'info> {
+    // The metadata owner and the signer for this transaction.
+    pub user: Signer<'info>,
+    // The metadata account belonging to the user, & from whose subscriptions the dialect will be removed.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata".as_ref(),
+            user.key.as_ref(),
+        ],
+        has_one = user,
+        bump = metadata_nonce,
+    )]
+    pub metadata: AccountLoader<'info, MetadataAccount>,
+    /// CHECK: Only the dialect's address is used, so that users can also unsubscribe from
+    // dialects which have already been closed.
+    pub dialect: AccountInfo<'info>,
+}
+
+/// Context for enabling or disabling a user's subscription to a dialect.
+///
+/// Only the owner of a metadata account can update its subscriptions.
+#[derive(Accounts)]
+#[instruction(metadata_nonce: u8)]
+pub struct SetSubscriptionEnabled<'info> {
+    // The metadata owner and the signer for this transaction.
+    pub user: Signer<'info>,
+    // The metadata account belonging to the user, & in which the subscription is stored.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata".as_ref(),
+            user.key.as_ref(),
+        ],
+        has_one = user,
+        bump = metadata_nonce,
+    )]
+    pub metadata: AccountLoader<'info, MetadataAccount>,
+    /// CHECK: Only the dialect's address is used to find the subscription.
+    pub dialect: AccountInfo<'info>,
+}
+
+/// Context for subscribing a user to a dialect, which adds the dialect's address to the list of
+/// subscriptions in one of the user's metadata pages.
+#[derive(Accounts)]
+#[instruction(metadata_nonce: u8, page_index: u8)]
+pub struct SubscribeUserToPage<'info> {
+    // The signing key, a.k.a. the user taking action to subscribe the other user to a new dialect.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    /// CHECK: N.b. we do not enforce that user is the signer. Meaning, users can subscribe other users
+    // to dialects.
+    pub user: AccountInfo<'info>,
+    // The metadata account belonging to the user.
+    #[account(
+        seeds = [
+            b"metadata".as_ref(),
+            user.key().as_ref(),
+        ],
+        bump = metadata_nonce,
+        // Pages are only used once the metadata account's subscriptions are full.
+        constraint = metadata.load()?.subscriptions.iter().all(is_present),
+        // Enforce no duplicate subscriptions.
+        constraint = metadata
+            .load()?
+            .subscriptions
+            .iter()
+            .filter(|s| s.pubkey == dialect.key())
+            .count() < 1
+    )]
+    pub metadata: AccountLoader<'info, MetadataAccount>,
+    // The metadata page belonging to the user, & to whose subscriptions the dialect will be added.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata".as_ref(),
+            user.key().as_ref(),
+            &[page_index],
+        ],
+        bump,
+        // Enforce no duplicate subscriptions within the page.
+        constraint = metadata_page
+            .load()?
+            .subscriptions
+            .iter()
+            .filter(|s| s.pubkey == dialect.key())
+            .count() < 1
+    )]
+    pub metadata_page: AccountLoader<'info, MetadataPage>,
+    /// CHECK: The user's metadata policy account, which doesn't exist for users who never set their
+    // policy. Its address is enforced by the seeds, and its owner when it is loaded.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata_policy".as_ref(),
+            user.key().as_ref(),
+        ],
+        bump,
+        // Pages don't hold invites, so the signer must be allowed to subscribe the user directly.
+        constraint = load_subscription_policy(&metadata_policy)?.allows_subscription_by(user.key, signer.key, &dialect_members(&dialect)?) @ ErrorCode::SubscriptionNotAllowed,
+    )]
+    pub metadata_policy: AccountInfo<'info>,
+    pub dialect: AccountLoader<'info, DialectAccount>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for unsubscribing a user from a dialect, which removes the dialect's address from the
+/// list of subscriptions in one of the user's metadata pages.
+///
+/// Only the owner of a metadata page can unsubscribe from a dialect.
+#[derive(Accounts)]
+#[instruction(page_index: u8)]
+pub struct UnsubscribeUserFromPage<'info> {
+    // The metadata page owner and the signer for this transaction.
+    pub user: Signer<'info>,
+    // The metadata page belonging to the user, & from whose subscriptions the dialect will be removed.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata".as_ref(),
+            user.key.as_ref(),
+            &[page_index],
+        ],
+        has_one = user,
+        bump,
+    )]
+    pub metadata_page: AccountLoader<'info, MetadataPage>,
+    /// CHECK: Only the dialect's address is used, so that users can also unsubscribe from
+    // dialects which have already been closed.
+    pub dialect: AccountInfo<'info>,
+}
+
+/// Context for enabling or disabling a user's subscription to a dialect stored in one of the
+/// user's metadata pages.
+///
+/// Only the owner of a metadata page can update its subscriptions.
+#[derive(Accounts)]
+#[instruction(page_index: u8)]
+pub struct SetPageSubscriptionEnabled<'info> {
+    // The metadata page owner and the signer for this transaction.
+    pub user: Signer<'info>,
+    // The metadata page belonging to the user, & in which the subscription is stored.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata".as_ref(),
+            user.key.as_ref(),
+            &[page_index],
+        ],
+        has_one = user,
+        bump,
+    )]
+    pub metadata_page: AccountLoader<'info, MetadataPage>,
+    /// CHECK: Only the dialect's address is used to find the subscription.
+    pub dialect: AccountInfo<'info>,
+}
+
+/// Context for creating a new dialect for one-on-one messaging. The owner deposits the rent,
+/// must be one of the members, and has special privileges for e.g. closing a dialect and
+/// recovering the deposited rent.
+#[derive(Accounts)]
+#[instruction(dialect_nonce: u8, encrypted: bool, scopes: [[bool; 2]; 2], capacity: u16)]
+pub struct CreateDialect<'info> {
+    #[account(mut)] // mut is needed because they're the payer for PDA initialization
+    // We dupe the owner in one of the members, since the members must be sorted
+    pub owner: Signer<'info>,
+    /// CHECK: First member, alphabetically
+    pub member0: AccountInfo<'info>,
+    /// CHECK: Second member, alphabetically
+    pub member1: AccountInfo<'info>,
+    // Support more users in this or other dialect struct
+    #[account(
+        init,
+        // The dialect's PDA is determined by its members' public keys, sorted alphabetically
+        seeds = [
+            b"dialect".as_ref(),
+            member0.key().as_ref(),
+            member1.key().as_ref(),
+        ],
+        // Assert that the members are sorted alphabetically, & unique
+        constraint = member0.key().cmp(&member1.key()) == std::cmp::Ordering::Less,
+        bump,
+        payer = owner,
+        // NB: max space for PDA = 10240, larger capacities are rejected by create_dialect
+        // space = discriminator + dialect account size + member slots + message buffer capacity
+        space = (DIALECT_ACCOUNT_SIZE + 2 * MEMBER_SIZE + capacity as usize)
+            .min(MAX_PERMITTED_DATA_INCREASE)
+    )]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for creating a new dialect for group messaging. The members are passed, sorted
+/// alphabetically, as remaining accounts. The owner deposits the rent and must be one of the
+/// members with admin privileges.
+#[derive(Accounts)]
+#[instruction(members_hash: [u8; 32], encrypted: bool, scopes: Vec<[bool; 2]>, capacity: u16)]
+pub struct CreateGroupDialect<'info> {
+    #[account(mut)] // mut is needed because they're the payer for PDA initialization
+    pub owner: Signer<'info>,
+    #[account(
+        init,
+        // The dialect's PDA is determined by the hash of its members' public keys, sorted
+        // alphabetically. The hash is checked against the remaining accounts in the instruction.
+        seeds = [
+            b"dialect".as_ref(),
+            members_hash.as_ref(),
+        ],
+        bump,
+        payer = owner,
+        // NB: max space for PDA = 10240, larger capacities are rejected by create_group_dialect
+        // space = discriminator + dialect account size + member slots + message buffer capacity
+        space = (DIALECT_ACCOUNT_SIZE + scopes.len() * MEMBER_SIZE + capacity as usize)
+            .min(MAX_PERMITTED_DATA_INCREASE)
+    )]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for closing a dialect account and refunding the rent to the dialect's recorded owner.
+/// Only a member with admin privileges can close it.
+#[derive(Accounts)]
+#[instruction(dialect_nonce: u8)]
+pub struct CloseDialect<'info> {
+    // The signer. Must be a member with admin privileges.
+    #[account(
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *admin.key && m.scopes[0]),
+    )]
+    pub admin: Signer<'info>,
+    /// CHECK: The recorded owner of the dialect, who paid for it or had its ownership
+    /// transferred to them. Receives the rent.
+    #[account(
+        mut,
+        constraint = load_dialect(&dialect)?.owner == *owner.key @ ErrorCode::NotDialectOwner,
+    )]
+    pub owner: AccountInfo<'info>,
+    // The dialect account being closed. Its address is not re-derived from the members here,
+    // since group dialects are seeded by a hash of their members.
+    #[account(
+        mut,
+        close = owner,
+    )]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for migrating a legacy dialect account to the current layout. Only a member with admin
+/// privileges can migrate it, checked in migrate_dialect since the legacy layout differs.
+#[derive(Accounts)]
+pub struct MigrateDialect<'info> {
+    // The signer. Becomes the recorded owner of the dialect. mut is needed because they top up the rent.
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    // The dialect being migrated.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for transferring the ownership of a dialect account. Only the recorded owner can transfer it.
+#[derive(Accounts)]
+pub struct TransferDialectOwnership<'info> {
+    // The recorded owner of the dialect.
+    #[account(
+        constraint = load_dialect(&dialect)?.owner == *owner.key @ ErrorCode::NotDialectOwner,
+    )]
+    pub owner: Signer<'info>,
+    /// CHECK: The new owner of the dialect. Only its address is used.
+    pub new_owner: AccountInfo<'info>,
+    // The dialect whose ownership is being transferred.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+}
+
+/// Context for setting the max message length of a dialect. Only a member with admin privileges can set it.
+#[derive(Accounts)]
+pub struct SetMaxMessageLength<'info> {
+    // The signer. Must be a member with admin privileges.
+    #[account(
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *admin.key && m.is_admin()),
+    )]
+    pub admin: Signer<'info>,
+    // The dialect whose max message length is being set.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+}
+
+/// Context for setting the edit window of a dialect. Only a member with admin privileges can set it.
+#[derive(Accounts)]
+pub struct SetEditWindow<'info> {
+    // The signer. Must be a member with admin privileges.
+    #[account(
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *admin.key && m.is_admin()),
+    )]
+    pub admin: Signer<'info>,
+    // The dialect whose edit window is being set.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
 }
 
-// Contexts
+/// Context for resizing the message buffer of a dialect. Only a member with admin privileges can resize it.
+#[derive(Accounts)]
+pub struct ResizeDialect<'info> {
+    // The signer. Must be a member with admin privileges. mut is needed because they top up the rent.
+    #[account(
+        mut,
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *admin.key && m.is_admin()),
+    )]
+    pub admin: Signer<'info>,
+    // The dialect being resized.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
 
-/// Context to create a metadata account for a user, created by the user.
+/// Context for setting whether a dialect's message payloads are emitted in events. Only a member with admin privileges can set it.
 #[derive(Accounts)]
-pub struct CreateMetadata<'info> {
-    // The metadata owner and the signer for this transaction.
+pub struct SetEmitMessagePayloads<'info> {
+    // The signer. Must be a member with admin privileges.
+    #[account(
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *admin.key && m.is_admin()),
+    )]
+    pub admin: Signer<'info>,
+    // The dialect whose events are being configured.
     #[account(mut)]
-    pub user: Signer<'info>,
-    // The metadata account being created
+    pub dialect: AccountLoader<'info, DialectAccount>,
+}
+
+/// Context for enabling or disabling the archival of a dialect's messages. Only a member with admin privileges can set it.
+#[derive(Accounts)]
+pub struct SetArchiveEnabled<'info> {
+    // The signer. Must be a member with admin privileges.
+    #[account(
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *admin.key && m.is_admin()),
+    )]
+    pub admin: Signer<'info>,
+    // The dialect whose archival is being set.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+}
+
+/// Context for creating the next message history page of a dialect. Any member can pay for it.
+#[derive(Accounts)]
+#[instruction(page_index: u16)]
+pub struct CreateMessageHistoryPage<'info> {
+    // The signer, who pays for the page. Must be a member of the dialect.
+    #[account(
+        mut,
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *payer.key),
+    )]
+    pub payer: Signer<'info>,
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+    // The message history page being created.
     #[account(
         init,
         seeds = [
-            b"metadata".as_ref(),
-            user.key.as_ref(),
+            b"history".as_ref(),
+            dialect.key().as_ref(),
+            page_index.to_le_bytes().as_ref(),
         ],
         bump,
-        payer = user,
-        // discriminator (8) + user + 32 x (subscription) = 1096
-        space = 8 + 32 + (32 * 33),
+        payer = payer,
+        // NB: max space for PDA = 10240
+        // space = discriminator + dialect + page_index + items_count + length + buffer
+        space = 8 + 32 + 2 + 2 + 2 + MESSAGE_HISTORY_PAGE_LENGTH,
     )]
-    pub metadata: AccountLoader<'info, MetadataAccount>,
+    pub history_page: AccountLoader<'info, MessageHistoryPage>,
     pub rent: Sysvar<'info, Rent>,
     pub system_program: Program<'info, System>,
 }
 
-/// Context to close a metadata account and recover its rent. This action is permanent, and all data is lost.
-///
-/// Only the owner of a metadata account can close it.
+/// Context for adding a member to a dialect. Only a member with admin privileges can add members.
 #[derive(Accounts)]
-#[instruction(metadata_nonce: u8)]
-pub struct CloseMetadata<'info> {
-    // The metadata owner and the signer for this transaction.
+pub struct AddMember<'info> {
+    // The signer. Must be a member with admin privileges. mut is needed because they top up the
+    // rent when a member slot is added.
+    #[account(
+        mut,
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *admin.key && m.is_admin()),
+    )]
+    pub admin: Signer<'info>,
+    // The dialect to which the member is being added.
     #[account(mut)]
-    pub user: Signer<'info>,
-    // The metadata account being closed.
+    pub dialect: AccountLoader<'info, DialectAccount>,
+    /// CHECK: The member being added. Only its address is used.
+    pub member: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for removing a member from a dialect. Only a member with admin privileges can remove members.
+#[derive(Accounts)]
+pub struct RemoveMember<'info> {
+    // The signer. Must be a member with admin privileges.
+    #[account(
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *admin.key && m.is_admin()),
+    )]
+    pub admin: Signer<'info>,
+    // The dialect from which the member is being removed.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+    /// CHECK: The member being removed. Only its address is used.
+    pub member: AccountInfo<'info>,
+}
+
+/// Context for updating the scopes of a dialect member. Only a member with admin privileges can update scopes.
+#[derive(Accounts)]
+pub struct UpdateMemberScopes<'info> {
+    // The signer. Must be a member with admin privileges.
+    #[account(
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *admin.key && m.is_admin()),
+    )]
+    pub admin: Signer<'info>,
+    // The dialect of the member whose scopes are being updated.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+    /// CHECK: The member whose scopes are being updated. Only its address is used.
+    pub member: AccountInfo<'info>,
+}
+
+/// Context for setting the max number of members of a dialect. Only a member with admin privileges can set it.
+#[derive(Accounts)]
+pub struct SetMaxMembers<'info> {
+    // The signer. Must be a member with admin privileges.
+    #[account(
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *admin.key && m.is_admin()),
+    )]
+    pub admin: Signer<'info>,
+    // The dialect whose max number of members is being set.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+}
+
+/// Context for sending a message in a dialect. Only a member with write privileges can send messages.
+#[derive(Accounts)]
+#[instruction(dialect_nonce: u8)]
+pub struct SendMessage<'info> {
+    // The signer. Must also be the message sender.
     #[account(
         mut,
-        close = user,
-        // The user closing the metadata account must be its owner.
-        seeds = [
-            b"metadata".as_ref(),
-            user.key.as_ref(),
-        ],
-        has_one = user, // TODO: Does seeds address this, is this redundant?
-        bump = metadata_nonce,
+        // The sender must be a member with write privileges.
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *sender.key) @ ErrorCode::SenderNotMember,
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *sender.key && m.scopes[1]),
     )]
-    pub metadata: AccountLoader<'info, MetadataAccount>,
+    pub sender: Signer<'info>,
+    // The dialect in which the message is being sent. Its address is not re-derived from the
+    // members here, since group dialects are seeded by a hash of their members.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
     pub rent: Sysvar<'info, Rent>,
     pub system_program: Program<'info, System>,
 }
 
-/// Context for subscribing a user to a dialect, which adds the dialect's address to the user's
-/// metadata's list of subscriptions.
+/// Context for editing a message in a dialect. Only the message's sender, with write privileges, can edit it.
 #[derive(Accounts)]
-#[instruction(dialect_nonce: u8, metadata_nonce: u8)] // metadata0_nonce: u8, metadata1_nonce: u8)]
-pub struct SubscribeUser<'info> {
-    // The signing key, a.k.a. the user taking action to subscribe the other user to a new dialect.
+pub struct EditMessage<'info> {
+    // The signer. Must be the message's sender, checked in edit_message.
+    #[account(
+        mut,
+        // The sender must be a member with write privileges.
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *sender.key && m.scopes[1]),
+    )]
+    pub sender: Signer<'info>,
+    // The dialect in which the message is being edited.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for reacting to a message in a dialect. Only a member with write privileges can react.
+#[derive(Accounts)]
+pub struct React<'info> {
+    // The signer. Must also be the reaction's sender.
+    #[account(
+        mut,
+        // The sender must be a member with write privileges.
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *sender.key && m.scopes[1]),
+    )]
+    pub sender: Signer<'info>,
+    // The dialect in which the message is being reacted to.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for marking messages as read in a dialect. Any member can mark messages as read for themselves.
+#[derive(Accounts)]
+pub struct MarkRead<'info> {
+    // The signer. Must be a member of the dialect, checked in mark_read.
+    pub member: Signer<'info>,
+    // The dialect in which the messages are being marked as read.
     #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+}
+
+/// Context for deleting a message from a dialect. Only the message's sender or a member with admin privileges can delete it.
+#[derive(Accounts)]
+pub struct DeleteMessage<'info> {
+    // The signer. Must be the message's sender or a member with admin privileges, checked in delete_message.
     pub signer: Signer<'info>,
-    /// CHECK: N.b. we do not enforce that user is the signer. Meaning, users can subscribe other users
-    // to dialects.
-    pub user: AccountInfo<'info>,
-    // The metadata account belonging to the user, & to whose subscriptions the dialect will be added.
+    // The dialect in which the message is being deleted.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+}
+
+/// Context for pinning a message in a dialect. Only a member with admin privileges can pin it.
+#[derive(Accounts)]
+pub struct PinMessage<'info> {
+    // The signer. Must be a member with admin privileges. mut is needed because they top up the
+    // rent when the pinned region grows.
     #[account(
         mut,
-        // Enforce the same constraint on the metadata account to ensure it belongs to the user.
-        seeds = [
-            b"metadata".as_ref(),
-            user.key().as_ref(),
-        ],
-        bump = metadata_nonce,
-        // Enforce no duplicate subscriptions.
-        constraint = metadata
-            .load()?
-            .subscriptions
-            .iter()
-            .filter(|s| s.pubkey == dialect.key())
-            .count() < 1
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *admin.key && m.is_admin()),
     )]
-    pub metadata: AccountLoader<'info, MetadataAccount>,
+    pub admin: Signer<'info>,
+    // The dialect in which the message is being pinned.
+    #[account(mut)]
     pub dialect: AccountLoader<'info, DialectAccount>,
     pub rent: Sysvar<'info, Rent>,
     pub system_program: Program<'info, System>,
 }
 
-/// Context for creating a new dialect for one-on-one messaging. The owner deposits the rent,
-/// must be one of the members, and has special privileges for e.g. closing a dialect and
-/// recovering the deposited rent.
+/// Context for pruning the expired messages of a dialect. Anyone can prune them.
 #[derive(Accounts)]
-#[instruction(dialect_nonce: u8)]
-pub struct CreateDialect<'info> {
+pub struct PruneExpired<'info> {
+    // The dialect whose expired messages are being pruned.
+    #[account(mut)]
+    pub dialect: AccountLoader<'info, DialectAccount>,
+}
+
+/// Context for creating a broadcast channel. The publisher deposits the rent, and is the only key
+/// that can broadcast messages in the channel.
+#[derive(Accounts)]
+#[instruction(channel_nonce: u8, capacity: u16)]
+pub struct CreateBroadcastChannel<'info> {
     #[account(mut)] // mut is needed because they're the payer for PDA initialization
-    // We dupe the owner in one of the members, since the members must be sorted
-    pub owner: Signer<'info>,
-    /// CHECK: First member, alphabetically
-    pub member0: AccountInfo<'info>,
-    /// CHECK: Second member, alphabetically
-    pub member1: AccountInfo<'info>,
-    // Support more users in this or other dialect struct
+    pub publisher: Signer<'info>,
     #[account(
         init,
-        // The dialect's PDA is determined by its members' public keys, sorted alphabetically
+        // A publisher has a single broadcast channel, seeded by its public key.
         seeds = [
-            b"dialect".as_ref(),
-            member0.key().as_ref(),
-            member1.key().as_ref(),
+            b"broadcast_channel".as_ref(),
+            publisher.key().as_ref(),
         ],
-        // TODO: Assert that owner is a member with admin privileges
-        // Assert that the members are sorted alphabetically, & unique
-        constraint = member0.key().cmp(&member1.key()) == std::cmp::Ordering::Less,
         bump,
-        payer = owner,
+        payer = publisher,
         // NB: max space for PDA = 10240
-        // space = discriminator + dialect account size
-        space = 8 + 68 + (2 + 2 + 2 + 8192) + 4 + 1
+        // space = discriminator + broadcast channel size + message buffer capacity
+        space = BROADCAST_CHANNEL_ACCOUNT_SIZE + capacity as usize
     )]
-    pub dialect: AccountLoader<'info, DialectAccount>,
+    pub channel: AccountLoader<'info, BroadcastChannel>,
     pub rent: Sysvar<'info, Rent>,
     pub system_program: Program<'info, System>,
 }
 
-/// Context for closing a dialect account and recovering the rent. Only the owning user who
-/// created the dialect can close it.
+/// Context for closing a broadcast channel and refunding the rent to its publisher. Only the
+/// publisher can close it.
 #[derive(Accounts)]
-#[instruction(dialect_nonce: u8)]
-pub struct CloseDialect<'info> {
-    // The owner, who originally created the dialect.
+#[instruction(channel_nonce: u8)]
+pub struct CloseBroadcastChannel<'info> {
+    // The channel's publisher and the signer for this transaction.
+    #[account(mut)]
+    pub publisher: Signer<'info>,
+    // The broadcast channel being closed.
     #[account(
         mut,
-        constraint = dialect.load()?.members.iter().filter(|m| m.public_key == *owner.key && m.scopes[0]).count() > 0,
+        close = publisher,
+        seeds = [
+            b"broadcast_channel".as_ref(),
+            publisher.key().as_ref(),
+        ],
+        has_one = publisher,
+        bump = channel_nonce,
     )]
-    pub owner: Signer<'info>,
-    // The dialect account being closed.
+    pub channel: AccountLoader<'info, BroadcastChannel>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for broadcasting a message in a broadcast channel. Only the channel's publisher can
+/// broadcast messages.
+#[derive(Accounts)]
+#[instruction(channel_nonce: u8)]
+pub struct BroadcastMessage<'info> {
+    // The channel's publisher and the signer for this transaction.
+    pub publisher: Signer<'info>,
+    // The broadcast channel in which the message is being broadcast.
     #[account(
         mut,
-        close = owner,
         seeds = [
-            b"dialect".as_ref(),
-            dialect.load()?.members[0].public_key.as_ref(),
-            dialect.load()?.members[1].public_key.as_ref(),
+            b"broadcast_channel".as_ref(),
+            publisher.key().as_ref(),
         ],
-        bump = dialect_nonce,
+        has_one = publisher,
+        bump = channel_nonce,
     )]
-    pub dialect: AccountLoader<'info, DialectAccount>,
+    pub channel: AccountLoader<'info, BroadcastChannel>,
+}
+
+/// Context for subscribing a user to a broadcast channel, which adds the channel's address to the
+/// user's metadata's list of subscriptions.
+///
+/// Only the owner of a metadata account can subscribe to broadcast channels.
+#[derive(Accounts)]
+#[instruction(metadata_nonce: u8)]
+pub struct SubscribeToBroadcastChannel<'info> {
+    // The metadata owner and the signer for this transaction.
+    pub user: Signer<'info>,
+    // The metadata account belonging to the user, & to whose subscriptions the channel will be added.
+    #[account(
+        mut,
+        seeds = [
+            b"metadata".as_ref(),
+            user.key.as_ref(),
+        ],
+        has_one = user,
+        bump = metadata_nonce,
+        // Enforce no duplicate subscriptions.
+        constraint = metadata
+            .load()?
+            .subscriptions
+            .iter()
+            .filter(|s| s.pubkey == channel.key())
+            .count() < 1,
+    )]
+    pub metadata: AccountLoader<'info, MetadataAccount>,
+    pub channel: AccountLoader<'info, BroadcastChannel>,
+}
+
+/// Context to create the account holding the topics of a user's subscription to a broadcast
+/// channel, created by the user.
+#[derive(Accounts)]
+pub struct CreateSubscriptionTopics<'info> {
+    // The subscription topics owner and the signer for this transaction.
+    #[account(mut)]
+    pub user: Signer<'info>,
+    // The subscription topics account being created
+    #[account(
+        init,
+        seeds = [
+            b"subscription_topics".as_ref(),
+            user.key.as_ref(),
+            channel.key().as_ref(),
+        ],
+        bump,
+        payer = user,
+        // discriminator (8) + user + channel + topics = 80
+        space = 8 + 32 + 32 + 8,
+    )]
+    pub subscription_topics: AccountLoader<'info, SubscriptionTopics>,
+    // The broadcast channel the subscription refers to. Only broadcast messages have topics.
+    pub channel: AccountLoader<'info, BroadcastChannel>,
     pub rent: Sysvar<'info, Rent>,
     pub system_program: Program<'info, System>,
 }
 
-/// Context for sending a message in a dialect. Only a member with write privileges can send messages.
+/// Context for setting the topics of a user's subscription to a broadcast channel.
+///
+/// Only the owner of a subscription topics account can set its topics.
 #[derive(Accounts)]
-#[instruction(dialect_nonce: u8)]
-pub struct SendMessage<'info> {
-    // The signer. Must also be the message sender.
+pub struct SetSubscriptionTopics<'info> {
+    // The subscription topics owner and the signer for this transaction.
+    pub user: Signer<'info>,
+    // The subscription topics account belonging to the user, & in which the topics are stored.
     #[account(
         mut,
-        // The sender must be a member with write privileges.
-        constraint = dialect.load()?.members.iter().filter(|m| m.public_key == *sender.key && m.scopes[1]).count() > 0,
+        seeds = [
+            b"subscription_topics".as_ref(),
+            user.key.as_ref(),
+            channel.key().as_ref(),
+        ],
+        has_one = user,
+        bump,
     )]
-    pub sender: Signer<'info>,
-    // The dialect in which the message is being sent.
+    pub subscription_topics: AccountLoader<'info, SubscriptionTopics>,
+    /// CHECK: Only the channel's address is used, as a seed of the subscription topics account.
+    pub channel: AccountInfo<'info>,
+}
+
+/// Context to close the account holding the topics of a user's subscription to a broadcast channel
+/// and recover its rent.
+///
+/// Only the owner of a subscription topics account can close it.
+#[derive(Accounts)]
+pub struct CloseSubscriptionTopics<'info> {
+    // The subscription topics owner and the signer for this transaction.
+    #[account(mut)]
+    pub user: Signer<'info>,
+    // The subscription topics account being closed.
     #[account(
         mut,
+        close = user,
         seeds = [
-            b"dialect".as_ref(),
-            dialect.load()?.members[0].public_key.as_ref(),
-            dialect.load()?.members[1].public_key.as_ref(),
+            b"subscription_topics".as_ref(),
+            user.key.as_ref(),
+            channel.key().as_ref(),
         ],
-        bump = dialect_nonce,
+        has_one = user,
+        bump,
+    )]
+    pub subscription_topics: AccountLoader<'info, SubscriptionTopics>,
+    /// CHECK: Only the channel's address is used, so that users can also close the topics of their
+    /// subscriptions to channels which have already been closed.
+    pub channel: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for unpinning a dialect's pinned message. Only a member with admin privileges can unpin it.
+#[derive(Accounts)]
+pub struct UnpinMessage<'info> {
+    // The signer. Must be a member with admin privileges.
+    #[account(
+        constraint = dialect_members(&dialect)?.iter().any(|m| m.public_key == *admin.key && m.is_admin()),
     )]
+    pub admin: Signer<'info>,
+    // The dialect whose pinned message is being unpinned.
+    #[account(mut)]
     pub dialect: AccountLoader<'info, DialectAccount>,
-    pub rent: Sysvar<'info, Rent>,
-    pub system_program: Program<'info, System>,
 }
 
 // Accounts
@@ -383,7 +2632,13 @@ pub struct SendMessage<'info> {
 /// For now, this metadata includes:
 ///
 /// 1. A reference back to the user's account via their pubkey.
-/// 2. The user's subscriptions, which are a list of dialect PDAs.
+/// 2. The user's subscriptions, which are a list of dialect or broadcast channel PDAs.
+///
+/// The user's subscription policy & pending invites are stored in a separate account, see
+/// MetadataPolicyAccount below.
+///
+/// Once the user creates a metadata page, a bitmap of the user's existing pages is stored in the
+/// account's data after the MetadataAccount struct, see METADATA_PAGES_LENGTH.
 ///
 /// The MetadataAccount will be expanded in the future to account for more information about the user.
 #[account(zero_copy)]
@@ -395,63 +2650,680 @@ pub struct MetadataAccount {
     subscriptions: [Subscription; 32], // 32 * space(Subscription)
 }
 
-const MESSAGE_BUFFER_LENGTH: usize = 8192;
+/// The MetadataPolicyAccount is an account that holds a user's subscription policy, see
+/// SubscriptionPolicy below, & the user's pending invites, which are a list of dialect PDAs other
+/// users tried to subscribe the user to.
+///
+/// It is seeded by the user's pubkey. Users without a metadata policy account have the MembersOnly
+/// policy.
+#[account(zero_copy)]
+#[derive(Default)]
+pub struct MetadataPolicyAccount {
+    /// Backward reference to the user's account with which this policy is associated.
+    user: Pubkey, // 32
+    /// The user's SubscriptionPolicy, encoded as u8.
+    subscription_policy: u8, // 1
+    /// A list of dialects other users invited the user to. Empty entries are default public keys.
+    invites: [Pubkey; 16], // 16 * 32
+}
+
+impl MetadataPolicyAccount {
+    /// Returns the user's subscription policy.
+    fn subscription_policy(&self) -> SubscriptionPolicy {
+        match self.subscription_policy {
+            1 => SubscriptionPolicy::Open,
+            2 => SubscriptionPolicy::OwnerApproval,
+            _ => SubscriptionPolicy::MembersOnly,
+        }
+    }
+}
+
+/// The MetadataPage is an account that holds additional subscriptions of a user, once the 32
+/// subscriptions of the user's MetadataAccount are full.
+///
+/// A user can have up to 256 pages, each seeded by the user's pubkey and the page's index.
+#[account(zero_copy)]
+#[derive(Default)]
+pub struct MetadataPage {
+    /// Backward reference to the user's account with which this page is associated.
+    user: Pubkey, // 32
+    /// The index of this page among the user's metadata pages.
+    page_index: u8, // 1
+    /// A list of dialects the user has subscribed to.
+    subscriptions: [Subscription; 32], // 32 * space(Subscription)
+}
+
+/// The SubscriptionTopics is an account that holds the topics of a user's subscription to a
+/// broadcast channel, seeded by the user's & the channel's pubkeys. Subscriptions without a
+/// SubscriptionTopics account include all topics, see ALL_TOPICS.
+#[account(zero_copy)]
+#[derive(Default)]
+pub struct SubscriptionTopics {
+    /// Backward reference to the user's account with which these topics are associated.
+    user: Pubkey, // 32
+    /// Address of the broadcast channel subscribed to.
+    channel: Pubkey, // 32
+    /// The topics of the broadcast channel subscribed to, as a bitmask: the user is subscribed to
+    /// topic i if bit i is set.
+    pub topics: u64, // 8
+}
+
+impl SubscriptionTopics {
+    /// Returns whether a broadcast message with the given topic, if any, is delivered to the
+    /// subscriber. Messages without a topic are delivered to all subscribers.
+    ///
+    /// Arguments
+    ///
+    /// * topic: The id of the message's topic, if any.
+    pub fn includes_topic(&self, topic: Option<u8>) -> bool {
+        match topic {
+            Some(topic) => topic < MAX_TOPICS && self.topics & (1 << topic) != 0,
+            None => true,
+        }
+    }
+}
+
+/// The length of the buffer of a message history page, which fills the 10240 bytes of a PDA.
+pub const MESSAGE_HISTORY_PAGE_LENGTH: usize = 10240 - (8 + 32 + 2 + 2 + 2);
+
+/// The minimum capacity of a dialect's message buffer.
+pub const MIN_MESSAGE_BUFFER_LENGTH: u16 = 1024;
+/// The maximum capacity of a dialect's message buffer, see resize_dialect. Offsets within the
+/// buffer are u16, so twice the capacity must fit in a u16.
+pub const MAX_MESSAGE_BUFFER_LENGTH: u16 = 16 * 1024;
+/// The maximum capacity of a one-on-one dialect's message buffer at creation. PDAs are limited to
+/// 10240 bytes when they are created, larger buffers require resize_dialect. Group dialects' member
+/// slots take up more of these bytes.
+pub const MAX_INITIAL_MESSAGE_BUFFER_LENGTH: u16 =
+    (MAX_PERMITTED_DATA_INCREASE - DIALECT_ACCOUNT_SIZE - 2 * MEMBER_SIZE) as u16;
+
 const ITEM_METADATA_OVERHEAD: u16 = 2;
+/// The type of the items storing messages sent by members, see DialectAccount.append.
+pub const ITEM_TYPE_TEXT: u8 = 0;
+/// The type of the items storing reactions to messages, see DialectAccount.react.
+pub const ITEM_TYPE_REACTION: u8 = 1;
+/// The type of the items storing messages written by the program itself. Reserved, the program
+/// doesn't write system messages yet.
+pub const ITEM_TYPE_SYSTEM: u8 = 2;
+/// The type of the items storing messages deleted by delete_message, whose text is zeroed.
+pub const ITEM_TYPE_TOMBSTONE: u8 = 3;
+// The item type, sender member index, flags, message id & timestamp stored before each message's text.
+const MESSAGE_HEADER_LENGTH: u16 = 1 + 1 + 1 + 8 + 4;
+// The id of another message, stored after the header of messages referencing it, i.e. edits & replies.
+const MESSAGE_REFERENCE_LENGTH: u16 = 8;
+// The envelope version & content type stored before each message's text.
+const MESSAGE_ENVELOPE_LENGTH: u16 = 1 + 1;
+/// The version of the message envelope written by the program, see DialectAccount.append.
+pub const MESSAGE_ENVELOPE_VERSION: u8 = 1;
+// The flag of edits appended by edit_message, which reference the edited message.
+const MESSAGE_FLAG_EDIT: u8 = 1;
+// The flag of replies, which reference the message replied to.
+const MESSAGE_FLAG_REPLY: u8 = 2;
+// The flag of messages that expire, which store their time-to-live in seconds after their reference,
+// and of the reactions to them.
+const MESSAGE_FLAG_TTL: u8 = 4;
+// The length of the time-to-live of expiring messages.
+const MESSAGE_TTL_LENGTH: u16 = 4;
+// The flag of broadcast messages with a topic, which store its id after their time-to-live.
+const MESSAGE_FLAG_TOPIC: u8 = 8;
+// The length of the topic id of broadcast messages with a topic.
+const MESSAGE_TOPIC_LENGTH: u16 = 1;
+/// The number of topics in a broadcast channel, see SubscriptionTopics.topics.
+pub const MAX_TOPICS: u8 = 64;
+/// The topics of subscriptions without a SubscriptionTopics account, i.e. all topics.
+pub const ALL_TOPICS: u64 = u64::MAX;
+/// The default time window in seconds in which the sender of a message can edit it.
+pub const DEFAULT_EDIT_WINDOW: u32 = 15 * 60;
+/// The maximum length of the copy of a dialect's pinned message, see pin_message.
+pub const PINNED_MESSAGE_LENGTH: usize = 512;
+
+/// The version of the dialect account layout, see DialectAccount.version.
+pub const DIALECT_ACCOUNT_VERSION: u8 = 2;
+/// The size of a dialect account without its member slots, pinned region & message buffer, which
+/// are stored in the account's data after the DialectAccount struct, so that they can be resized.
+const DIALECT_ACCOUNT_SIZE: usize = 8 + std::mem::size_of::<DialectAccount>();
+/// The size of a member slot, see Member.
+const MEMBER_SIZE: usize = std::mem::size_of::<Member>();
+/// The size of the dialect accounts created before DialectAccount.version was introduced, see
+/// LegacyDialectAccount.
+const LEGACY_DIALECT_ACCOUNT_SIZE: usize = 8 + std::mem::size_of::<LegacyDialectAccount>();
+/// The capacity of the message buffer of the dialect accounts created before
+/// DialectAccount.version was introduced.
+const LEGACY_MESSAGE_BUFFER_LENGTH: u16 = 8192;
+/// The size of a broadcast channel account without its message buffer, which is stored in the
+/// account's data after the BroadcastChannel struct.
+const BROADCAST_CHANNEL_ACCOUNT_SIZE: usize = 8 + std::mem::size_of::<BroadcastChannel>();
+/// The size of a metadata account without its bitmap of metadata pages, which is stored in the
+/// account's data after the MetadataAccount struct.
+const METADATA_ACCOUNT_SIZE: usize = 8 + std::mem::size_of::<MetadataAccount>();
+/// The length of the bitmap of a user's existing metadata pages: bit i of byte i / 8 is set if
+/// page i exists.
+const METADATA_PAGES_LENGTH: usize = 256 / 8;
+/// The maximum capacity of a broadcast channel's message buffer. PDAs are limited to 10240 bytes
+/// when they are created.
+pub const MAX_INITIAL_BROADCAST_BUFFER_LENGTH: u16 =
+    (MAX_PERMITTED_DATA_INCREASE - BROADCAST_CHANNEL_ACCOUNT_SIZE) as u16;
 
 /// The DialectAccount is the main account for creating messaging.
 ///
 /// The DialectAccount stores
 ///
-/// 1. references to the dialect's members and their scopes (currently two members for one-on-one messaging),
+/// 1. its member slots, i.e. references to the dialect's members and their scopes (two members for one-on-one messaging, up to max_members for group messaging),
 /// 2. its messages, which are stored in a CyclicByteBuffer (see below),
 /// 3. the time stamp of the last message sent in the dialect, and
 /// 4. whether or not the dialect is encrypted.
+///
+/// The member slots, the copy of the pinned message & the message buffer are stored in this
+/// order at the end of the account's data, after the DialectAccount struct, and each sized to fit
+/// its content, so that e.g. one-on-one dialects don't pay rent for the slots of group dialects.
+/// See load_dialect_mut.
+///
+/// The version field overlaps the scopes of the first member of the dialect accounts created
+/// before it was introduced, which are 0 or 1, so that their layout can be told apart. These
+/// accounts must be migrated to the current layout, see migrate_dialect.
 #[account(zero_copy)]
-// zero_copy used to use repr(packed) rather than its new default, repr(C), so
-// we need to explicitly use repr(packed) here to maintain backwards
-// compatibility with old dialect accounts.
-#[repr(packed)]
+#[repr(C, packed)]
 /// NB: max space for PDA = 10240
-/// space = 8 + 68 + (2 + 2 + 2 + 8192) + 4 + 1
+/// space = 8 + 32 + 1 + 1 + 1 + (2 + 2 + 2 + 2) + 4 + 1 + 2 + 1 + 2 + 8 + 1 + 4 + 2 + (members_count * 43) + pinned_message_length + capacity
 pub struct DialectAccount {
-    /// The Dialect members. See the Member struct below
-    pub members: [Member; 2], // 2 * Member = 68
+    /// The owner of the dialect, who receives its rent when it is closed. Initially the user who
+    /// paid for the dialect account, see transfer_dialect_ownership.
+    pub owner: Pubkey, // 32
+    /// The version of the account's layout, DIALECT_ACCOUNT_VERSION.
+    pub version: u8, // 1
+    /// The maximum number of member slots, see set_max_members.
+    pub max_members: u8, // 1
+    /// The number of member slots, including the slots of removed members. Messages reference
+    /// their sender by slot index, so the slots of removed members are retained. See Member.
+    pub members_count: u8, // 1
     /// The dalect's messages. See the CyclicByteButffer below
-    pub messages: CyclicByteBuffer, // 2 + 2 + 2 + 8192
+    pub messages: CyclicByteBuffer, // 2 + 2 + 2 + 2
     /// The last message timestamp, for convenience, it should always match the timestamp of the last message sent, or if there are no messages yet the timestamp of the dialect's creation.
     pub last_message_timestamp: u32, // 4, UTC seconds, max value = Sunday, February 7, 2106 6:28:15 AM
     /// A bool representing whether or not the dialect is encrypted.
     pub encrypted: bool, // 1
+    /// The maximum length of a message's text in bytes, up to the length of the largest message
+    /// that fits in the message buffer. Admins can lower it, see set_max_message_length.
+    pub max_message_length: u16, // 2
+    /// Whether messages evicted from the message buffer are archived in the dialect's message
+    /// history pages, see MessageHistoryPage.
+    pub archive_enabled: bool, // 1
+    /// The number of message history pages created for the dialect. Evicted messages are archived
+    /// after the last page holding messages, see archive_messages.
+    pub history_pages_count: u16, // 2
+    /// The id of the next message sent in the dialect. Message ids start at 0 and are assigned in
+    /// order, so gaps between the ids of the retrieved messages reveal evicted messages.
+    pub next_message_id: u64, // 8
+    /// Whether the MessageSentEvents include the message's payload, see set_emit_message_payloads.
+    pub emit_message_payloads: bool, // 1
+    /// The time window in seconds after a message is sent in which its sender can edit it, see
+    /// set_edit_window.
+    pub edit_window: u32, // 4
+    /// The length of the copy of the pinned message, in the same format as in the message buffer,
+    /// without its size, or 0 if no message is pinned. The copy is outside the message buffer, so
+    /// that the pinned message is never evicted. See pin_message.
+    pub pinned_message_length: u16, // 2
 }
 
 impl DialectAccount {
+    /// Returns the offset of the copy of the pinned message in the account's data, after the
+    /// member slots.
+    fn pinned_message_offset(&self) -> usize {
+        DIALECT_ACCOUNT_SIZE + self.members_count as usize * MEMBER_SIZE
+    }
+
+    /// Returns the offset of the message buffer in the account's data, after the copy of the
+    /// pinned message.
+    fn buffer_offset(&self) -> usize {
+        self.pinned_message_offset() + self.pinned_message_length as usize
+    }
+
+    /// Returns the length of the largest message text that fits in the dialect's message buffer,
+    /// including a reference to another message & the message envelope. When archival is enabled, the message must also
+    /// fit in a message history page.
+    pub fn max_message_length_limit(&self) -> u16 {
+        let mut item_length_limit = self.messages.capacity;
+        if self.archive_enabled {
+            item_length_limit = item_length_limit.min(MESSAGE_HISTORY_PAGE_LENGTH as u16);
+        }
+        item_length_limit
+            - ITEM_METADATA_OVERHEAD
+            - MESSAGE_HEADER_LENGTH
+            - MESSAGE_REFERENCE_LENGTH
+            - MESSAGE_TTL_LENGTH
+            - MESSAGE_ENVELOPE_LENGTH
+    }
+
+    /// Returns the index of the member slot a new member should be stored in. If it is
+    /// members_count, a new slot must be added.
+    ///
+    /// Arguments
+    ///
+    /// * member_slots: The dialect's member slots.
+    /// * pinned_message: The copy of the dialect's pinned message, if any.
+    /// * buffer: The dialect's message buffer.
+    /// * public_key: The public key of the new member.
+    fn find_slot_for_new_member(
+        &self,
+        member_slots: &[Member],
+        pinned_message: &[u8],
+        buffer: &[u8],
+        public_key: &Pubkey,
+    ) -> Result<usize> {
+        if current_members(member_slots).any(|m| m.public_key == *public_key) {
+            return err!(ErrorCode::MemberAlreadyExists);
+        }
+        // A removed member gets their previous slot back.
+        if let Some(idx) = member_slots
+            .iter()
+            .position(|m| m.public_key == *public_key)
+        {
+            return Ok(idx);
+        }
+        if self.members_count < self.max_members {
+            return Ok(self.members_count as usize);
+        }
+        // Archived messages may reference any slot.
+        if self.history_pages_count > 0 {
+            return err!(ErrorCode::DialectMembersLimitReached);
+        }
+        // Otherwise, reuse the slot of a removed member that no stored item, nor the pinned message,
+        // references. Items of all types store their sender's member slot index after the item type.
+        let mut referenced_slots: Vec<u8> = self
+            .messages
+            .items(buffer)
+            .iter()
+            .map(|item| item[1])
+            .collect();
+        if !pinned_message.is_empty() {
+            referenced_slots.push(pinned_message[1]);
+        }
+        match member_slots
+            .iter()
+            .enumerate()
+            .position(|(idx, m)| m.removed && !referenced_slots.contains(&(idx as u8)))
+        {
+            Some(idx) => Ok(idx),
+            None => err!(ErrorCode::DialectMembersLimitReached),
+        }
+    }
+
+    /// Finds a text message, i.e. not deleted, in the message buffer by its id. Returns the message
+    /// along with its offset in the buffer, see CyclicByteBuffer.write.
+    ///
+    /// Arguments
+    ///
+    /// * buffer: The dialect's message buffer.
+    /// * message_id: The id of the message.
+    fn find_message(&self, buffer: &[u8], message_id: u64) -> Result<(u16, Vec<u8>)> {
+        match self
+            .messages
+            .items_with_offsets(buffer)
+            .into_iter()
+            .find(|(_, message)| {
+                message[0] == ITEM_TYPE_TEXT && message[3..11] == message_id.to_be_bytes()
+            }) {
+            Some(message) => Ok(message),
+            None => err!(ErrorCode::MessageNotFound),
+        }
+    }
+
     /// Append another message to the dialect's messages. See the CyclicByteBuffer for more information on implementation.
+    /// Messages are stored as [item type, sender member index, flags, message id, timestamp, envelope, text],
+    /// numbers in big endian, where the envelope is [envelope version, content type].
+    /// Edits & replies are flagged and store the id of the message they reference before the envelope.
+    /// Expiring messages are flagged and store their time-to-live after the reference, if any.
+    /// Returns the messages evicted from the message buffer to make room for it, oldest first.
     ///
     /// Arguments
     ///
+    /// * buffer: The dialect's message buffer.
     /// * text: The message to append, encoded in u8.
-    /// * sender: The sender of the message, as a generic AccountInfo.
-    fn append(&mut self, text: Vec<u8>, sender: &mut AccountInfo) {
-        let now = Clock::get().unwrap().unix_timestamp as u32;
+    /// * content_type: The content type of the text, validated unless the dialect is encrypted.
+    /// * sender_member_idx: The index of the member slot of the sender of the message.
+    /// * reference: The flag of the message's reference & the id of the message it references, if any.
+    /// * ttl: The time-to-live of the message in seconds, if it expires.
+    fn append(
+        &mut self,
+        buffer: &mut [u8],
+        text: Vec<u8>,
+        content_type: ContentType,
+        sender_member_idx: u8,
+        reference: Option<(u8, u64)>,
+        ttl: Option<u32>,
+    ) -> Result<Vec<Vec<u8>>> {
+        if text.len() > self.max_message_length as usize {
+            return err!(ErrorCode::MessageTooLarge);
+        }
+        // Encrypted texts can't be validated.
+        if !self.encrypted && !content_type.is_valid(&text) {
+            return err!(ErrorCode::InvalidMessageContent);
+        }
+        let now = match Clock::get() {
+            Ok(clock) => clock.unix_timestamp as u32,
+            Err(_) => return err!(ErrorCode::ClockUnavailable),
+        };
+        let message_id = self.next_message_id;
+        let mut serialized_message = vec![ITEM_TYPE_TEXT];
+        serialized_message.extend_from_slice(&sender_member_idx.to_be_bytes());
+        let mut flags = match reference {
+            Some((flag, _)) => flag,
+            None => 0,
+        };
+        if ttl.is_some() {
+            flags |= MESSAGE_FLAG_TTL;
+        }
+        serialized_message.push(flags);
+        serialized_message.extend_from_slice(&message_id.to_be_bytes());
+        serialized_message.extend_from_slice(&now.to_be_bytes());
+        if let Some((_, referenced_message_id)) = reference {
+            serialized_message.extend_from_slice(&referenced_message_id.to_be_bytes());
+        }
+        if let Some(ttl) = ttl {
+            serialized_message.extend_from_slice(&ttl.to_be_bytes());
+        }
+        serialized_message.push(MESSAGE_ENVELOPE_VERSION);
+        serialized_message.push(content_type as u8);
+        serialized_message.extend(text);
+        let evicted_items = self.messages.append(buffer, serialized_message)?;
         self.last_message_timestamp = now;
-        let sender_member_idx = self
-            .members
+        self.next_message_id += 1;
+        Ok(evicted_items)
+    }
+
+    /// Append a reaction to a message to the dialect's messages. Reactions are stored as compact
+    /// items [item type, sender member index, flags, message id, reaction], numbers in big endian,
+    /// without an id or timestamp of their own. Reactions to expiring messages still in the message
+    /// buffer are flagged, so that they are never archived either.
+    /// Returns the messages evicted from the message buffer to make room for it, oldest first.
+    ///
+    /// Arguments
+    ///
+    /// * buffer: The dialect's message buffer.
+    /// * message_id: The id of the message reacted to.
+    /// * reaction: The reaction code, e.g. an emoji's Unicode code point.
+    /// * sender_member_idx: The index of the member slot of the sender of the reaction.
+    fn react(
+        &mut self,
+        buffer: &mut [u8],
+        message_id: u64,
+        reaction: u32,
+        sender_member_idx: u8,
+    ) -> Result<Vec<Vec<u8>>> {
+        if message_id >= self.next_message_id {
+            return err!(ErrorCode::MessageNotFound);
+        }
+        let expires = self.messages.items(buffer).iter().any(|item| {
+            item[0] != ITEM_TYPE_REACTION
+                && item[3..11] == message_id.to_be_bytes()
+                && item[2] & MESSAGE_FLAG_TTL != 0
+        });
+        let flags = match expires {
+            true => MESSAGE_FLAG_TTL,
+            false => 0,
+        };
+        let mut serialized_reaction = vec![ITEM_TYPE_REACTION, sender_member_idx, flags];
+        serialized_reaction.extend_from_slice(&message_id.to_be_bytes());
+        serialized_reaction.extend_from_slice(&reaction.to_be_bytes());
+        self.messages.append(buffer, serialized_reaction)
+    }
+
+    /// Erases the expired messages from the front of the message buffer, along with the reactions
+    /// to them. Returns the number of erased items.
+    ///
+    /// Arguments
+    ///
+    /// * buffer: The dialect's message buffer.
+    /// * now: The current time, in UTC seconds.
+    fn prune_expired(&mut self, buffer: &mut [u8], now: u32) -> u16 {
+        let mut pruned_message_ids = Vec::new();
+        let mut pruned_items_count = 0;
+        while let Some(item) = self.messages.oldest_item(buffer) {
+            let expired = match item[0] {
+                ITEM_TYPE_REACTION => pruned_message_ids
+                    .contains(&u64::from_be_bytes(item[3..11].try_into().unwrap())),
+                _ => match message_ttl(&item) {
+                    Some(ttl) => {
+                        let sent_at = u32::from_be_bytes(item[11..15].try_into().unwrap());
+                        sent_at as u64 + ttl as u64 <= now as u64
+                    }
+                    None => false,
+                },
+            };
+            if !expired {
+                break;
+            }
+            if item[0] != ITEM_TYPE_REACTION {
+                pruned_message_ids.push(u64::from_be_bytes(item[3..11].try_into().unwrap()));
+            }
+            self.messages.erase_oldest_item(buffer);
+            pruned_items_count += 1;
+        }
+        pruned_items_count
+    }
+}
+
+/// The layout of the dialect accounts created before DialectAccount.version was introduced, which
+/// stored two member slots & a message buffer of LEGACY_MESSAGE_BUFFER_LENGTH bytes in place, see
+/// migrate_dialect. Their messages were stored as [sender member index, timestamp, text].
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+// space = 8 + (2 * 34) + 2 + 2 + 2 + 8192 + 4 + 1
+struct LegacyDialectAccount {
+    members: [LegacyMember; 2],  // 2 * 34
+    read_offset: u16,            // 2
+    write_offset: u16,           // 2
+    items_count: u16,            // 2
+    buffer: [u8; 8192],          // 8192
+    last_message_timestamp: u32, // 4
+    encrypted: bool,             // 1
+}
+
+/// A member slot of a LegacyDialectAccount.
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+// space = 34
+struct LegacyMember {
+    public_key: Pubkey, // 32
+    scopes: [bool; 2],  // 2
+}
+
+// Legacy dialect accounts are only ever borrowed from the account's data, see migrate_dialect.
+unsafe impl bytemuck::Pod for LegacyDialectAccount {}
+unsafe impl bytemuck::Zeroable for LegacyDialectAccount {}
+
+impl LegacyDialectAccount {
+    /// Returns the member slots, in the current format.
+    fn member_slots(&self) -> Vec<Member> {
+        let members = self.members;
+        members
             .iter()
-            .position(|m| m.public_key == *sender.key)
-            .unwrap() as u8;
-        let mut serialized_message = Vec::new();
-        serialized_message.extend(sender_member_idx.to_be_bytes().into_iter());
-        serialized_message.extend(now.to_be_bytes().into_iter());
+            .map(|m| Member {
+                public_key: m.public_key,
+                scopes: m.scopes,
+                removed: false,
+                read_until: 0,
+            })
+            .collect()
+    }
+
+    /// Returns the messages, oldest first, in the current format, see DialectAccount.append. The
+    /// messages get ids in the order they were sent.
+    fn messages(&self) -> Vec<Vec<u8>> {
+        let messages = CyclicByteBuffer {
+            read_offset: self.read_offset,
+            write_offset: self.write_offset,
+            items_count: self.items_count,
+            capacity: LEGACY_MESSAGE_BUFFER_LENGTH,
+        };
+        messages
+            .items(&self.buffer)
+            .into_iter()
+            .enumerate()
+            .map(|(idx, item)| {
+                let mut message = vec![ITEM_TYPE_TEXT, item[0], 0];
+                message.extend_from_slice(&(idx as u64).to_be_bytes());
+                message.extend_from_slice(&item[1..5]);
+                message.push(MESSAGE_ENVELOPE_VERSION);
+                message.push(ContentType::TextPlain as u8);
+                message.extend_from_slice(&item[5..]);
+                message
+            })
+            .collect()
+    }
+}
+
+/// The MessageHistoryPage is an account that archives messages evicted from a dialect's message
+/// buffer, when archival is enabled for the dialect. See set_archive_enabled.
+///
+/// A dialect's pages are seeded by the dialect's address and the page's index, and chained by
+/// their index: evicted messages are archived in the last page, and once it is full the next
+/// page must be created. See create_message_history_page.
+#[account(zero_copy)]
+pub struct MessageHistoryPage {
+    /// Backward reference to the dialect whose messages are archived in this page.
+    pub dialect: Pubkey, // 32
+    /// The index of this page among the dialect's message history pages.
+    pub page_index: u16, // 2
+    /// The number of messages archived in this page.
+    pub items_count: u16, // 2
+    /// The number of bytes of [buffer] in use.
+    pub length: u16, // 2
+    /// The archived messages, oldest first, in the same format as in the dialect's message buffer:
+    /// each message is prefixed with its size, stored in 2 bytes.
+    // NB: the IDL generator doesn't support constant array lengths, = MESSAGE_HISTORY_PAGE_LENGTH
+    pub buffer: [u8; 10194], // 10194
+}
+
+impl MessageHistoryPage {
+    /// Returns whether there's space left in the page for an evicted message.
+    ///
+    /// Arguments
+    ///
+    /// * item: The evicted message, as stored in the dialect's message buffer.
+    fn has_room_for(&self, item: &[u8]) -> bool {
+        self.length as usize + ITEM_METADATA_OVERHEAD as usize + item.len()
+            <= MESSAGE_HISTORY_PAGE_LENGTH
+    }
+
+    /// Appends an evicted message to the page. Fails if there's no space left for it, in which
+    /// case the next page must be created.
+    ///
+    /// Arguments
+    ///
+    /// * item: The evicted message, as stored in the dialect's message buffer.
+    fn append(&mut self, item: &[u8]) -> Result<()> {
+        if !self.has_room_for(item) {
+            return err!(ErrorCode::MessageHistoryPageFull);
+        }
+        let offset = self.length as usize;
+        let item_with_metadata_len = ITEM_METADATA_OVERHEAD as usize + item.len();
+        let item_len = (item.len() as u16).to_be_bytes();
+        self.buffer[offset..offset + ITEM_METADATA_OVERHEAD as usize].copy_from_slice(&item_len);
+        self.buffer[offset + ITEM_METADATA_OVERHEAD as usize..offset + item_with_metadata_len]
+            .copy_from_slice(item);
+        self.length += item_with_metadata_len as u16;
+        self.items_count += 1;
+        Ok(())
+    }
+}
+
+/// The BroadcastChannel is an account in which a single publisher, e.g. a dapp, broadcasts
+/// messages to any number of users, who subscribe to it by adding its address to the subscriptions
+/// in their metadata account, instead of a dialect per user. See subscribe_to_broadcast_channel.
+///
+/// As for dialects, its messages are stored in a CyclicByteBuffer at the end of the account's data,
+/// in the same format as dialect messages, with the publisher as the sender in member slot 0.
+#[account(zero_copy)]
+// Packed as DialectAccount, since CyclicByteBuffer is too.
+#[repr(C, packed)]
+/// space = 8 + 32 + (2 + 2 + 2 + 2) + 4 + 8 + capacity
+pub struct BroadcastChannel {
+    /// The channel's publisher, the only key that can broadcast messages in it. The publisher
+    /// paid for the channel, and receives its rent when it is closed.
+    pub publisher: Pubkey, // 32
+    /// The channel's messages. See the CyclicByteBuffer below.
+    pub messages: CyclicByteBuffer, // 2 + 2 + 2 + 2
+    /// The timestamp of the last message broadcast, or if there are no messages yet the timestamp
+    /// of the channel's creation.
+    pub last_message_timestamp: u32, // 4, UTC seconds
+    /// The id of the next message broadcast in the channel. Message ids start at 0 and are
+    /// assigned in order, as in dialects.
+    pub next_message_id: u64, // 8
+}
+
+impl BroadcastChannel {
+    /// Returns the maximum length of a message's text that fits in the channel's message buffer.
+    pub fn max_message_length(&self) -> u16 {
+        self.messages.capacity
+            - ITEM_METADATA_OVERHEAD
+            - MESSAGE_HEADER_LENGTH
+            - MESSAGE_TOPIC_LENGTH
+            - MESSAGE_ENVELOPE_LENGTH
+    }
+
+    /// Append a message to the channel's messages, in the same format as dialect messages, see
+    /// DialectAccount.append. The publisher is stored as the sender in member slot 0. Messages with
+    /// a topic are flagged and store its id before the envelope.
+    ///
+    /// Arguments
+    ///
+    /// * buffer: The channel's message buffer.
+    /// * text: The message to append, encoded in u8.
+    /// * content_type: The content type of the text, which is validated.
+    /// * topic: The id of the message's topic, if any.
+    fn append(
+        &mut self,
+        buffer: &mut [u8],
+        text: Vec<u8>,
+        content_type: ContentType,
+        topic: Option<u8>,
+    ) -> Result<()> {
+        if text.len() > self.max_message_length() as usize {
+            return err!(ErrorCode::MessageTooLarge);
+        }
+        if matches!(topic, Some(topic) if topic >= MAX_TOPICS) {
+            return err!(ErrorCode::InvalidTopic);
+        }
+        if !content_type.is_valid(&text) {
+            return err!(ErrorCode::InvalidMessageContent);
+        }
+        let now = match Clock::get() {
+            Ok(clock) => clock.unix_timestamp as u32,
+            Err(_) => return err!(ErrorCode::ClockUnavailable),
+        };
+        let message_id = self.next_message_id;
+        let flags = match topic {
+            Some(_) => MESSAGE_FLAG_TOPIC,
+            None => 0,
+        };
+        let mut serialized_message = vec![ITEM_TYPE_TEXT, 0, flags];
+        serialized_message.extend_from_slice(&message_id.to_be_bytes());
+        serialized_message.extend_from_slice(&now.to_be_bytes());
+        if let Some(topic) = topic {
+            serialized_message.push(topic);
+        }
+        serialized_message.push(MESSAGE_ENVELOPE_VERSION);
+        serialized_message.push(content_type as u8);
         serialized_message.extend(text);
-        self.messages.append(serialized_message)
+        self.messages.append(buffer, serialized_message)?;
+        self.last_message_timestamp = now;
+        self.next_message_id += 1;
+        Ok(())
     }
 }
 
 /// A special data structure that is used to efficiently store arbitrary length byte arrays.
 /// Maintains FIFO attributes on top of cyclic buffer.
 /// Ensures there's a space to append new item by erasing old items, if no space available.
+///
+/// The underlying bytebuffer is not part of the struct: it is stored in the dialect account's
+/// data after the DialectAccount struct, so that it can be resized, and passed to the methods
+/// below as [buffer]. Broadcast channels store their message buffer alike.
 #[zero_copy]
-// space = 2 + 2 + 2 + 8192
+// DialectAccount is repr(packed), so its fields must be too in order to be borrowed.
+#[repr(C, packed)]
+// space = 2 + 2 + 2 + 2
 pub struct CyclicByteBuffer {
     /// Offset of first item in [buffer].
     pub read_offset: u16, // 2
@@ -459,28 +3331,74 @@ pub struct CyclicByteBuffer {
     pub write_offset: u16, // 2
     /// Current number of items in [buffer].
     pub items_count: u16, // 2
-    /// Underlying bytebuffer, stores items.
-    pub buffer: [u8; 8192], // 8192
+    /// Length of the underlying bytebuffer, which stores items.
+    pub capacity: u16, // 2
 }
 
 impl CyclicByteBuffer {
     /// Appends an arbitrary length item passed in the parameter to the end of the buffer.
     /// If the buffer has no space for insertion, it returns removes old items until there's enough space.
+    /// Fails if the item with its metadata is larger than the whole buffer.
+    /// Returns the removed items, oldest first.
     ///
     /// ### Arguments
     ///
+    /// * buffer: the underlying bytebuffer.
     /// * item: an bytebuffer/bytearray to be appended.
-    fn append(&mut self, item: Vec<u8>) {
+    fn append(&mut self, buffer: &mut [u8], item: Vec<u8>) -> Result<Vec<Vec<u8>>> {
+        let item_with_metadata_len = match u16::try_from(item.len()) {
+            Ok(item_len) if item_len <= self.capacity - ITEM_METADATA_OVERHEAD => {
+                item_len + ITEM_METADATA_OVERHEAD
+            }
+            _ => return err!(ErrorCode::MessageTooLarge),
+        };
         let item_with_metadata = &mut Vec::new();
-        let item_len = (item.len() as u16).to_be_bytes();
-        item_with_metadata.extend(item_len.into_iter());
+        let item_len = (item_with_metadata_len - ITEM_METADATA_OVERHEAD).to_be_bytes();
+        item_with_metadata.extend_from_slice(&item_len);
         item_with_metadata.extend(item);
 
-        let new_write_offset: u16 = self.mod_(self.write_offset + item_with_metadata.len() as u16);
-        while self.no_space_available_for(item_with_metadata.len() as u16) {
-            self.erase_oldest_item()
+        let new_write_offset: u16 = self.mod_(self.write_offset + item_with_metadata_len);
+        let mut erased_items = Vec::new();
+        while self.no_space_available_for(item_with_metadata_len) {
+            erased_items.push(self.erase_oldest_item(buffer));
         }
-        self.write_new_item(item_with_metadata, new_write_offset);
+        self.write_new_item(buffer, item_with_metadata, new_write_offset);
+        Ok(erased_items)
+    }
+
+    /// Empties the buffer and sets its [capacity] to the length of [buffer], then appends the
+    /// newest of the given items that fit in it, oldest first.
+    ///
+    /// Returns the items that didn't fit, oldest first.
+    ///
+    /// ### Arguments
+    ///
+    /// * buffer: the underlying bytebuffer, of the new capacity.
+    /// * items: the items to be appended, oldest first.
+    fn reset(&mut self, buffer: &mut [u8], mut items: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>> {
+        buffer.fill(0);
+        self.read_offset = 0;
+        self.write_offset = 0;
+        self.items_count = 0;
+        self.capacity = buffer.len() as u16;
+        let mut available_space = self.capacity as usize;
+        let fitting_items_count = items
+            .iter()
+            .rev()
+            .take_while(|item| {
+                let item_size = ITEM_METADATA_OVERHEAD as usize + item.len();
+                if item_size > available_space {
+                    return false;
+                }
+                available_space -= item_size;
+                true
+            })
+            .count();
+        let fitting_items = items.split_off(items.len() - fitting_items_count);
+        for item in fitting_items {
+            self.append(buffer, item)?;
+        }
+        Ok(items)
     }
 
     /// Returns a number by modulo of buffer length.
@@ -490,8 +3408,8 @@ impl CyclicByteBuffer {
     /// ### Arguments
     ///
     /// * value: an offset/position to be re-calculated.
-    fn mod_(&mut self, value: u16) -> u16 {
-        value % MESSAGE_BUFFER_LENGTH as u16
+    fn mod_(&self, value: u16) -> u16 {
+        value % self.capacity
     }
 
     /// Returns true if there's no free space to append item of size [item_size] to buffer.
@@ -501,48 +3419,68 @@ impl CyclicByteBuffer {
     /// ### Arguments
     ///
     /// * item_size: a size of an item that is added to buffer.
-    fn no_space_available_for(&mut self, item_size: u16) -> bool {
+    fn no_space_available_for(&self, item_size: u16) -> bool {
         self.get_available_space() < item_size
     }
 
     /// Returns the amount of available free space.
-    fn get_available_space(&mut self) -> u16 {
+    fn get_available_space(&self) -> u16 {
         if self.items_count == 0 {
-            return MESSAGE_BUFFER_LENGTH as u16;
+            return self.capacity;
         }
-        self.mod_(MESSAGE_BUFFER_LENGTH as u16 + self.read_offset - self.write_offset)
+        self.mod_(self.capacity + self.read_offset - self.write_offset)
     }
 
     /// Erases the oldest item from buffer by zeroing and recalculating [read_offset] and [items_count].
-    fn erase_oldest_item(&mut self) {
-        let item_size = ITEM_METADATA_OVERHEAD + self.read_item_size();
+    /// Returns the erased item.
+    fn erase_oldest_item(&mut self, buffer: &mut [u8]) -> Vec<u8> {
+        let item = self.read(
+            buffer,
+            self.read_item_size(buffer),
+            self.mod_(self.read_offset + ITEM_METADATA_OVERHEAD),
+        );
+        let item_size = ITEM_METADATA_OVERHEAD + item.len() as u16;
         let zeros = &mut vec![0u8; item_size as usize];
-        self.write(zeros, self.read_offset);
+        self.write(buffer, zeros, self.read_offset);
         self.read_offset = self.mod_(self.read_offset + item_size);
         self.items_count -= 1;
+        item
+    }
+
+    /// Returns the oldest item, if any, without erasing it.
+    fn oldest_item(&self, buffer: &[u8]) -> Option<Vec<u8>> {
+        if self.items_count == 0 {
+            return None;
+        }
+        Some(self.read(
+            buffer,
+            self.read_item_size(buffer),
+            self.mod_(self.read_offset + ITEM_METADATA_OVERHEAD),
+        ))
     }
 
     /// Returns the size of the item that is present in buffer at [read_offset] position.
-    fn read_item_size(&mut self) -> u16 {
+    fn read_item_size(&self, buffer: &[u8]) -> u16 {
         let read_offset = self.read_offset;
-        let tail_size = MESSAGE_BUFFER_LENGTH as u16 - read_offset;
+        let tail_size = self.capacity - read_offset;
         if tail_size >= ITEM_METADATA_OVERHEAD {
             return u16::from_be_bytes([
-                self.buffer[read_offset as usize],
-                self.buffer[read_offset as usize + 1],
+                buffer[read_offset as usize],
+                buffer[read_offset as usize + 1],
             ]);
         }
-        u16::from_be_bytes([self.buffer[read_offset as usize], self.buffer[0]])
+        u16::from_be_bytes([buffer[read_offset as usize], buffer[0]])
     }
 
     /// Performs writing of [item] to buffer at [write_offset] position.
     ///
     /// ### Arguments
     ///
+    /// * buffer: the underlying bytebuffer.
     /// * item: an bytebuffer/bytearray to be written at [write_offset] position.
     /// * new_write_offset: a new [write_offset] to be set after writing.
-    fn write_new_item(&mut self, item: &mut Vec<u8>, new_write_offset: u16) {
-        self.write(item, self.write_offset);
+    fn write_new_item(&mut self, buffer: &mut [u8], item: &mut Vec<u8>, new_write_offset: u16) {
+        self.write(buffer, item, self.write_offset);
         self.write_offset = new_write_offset;
         self.items_count += 1;
     }
@@ -553,18 +3491,51 @@ impl CyclicByteBuffer {
     ///
     /// ### Arguments
     ///
+    /// * buffer: the underlying bytebuffer.
     /// * item: an bytebuffer/bytearray to be written at [offset] position.
     /// * offset: an [offset] where to write item.
-    fn write(&mut self, item: &mut Vec<u8>, offset: u16) {
+    fn write(&self, buffer: &mut [u8], item: &mut Vec<u8>, offset: u16) {
         for (idx, e) in item.iter().enumerate() {
             let pos = self.mod_(offset + idx as u16);
-            self.buffer[pos as usize] = *e;
+            buffer[pos as usize] = *e;
+        }
+    }
+
+    /// Returns the items present in buffer, starting from the oldest item at [read_offset] position.
+    fn items(&self, buffer: &[u8]) -> Vec<Vec<u8>> {
+        self.items_with_offsets(buffer)
+            .into_iter()
+            .map(|(_, item)| item)
+            .collect()
+    }
+
+    /// Returns the items present in buffer along with their offsets, starting from the oldest item
+    /// at [read_offset] position. An item can be overwritten in place by writing an item of the
+    /// same size at its offset.
+    fn items_with_offsets(&self, buffer: &[u8]) -> Vec<(u16, Vec<u8>)> {
+        let mut items = Vec::with_capacity(self.items_count as usize);
+        let mut offset = self.read_offset;
+        for _ in 0..self.items_count {
+            let item_size_bytes = self.read(buffer, ITEM_METADATA_OVERHEAD, offset);
+            let item_size = u16::from_be_bytes([item_size_bytes[0], item_size_bytes[1]]);
+            let item_offset = self.mod_(offset + ITEM_METADATA_OVERHEAD);
+            items.push((item_offset, self.read(buffer, item_size, item_offset)));
+            offset = self.mod_(offset + ITEM_METADATA_OVERHEAD + item_size);
         }
+        items
     }
 
-    /// Returns an underlying [buffer] that contains all items.
-    fn _raw(&mut self) -> [u8; MESSAGE_BUFFER_LENGTH] {
-        self.buffer
+    /// Performs reading of [size] bytes from buffer at [offset] position.
+    ///
+    /// ### Arguments
+    ///
+    /// * buffer: the underlying bytebuffer.
+    /// * size: a number of bytes to be read.
+    /// * offset: an [offset] where to start reading.
+    fn read(&self, buffer: &[u8], size: u16, offset: u16) -> Vec<u8> {
+        (0..size)
+            .map(|idx| buffer[self.mod_(offset + idx) as usize])
+            .collect()
     }
 }
 
@@ -577,69 +3548,427 @@ impl CyclicByteBuffer {
 #[derive(Default)]
 // space = 33
 pub struct Subscription {
-    /// Address of dialect account subscribed to.
+    /// Address of dialect or broadcast channel account subscribed to.
     pub pubkey: Pubkey, // 32
     /// A switcher to enable/disable subscription.
     pub enabled: bool, // 1
 }
 
-/// User who can exchange messages using a dialect account.
+/// A policy that decides whether other users can subscribe a user to dialects directly. Subscriptions
+/// that are not allowed directly are stored as invites, which the user can accept or reject.
+///
+/// A user can always subscribe themselves to any dialect, and only members of a dialect can invite
+/// users to it.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionPolicy {
+    /// Others can subscribe the user to dialects the user is a member of. This is the default, for
+    /// users without a metadata policy account.
+    MembersOnly,
+    /// Others can subscribe the user to any dialect.
+    Open,
+    /// Others can only invite the user to dialects.
+    OwnerApproval,
+}
+
+impl SubscriptionPolicy {
+    /// Returns true if the signer can subscribe the user to the dialect directly, rather than
+    /// only inviting them.
+    ///
+    /// ### Arguments
+    ///
+    /// * user: The user being subscribed.
+    /// * signer: The user taking action to subscribe the user.
+    /// * members: The current members of the dialect the user is being subscribed to.
+    fn allows_subscription_by(&self, user: &Pubkey, signer: &Pubkey, members: &[Member]) -> bool {
+        if signer == user {
+            return true;
+        }
+        match self {
+            SubscriptionPolicy::Open => true,
+            SubscriptionPolicy::MembersOnly => members.iter().any(|m| m.public_key == *user),
+            SubscriptionPolicy::OwnerApproval => false,
+        }
+    }
+}
+
+/// The content type of a message's text, stored in the message envelope. The texts of known
+/// content types are validated when they are sent, unless the dialect is encrypted.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    /// UTF-8 plain text. This is the default.
+    TextPlain,
+    /// UTF-8 Markdown.
+    Markdown,
+    /// UTF-8 JSON object or array. Only the encoding & the structure are validated, see
+    /// has_valid_json_structure.
+    Json,
+    /// A reference to an attachment stored off-chain: the SHA-256 hash of the attachment,
+    /// followed by its UTF-8 URI.
+    AttachmentReference,
+}
+
+impl ContentType {
+    /// Returns whether a text is valid for the content type.
+    ///
+    /// Arguments
+    ///
+    /// * text: The text, encoded in u8.
+    fn is_valid(&self, text: &[u8]) -> bool {
+        match self {
+            ContentType::TextPlain | ContentType::Markdown => std::str::from_utf8(text).is_ok(),
+            ContentType::Json => match std::str::from_utf8(text) {
+                Ok(text) => has_valid_json_structure(text),
+                Err(_) => false,
+            },
+            ContentType::AttachmentReference => {
+                text.len() > 32 && std::str::from_utf8(&text[32..]).is_ok()
+            }
+        }
+    }
+}
+
+/// User who can exchange messages using a dialect account, stored in one of the dialect's member
+/// slots. See DialectAccount.
 #[zero_copy]
+// Member slots are stored at unaligned offsets of the dialect account's data.
+#[repr(C, packed)]
 #[derive(Default)]
-// space = 34
+// space = 43
 pub struct Member {
     /// User public key.
     pub public_key: Pubkey, // 32
     /// Flags that are used to support basic RBAC authorization to dialect account.
     /// - When ```scopes[0]``` is set to true, the user is granted admin role.
     /// - When ```scopes[1]``` is set to true, the user is granted writer role.
-    /// ```
+    /// ```text
     /// // Examples
     /// scopes: [true, true] // allows to administer account + read messages + write messages
     /// scopes: [false, true] // allows to read messages + write messages
     /// scopes: [false, false] // allows to read messages
     /// ```
     pub scopes: [bool; 2], // 2
+    /// Whether the user was removed from the dialect. Removed members have no scopes, but their
+    /// slot is retained so that the messages they sent are still attributed to them.
+    pub removed: bool, // 1
+    /// The read pointer of the member: the messages with an id lower than read_until were read by
+    /// the member, see mark_read. It starts at the dialect's next message id when the member is
+    /// added, and moves past the messages & edits the member sends. The member's unread messages
+    /// count is DialectAccount.next_message_id - read_until, in which edits are counted, since they
+    /// have message ids too.
+    pub read_until: u64, // 8
+}
+
+// Member slots are cast from the dialect account's data, see load_dialect_mut.
+unsafe impl bytemuck::Pod for Member {}
+unsafe impl bytemuck::Zeroable for Member {}
+
+impl Member {
+    fn is_admin(&self) -> bool {
+        return self.scopes[0];
+    }
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("The dialect owner must be a member with admin privileges")]
+    DialectOwnerIsNotAdmin,
+    #[msg("A dialect must have between 2 and its max number of members")]
+    InvalidNumberOfMembers,
+    #[msg("The number of member scopes must match the number of members")]
+    MemberScopesMismatch,
+    #[msg("The dialect members must be unique and sorted alphabetically")]
+    MembersNotSorted,
+    #[msg("The members hash does not match the dialect members")]
+    MembersHashMismatch,
+    #[msg("The user is not subscribed to the dialect")]
+    SubscriptionNotFound,
+    #[msg("No subscription slots left, use a metadata page for additional subscriptions")]
+    SubscriptionsLimitReached,
+    #[msg(
+        "The user's subscription policy does not allow the signer to subscribe them to the dialect"
+    )]
+    SubscriptionNotAllowed,
+    #[msg("The user has no invite to the dialect")]
+    InviteNotFound,
+    #[msg("No invite slots left, the user must accept or reject pending invites first")]
+    InvitesLimitReached,
+    #[msg("The user is already a member of the dialect")]
+    MemberAlreadyExists,
+    #[msg("The user is not a member of the dialect")]
+    MemberNotFound,
+    #[msg("No member slots left in the dialect")]
+    DialectMembersLimitReached,
+    #[msg("The last admin of a dialect cannot be removed or demoted")]
+    LastAdminCannotBeRemoved,
+    #[msg("The account is not the recorded owner of the dialect")]
+    NotDialectOwner,
+    #[msg("The sender is not a member of the dialect")]
+    SenderNotMember,
+    #[msg("The message is larger than the dialect's max message length")]
+    MessageTooLarge,
+    #[msg("The clock sysvar is unavailable")]
+    ClockUnavailable,
+    #[msg("The max message length must be between 1 and the length of the largest message that fits in the message buffer")]
+    InvalidMaxMessageLength,
+    #[msg("The message buffer capacity is out of bounds")]
+    InvalidMessageBufferCapacity,
+    #[msg("The dialect's last message history pages are required to archive evicted messages")]
+    MessageHistoryPageRequired,
+    #[msg("The message history page is full, the next page must be created")]
+    MessageHistoryPageFull,
+    #[msg("The message history page index must be the number of pages of the dialect")]
+    InvalidMessageHistoryPageIndex,
+    #[msg("The message was not found in the dialect's message buffer")]
+    MessageNotFound,
+    #[msg("The signer is not the sender of the message")]
+    NotMessageSender,
+    #[msg("The edit window of the message has expired")]
+    EditWindowExpired,
+    #[msg("Edits cannot be edited, edit the original message instead")]
+    MessageNotEditable,
+    #[msg("The message's text is not valid for its content type")]
+    InvalidMessageContent,
+    #[msg("Edits & expiring messages cannot be pinned")]
+    MessageNotPinnable,
+    #[msg("The message is longer than PINNED_MESSAGE_LENGTH")]
+    PinnedMessageTooLarge,
+    #[msg("The dialect has no pinned message")]
+    NoPinnedMessage,
+    #[msg("The message has expired")]
+    MessageExpired,
+    #[msg("Topic ids must be lower than MAX_TOPICS")]
+    InvalidTopic,
+    #[msg("The dialect account has the legacy layout, it must be migrated first")]
+    LegacyDialectAccount,
+    #[msg("The dialect account does not have the legacy layout")]
+    DialectAlreadyMigrated,
+    #[msg("The max number of members must be at least 2 and the number of member slots")]
+    InvalidMaxMembers,
+    #[msg("The user's existing metadata pages must be passed in index order")]
+    MetadataPagesRequired,
+    #[msg("The user is already subscribed to the dialect")]
+    AlreadySubscribed,
+    #[msg("Only members of the dialect can invite users to it")]
+    InviterNotMember,
+    #[msg("The user is already invited to the dialect")]
+    AlreadyInvited,
+    #[msg("The time-to-live of a message must be positive")]
+    InvalidTtl,
+}
+
+/// An event that is fired new dialect account is created.
+#[event]
+pub struct DialectCreatedEvent {
+    /// Address of newly created dialect account.
+    pub dialect: Pubkey,
+    /// A list of dialect members: the users who exchange messages using single dialect account.
+    pub members: Vec<Pubkey>,
+}
+
+// Events
+
+/// An event that is fired when some user sends message to dialect account.
+#[event]
+pub struct DialectDeletedEvent {
+    /// Address of deleted dialect account.
+    pub dialect: Pubkey,
+    /// A list of dialect members: the users who exchange messages using single dialect account.
+    pub members: Vec<Pubkey>,
+}
+
+/// An event that is fired when the ownership of dialect account is transferred.
+#[event]
+pub struct DialectOwnershipTransferredEvent {
+    /// Address of dialect account whose ownership was transferred.
+    pub dialect: Pubkey,
+    /// Owner before the transfer.
+    pub previous_owner: Pubkey,
+    /// Owner after the transfer.
+    pub new_owner: Pubkey,
+}
+
+/// An event that is fired when the message buffer of dialect account is resized.
+#[event]
+pub struct DialectResizedEvent {
+    /// Address of dialect account that was resized.
+    pub dialect: Pubkey,
+    /// New capacity of the message buffer.
+    pub capacity: u16,
+}
+
+/// An event that is fired when a member is added to dialect account.
+#[event]
+pub struct MemberAddedEvent {
+    /// Address of dialect account to which member was added.
+    pub dialect: Pubkey,
+    /// User that was added.
+    pub member: Pubkey,
+    /// Scopes of the added member.
+    pub scopes: [bool; 2],
+}
+
+/// An event that is fired when a member is removed from dialect account.
+#[event]
+pub struct MemberRemovedEvent {
+    /// Address of dialect account from which member was removed.
+    pub dialect: Pubkey,
+    /// User that was removed.
+    pub member: Pubkey,
+}
+
+/// An event that is fired when the scopes of a dialect member are updated.
+#[event]
+pub struct MemberScopesUpdatedEvent {
+    /// Address of dialect account of the member.
+    pub dialect: Pubkey,
+    /// User whose scopes were updated.
+    pub member: Pubkey,
+    /// New scopes of the member.
+    pub scopes: [bool; 2],
+}
+
+#[event]
+pub struct MessageSentEvent {
+    /// Address of dialect account where messaging happens.
+    pub dialect: Pubkey,
+    /// User that sent a message.
+    pub sender: Pubkey,
+    /// The id of the message, see DialectAccount.next_message_id.
+    pub id: u64,
+    /// The time the message was sent, in UTC seconds.
+    pub timestamp: u32,
+    /// The length of the message's payload, i.e. its serialized text, in bytes.
+    pub length: u16,
+    /// The SHA-256 hash of the message's payload.
+    pub payload_hash: [u8; 32],
+    /// The message's payload, only if the dialect emits message payloads, see set_emit_message_payloads.
+    pub payload: Option<Vec<u8>>,
+    /// The id of the message replied to, if the message is a reply.
+    pub reply_to: Option<u64>,
+    /// The content type of the message's payload, see ContentType.
+    pub content_type: u8,
+    /// The time-to-live of the message in seconds, if it expires.
+    pub ttl: Option<u32>,
+}
+
+/// An event that is fired when a message is edited in a dialect.
+#[event]
+pub struct MessageEditedEvent {
+    /// Address of dialect account where the message was edited.
+    pub dialect: Pubkey,
+    /// The sender of the message, who edited it.
+    pub sender: Pubkey,
+    /// The id of the edit, which is appended as a new message.
+    pub id: u64,
+    /// The id of the edited message.
+    pub edited_message_id: u64,
+}
+
+/// An event that is fired when a member reacts to a message in a dialect.
+#[event]
+pub struct MessageReactedEvent {
+    /// Address of dialect account where the message was reacted to.
+    pub dialect: Pubkey,
+    /// The member who reacted.
+    pub sender: Pubkey,
+    /// The id of the message reacted to.
+    pub message_id: u64,
+    /// The reaction code, e.g. an emoji's Unicode code point.
+    pub reaction: u32,
 }
 
-impl Member {
-    fn is_admin(&self) -> bool {
-        return self.scopes[0];
-    }
+/// An event that is fired when a member marks messages as read in a dialect.
+#[event]
+pub struct MessagesReadEvent {
+    /// Address of dialect account where the messages were read.
+    pub dialect: Pubkey,
+    /// The member who read the messages.
+    pub member: Pubkey,
+    /// The member's read pointer, see Member.read_until.
+    pub read_until: u64,
 }
 
-#[error_code]
-pub enum ErrorCode {
-    #[msg("The dialect owner must be a member with admin privileges")]
-    DialectOwnerIsNotAdmin,
+/// An event that is fired when a message is deleted from a dialect.
+#[event]
+pub struct MessageDeletedEvent {
+    /// Address of dialect account where the message was deleted.
+    pub dialect: Pubkey,
+    /// The id of the deleted message.
+    pub id: u64,
+    /// The message's sender or the admin who deleted the message.
+    pub deleted_by: Pubkey,
 }
 
-/// An event that is fired new dialect account is created.
+/// An event that is fired when expired messages are pruned from a dialect.
 #[event]
-pub struct DialectCreatedEvent {
-    /// Address of newly created dialect account.
+pub struct MessagesPrunedEvent {
+    /// Address of dialect account where the messages were pruned.
     pub dialect: Pubkey,
-    /// A list of dialect members: two users who exchange messages using single dialect account.
-    pub members: [Pubkey; 2],
+    /// The number of pruned items, i.e. expired messages & the reactions to them.
+    pub pruned_items_count: u16,
 }
 
-// Events
-
-/// An event that is fired when some user sends message to dialect account.
+/// An event that is fired when a message is pinned in a dialect.
 #[event]
-pub struct DialectDeletedEvent {
-    /// Address of deleted dialect account.
+pub struct MessagePinnedEvent {
+    /// Address of dialect account where the message was pinned.
     pub dialect: Pubkey,
-    /// A list of dialect members: two users who exchange messages using single dialect account.
-    pub members: [Pubkey; 2],
+    /// The id of the pinned message.
+    pub message_id: u64,
+    /// The admin who pinned the message.
+    pub pinned_by: Pubkey,
 }
 
+/// An event that is fired when a dialect's pinned message is unpinned.
 #[event]
-pub struct MessageSentEvent {
-    /// Address of dialect account where messaging happens.
+pub struct MessageUnpinnedEvent {
+    /// Address of dialect account where the message was unpinned.
     pub dialect: Pubkey,
-    /// User that sent a message.
-    pub sender: Pubkey,
+    /// The id of the unpinned message.
+    pub message_id: u64,
+    /// The admin who unpinned the message.
+    pub unpinned_by: Pubkey,
+}
+
+/// An event that is fired when a broadcast channel is created.
+#[event]
+pub struct BroadcastChannelCreatedEvent {
+    /// Address of newly created broadcast channel account.
+    pub channel: Pubkey,
+    /// The channel's publisher.
+    pub publisher: Pubkey,
+}
+
+/// An event that is fired when a broadcast channel is closed.
+#[event]
+pub struct BroadcastChannelDeletedEvent {
+    /// Address of closed broadcast channel account.
+    pub channel: Pubkey,
+    /// The channel's publisher.
+    pub publisher: Pubkey,
+}
+
+/// An event that is fired when a message is broadcast in a broadcast channel, see MessageSentEvent.
+#[event]
+pub struct BroadcastMessageSentEvent {
+    /// Address of broadcast channel account where the message was broadcast.
+    pub channel: Pubkey,
+    /// The channel's publisher.
+    pub publisher: Pubkey,
+    /// The id of the message, see BroadcastChannel.next_message_id.
+    pub id: u64,
+    /// The time the message was broadcast, in UTC seconds.
+    pub timestamp: u32,
+    /// The length of the message's payload, i.e. its serialized text, in bytes.
+    pub length: u16,
+    /// The SHA-256 hash of the message's payload.
+    pub payload_hash: [u8; 32],
+    /// The message's payload. Broadcast messages are public, so it is always emitted.
+    pub payload: Vec<u8>,
+    /// The content type of the message's payload, see ContentType.
+    pub content_type: u8,
+    /// The id of the message's topic, if any, see SubscriptionTopics.includes_topic.
+    pub topic: Option<u8>,
 }
 
 /// An event that is fired when the metadata account owner is subscribed to dialect.
@@ -651,6 +3980,57 @@ pub struct UserSubscribedEvent {
     pub dialect: Pubkey,
 }
 
+/// An event that is fired when the metadata account owner is unsubscribed from dialect.
+#[event]
+pub struct UserUnsubscribedEvent {
+    /// Address of owner metadata account, from which subscription to dialect was removed.
+    pub metadata: Pubkey,
+    /// Address of dialect account from which user was unsubscribed.
+    pub dialect: Pubkey,
+}
+
+/// An event that is fired when the metadata account owner enables or disables a subscription to dialect.
+#[event]
+pub struct SubscriptionEnabledUpdatedEvent {
+    /// Address of owner metadata account, where subscription to dialect is stored.
+    pub metadata: Pubkey,
+    /// Address of dialect account the subscription refers to.
+    pub dialect: Pubkey,
+    /// Whether the subscription is now enabled.
+    pub enabled: bool,
+}
+
+/// An event that is fired when a user sets the topics of their subscription to a broadcast channel.
+#[event]
+pub struct SubscriptionTopicsUpdatedEvent {
+    /// Address of the user's subscription topics account, where the topics are stored.
+    pub subscription_topics: Pubkey,
+    /// Address of broadcast channel account the subscription refers to.
+    pub channel: Pubkey,
+    /// The topics subscribed to, as a bitmask, see SubscriptionTopics.topics.
+    pub topics: u64,
+}
+
+/// An event that is fired when the metadata account owner is invited to dialect by another user.
+#[event]
+pub struct UserInvitedEvent {
+    /// Address of owner metadata policy account, where invite to dialect is stored.
+    pub metadata_policy: Pubkey,
+    /// Address of dialect account to which user was invited.
+    pub dialect: Pubkey,
+    /// User that sent the invite.
+    pub inviter: Pubkey,
+}
+
+/// An event that is fired when the metadata account owner rejects an invite to dialect.
+#[event]
+pub struct InviteRejectedEvent {
+    /// Address of owner metadata policy account, from which invite to dialect was removed.
+    pub metadata_policy: Pubkey,
+    /// Address of dialect account to which user was invited.
+    pub dialect: Pubkey,
+}
+
 /// An event that is fired when new metadata account is created.
 #[event]
 pub struct MetadataCreatedEvent {
@@ -682,6 +4062,744 @@ pub fn is_present(subscription: &Subscription) -> bool {
     subscription.pubkey != Pubkey::default()
 }
 
+/// This function adds a subscription to a dialect in the first free slot of a list of subscriptions.
+/// Present subscriptions are kept contiguous, so the first free slot is right after them.
+///
+/// ### Arguments
+///
+/// * subscriptions: the subscriptions of a metadata account or page.
+/// * dialect: the address of the dialect being subscribed to.
+fn add_subscription(subscriptions: &mut [Subscription], dialect: Pubkey) -> Result<()> {
+    let num_subscriptions = subscriptions.iter().filter(|s| is_present(s)).count();
+    if num_subscriptions >= subscriptions.len() {
+        return err!(ErrorCode::SubscriptionsLimitReached);
+    }
+    subscriptions[num_subscriptions] = Subscription {
+        pubkey: dialect,
+        enabled: true,
+    };
+    Ok(())
+}
+
+/// This function removes the subscription to a dialect from a list of subscriptions, shifting the
+/// following subscriptions left to keep present subscriptions contiguous.
+///
+/// ### Arguments
+///
+/// * subscriptions: the subscriptions of a metadata account or page.
+/// * dialect: the address of the dialect being unsubscribed from.
+fn remove_subscription(subscriptions: &mut [Subscription], dialect: &Pubkey) -> Result<()> {
+    let subscription_idx = match subscriptions.iter().position(|s| s.pubkey == *dialect) {
+        Some(idx) => idx,
+        None => return err!(ErrorCode::SubscriptionNotFound),
+    };
+    let last_idx = subscriptions.len() - 1;
+    subscriptions.copy_within(subscription_idx + 1.., subscription_idx);
+    subscriptions[last_idx] = Subscription::default();
+    Ok(())
+}
+
+/// This function finds the subscription to a dialect in a list of subscriptions.
+///
+/// ### Arguments
+///
+/// * subscriptions: the subscriptions of a metadata account or page.
+/// * dialect: the address of the dialect subscribed to.
+fn find_subscription<'a>(
+    subscriptions: &'a mut [Subscription],
+    dialect: &Pubkey,
+) -> Result<&'a mut Subscription> {
+    match subscriptions.iter_mut().find(|s| s.pubkey == *dialect) {
+        Some(subscription) => Ok(subscription),
+        None => err!(ErrorCode::SubscriptionNotFound),
+    }
+}
+
+/// This function returns the bitmap of a user's existing metadata pages, stored in their metadata
+/// account's data after the MetadataAccount struct. Users who never created a metadata page have
+/// no bitmap, and no pages.
+///
+/// ### Arguments
+///
+/// * metadata: the user's metadata account.
+fn load_metadata_pages(metadata: &AccountInfo) -> Result<[u8; METADATA_PAGES_LENGTH]> {
+    let data = metadata.try_borrow_data()?;
+    let mut pages = [0; METADATA_PAGES_LENGTH];
+    if let Some(bitmap) =
+        data.get(METADATA_ACCOUNT_SIZE..METADATA_ACCOUNT_SIZE + METADATA_PAGES_LENGTH)
+    {
+        pages.copy_from_slice(bitmap);
+    }
+    Ok(pages)
+}
+
+/// This function records whether one of a user's metadata pages exists in the bitmap of their
+/// metadata account. The bitmap is added to the account before its first page is recorded, see
+/// create_metadata_page.
+///
+/// ### Arguments
+///
+/// * metadata: the user's metadata account.
+/// * page_index: the index of the metadata page.
+/// * exists: whether the page exists.
+fn set_metadata_page_exists(metadata: &AccountInfo, page_index: u8, exists: bool) -> Result<()> {
+    let mut data = metadata.try_borrow_mut_data()?;
+    let byte_idx = METADATA_ACCOUNT_SIZE + page_index as usize / 8;
+    if let Some(byte) = data.get_mut(byte_idx) {
+        let bit = 1 << (page_index % 8);
+        if exists {
+            *byte |= bit;
+        } else {
+            *byte &= !bit;
+        }
+    }
+    Ok(())
+}
+
+/// This function checks that a user isn't subscribed to a dialect in any of their metadata pages.
+/// The user's existing pages, as recorded in the bitmap of their metadata account, must be the
+/// remaining accounts, in index order. Pages closed by the user leave gaps between indexes, which
+/// are skipped.
+///
+/// ### Arguments
+///
+/// * user: the address of the user.
+/// * metadata: the user's metadata account.
+/// * dialect: the address of the dialect being subscribed to.
+/// * remaining_accounts: the instruction's remaining accounts.
+fn check_not_subscribed_in_pages(
+    user: &Pubkey,
+    metadata: &AccountInfo,
+    dialect: &Pubkey,
+    remaining_accounts: &[AccountInfo],
+) -> Result<()> {
+    let pages = load_metadata_pages(metadata)?;
+    let mut page_infos = remaining_accounts.iter();
+    for page_index in 0..=u8::MAX {
+        if pages[page_index as usize / 8] & (1 << (page_index % 8)) == 0 {
+            continue;
+        }
+        let page_info = match page_infos.next() {
+            Some(page_info) => page_info,
+            None => return err!(ErrorCode::MetadataPagesRequired),
+        };
+        // Pages are only created at their PDA, so the user & index stored in a page identify it.
+        let page_loader = AccountLoader::<MetadataPage>::try_from(page_info)?;
+        let page = page_loader.load()?;
+        if page.user != *user || page.page_index != page_index {
+            return err!(ErrorCode::MetadataPagesRequired);
+        }
+        if page.subscriptions.iter().any(|s| s.pubkey == *dialect) {
+            return err!(ErrorCode::AlreadySubscribed);
+        }
+    }
+    Ok(())
+}
+
+/// This function returns a user's subscription policy, stored in their metadata policy account.
+/// Users without a metadata policy account have the MembersOnly policy.
+///
+/// ### Arguments
+///
+/// * metadata_policy: the user's metadata policy account, which may not exist.
+fn load_subscription_policy(metadata_policy: &AccountInfo) -> Result<SubscriptionPolicy> {
+    if metadata_policy.data_is_empty() {
+        return Ok(SubscriptionPolicy::MembersOnly);
+    }
+    let metadata_policy_loader = AccountLoader::<MetadataPolicyAccount>::try_from(metadata_policy)?;
+    let subscription_policy = metadata_policy_loader.load()?.subscription_policy();
+    Ok(subscription_policy)
+}
+
+/// This function removes the invite to a dialect from a user's metadata policy account, if the
+/// user has one & is invited to the dialect, once the user is subscribed to the dialect directly.
+///
+/// ### Arguments
+///
+/// * metadata_policy: the user's metadata policy account, which may not exist.
+/// * dialect: the address of the dialect the user is subscribed to.
+fn consume_invite(metadata_policy: &AccountInfo, dialect: &Pubkey) -> Result<()> {
+    if metadata_policy.data_is_empty() {
+        return Ok(());
+    }
+    let metadata_policy_loader = AccountLoader::<MetadataPolicyAccount>::try_from(metadata_policy)?;
+    let mut metadata_policy = metadata_policy_loader.load_mut()?;
+    if metadata_policy.invites.contains(dialect) {
+        remove_invite(&mut metadata_policy.invites, dialect)?;
+    }
+    Ok(())
+}
+
+/// This function adds an invite to a dialect in the first free slot of a list of invites.
+///
+/// ### Arguments
+///
+/// * invites: the invites of a metadata policy account.
+/// * dialect: the address of the dialect the user is invited to.
+fn add_invite(invites: &mut [Pubkey], dialect: Pubkey) -> Result<()> {
+    match invites.iter_mut().find(|i| **i == Pubkey::default()) {
+        Some(invite) => *invite = dialect,
+        None => return err!(ErrorCode::InvitesLimitReached),
+    }
+    Ok(())
+}
+
+/// This function removes the invite to a dialect from a list of invites, shifting the following
+/// invites left to keep pending invites contiguous.
+///
+/// ### Arguments
+///
+/// * invites: the invites of a metadata policy account.
+/// * dialect: the address of the dialect the user is invited to.
+fn remove_invite(invites: &mut [Pubkey], dialect: &Pubkey) -> Result<()> {
+    let invite_idx = match invites.iter().position(|i| i == dialect) {
+        Some(idx) => idx,
+        None => return err!(ErrorCode::InviteNotFound),
+    };
+    let last_idx = invites.len() - 1;
+    invites.copy_within(invite_idx + 1.., invite_idx);
+    invites[last_idx] = Pubkey::default();
+    Ok(())
+}
+
+/// This function hashes a set of dialect members' public keys, which must already be sorted
+/// alphabetically. The hash is used to seed group dialect PDAs, since the number of seeds
+/// is limited.
+///
+/// ### Arguments
+///
+/// * members: the sorted public keys of the dialect members.
+pub fn hash_members(members: &[Pubkey]) -> [u8; 32] {
+    let seeds: Vec<&[u8]> = members.iter().map(|m| m.as_ref()).collect();
+    hashv(&seeds).to_bytes()
+}
+
+/// This function archives the messages evicted from a dialect's message buffer, if archival is
+/// enabled for the dialect, in its last message history pages. The remaining accounts must be
+/// consecutive pages of the dialect, ending with its last page. The messages are appended after
+/// the last of these pages holding messages, so that archived messages stay in order, and spill
+/// over to the following pages when it is full. Expiring messages & the reactions to them are
+/// never archived, so that they disappear.
+///
+/// ### Arguments
+///
+/// * dialect: the dialect account.
+/// * dialect_key: the dialect account's address.
+/// * remaining_accounts: the instruction's remaining accounts.
+/// * evicted_items: the evicted messages, oldest first.
+fn archive_messages(
+    dialect: &DialectAccount,
+    dialect_key: Pubkey,
+    remaining_accounts: &[AccountInfo],
+    evicted_items: Vec<Vec<u8>>,
+) -> Result<()> {
+    let evicted_items: Vec<Vec<u8>> = evicted_items
+        .into_iter()
+        .filter(|item| item[2] & MESSAGE_FLAG_TTL == 0)
+        .collect();
+    if !dialect.archive_enabled || evicted_items.is_empty() {
+        return Ok(());
+    }
+    let first_page_index =
+        match (dialect.history_pages_count as usize).checked_sub(remaining_accounts.len()) {
+            Some(first_page_index) if !remaining_accounts.is_empty() => first_page_index,
+            _ => return err!(ErrorCode::MessageHistoryPageRequired),
+        };
+    let mut history_page_loaders = Vec::with_capacity(remaining_accounts.len());
+    for (idx, history_page_info) in remaining_accounts.iter().enumerate() {
+        let history_page_loader = AccountLoader::<MessageHistoryPage>::try_from(history_page_info)?;
+        {
+            let history_page = history_page_loader.load()?;
+            if history_page.dialect != dialect_key
+                || history_page.page_index as usize != first_page_index + idx
+            {
+                return err!(ErrorCode::MessageHistoryPageRequired);
+            }
+        }
+        history_page_loaders.push(history_page_loader);
+    }
+    let mut page_idx = 0;
+    for (idx, history_page_loader) in history_page_loaders.iter().enumerate() {
+        if history_page_loader.load()?.items_count > 0 {
+            page_idx = idx;
+        }
+    }
+    for item in evicted_items {
+        let mut history_page = history_page_loaders[page_idx].load_mut()?;
+        if !history_page.has_room_for(&item) && page_idx + 1 < history_page_loaders.len() {
+            drop(history_page);
+            page_idx += 1;
+            history_page = history_page_loaders[page_idx].load_mut()?;
+        }
+        history_page.append(&item)?;
+    }
+    Ok(())
+}
+
+/// This function returns the offset of a message's envelope, after its header, the id of the
+/// message it references if it is an edit or a reply, its time-to-live if it expires, and its
+/// topic if it is a broadcast message with a topic.
+///
+/// ### Arguments
+///
+/// * message: the message, in the same format as in the dialect's message buffer.
+fn envelope_offset(message: &[u8]) -> usize {
+    let mut offset = MESSAGE_HEADER_LENGTH;
+    if message[2] & (MESSAGE_FLAG_EDIT | MESSAGE_FLAG_REPLY) != 0 {
+        offset += MESSAGE_REFERENCE_LENGTH;
+    }
+    if message[2] & MESSAGE_FLAG_TTL != 0 {
+        offset += MESSAGE_TTL_LENGTH;
+    }
+    if message[2] & MESSAGE_FLAG_TOPIC != 0 {
+        offset += MESSAGE_TOPIC_LENGTH;
+    }
+    offset as usize
+}
+
+/// This function returns the time-to-live of a message in seconds, if it expires.
+///
+/// ### Arguments
+///
+/// * message: the message, in the same format as in the dialect's message buffer.
+fn message_ttl(message: &[u8]) -> Option<u32> {
+    if message[2] & MESSAGE_FLAG_TTL == 0 {
+        return None;
+    }
+    let mut offset = MESSAGE_HEADER_LENGTH as usize;
+    if message[2] & (MESSAGE_FLAG_EDIT | MESSAGE_FLAG_REPLY) != 0 {
+        offset += MESSAGE_REFERENCE_LENGTH as usize;
+    }
+    Some(u32::from_be_bytes(
+        message[offset..][..4].try_into().unwrap(),
+    ))
+}
+
+/// This function checks the structure of a JSON text, without parsing its values: the text must be
+/// an object or an array, whose braces & brackets outside of strings are balanced.
+///
+/// ### Arguments
+///
+/// * text: the JSON text.
+fn has_valid_json_structure(text: &str) -> bool {
+    let text = text.trim_matches(|c| matches!(c, ' ' | '\t' | '\n' | '\r'));
+    if !text.starts_with('{') && !text.starts_with('[') {
+        return false;
+    }
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    // Multi-byte UTF-8 characters never contain ASCII bytes, so the text is scanned by byte.
+    for (idx, byte) in text.bytes().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' => closers.push(b'}'),
+            b'[' => closers.push(b']'),
+            b'}' | b']' => {
+                if closers.pop() != Some(byte) {
+                    return false;
+                }
+                // The top-level object or array must span the whole text.
+                if closers.is_empty() {
+                    return idx + 1 == text.len();
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// This function replaces a message by a tombstone of the same size, which keeps the message's
+/// sender, id, timestamp & time-to-live if any, so that it still expires. Tombstones of edits keep
+/// the id of the edited message, so that readers still fold them into it, while tombstones of
+/// replies drop the id of the message replied to.
+///
+/// ### Arguments
+///
+/// * message: the message, in the same format as in the dialect's message buffer.
+fn tombstone(message: &mut [u8]) {
+    let ttl = message_ttl(message);
+    let mut offset = MESSAGE_HEADER_LENGTH as usize;
+    if message[2] & MESSAGE_FLAG_EDIT != 0 {
+        offset += MESSAGE_REFERENCE_LENGTH as usize;
+    }
+    message[0] = ITEM_TYPE_TOMBSTONE;
+    message[2] &= MESSAGE_FLAG_EDIT | MESSAGE_FLAG_TTL;
+    message[offset..].fill(0);
+    if let Some(ttl) = ttl {
+        message[offset..][..4].copy_from_slice(&ttl.to_be_bytes());
+    }
+}
+
+/// This function initializes a new dialect account, with a member slot for each of its members.
+///
+/// ### Arguments
+///
+/// * dialect: the dialect account's loader, of a new account sized for its member slots.
+/// * owner: the owner of the dialect, who must be one of its members with admin privileges.
+/// * member_keys: the public keys of the dialect's members.
+/// * scopes: the scopes of the dialect's members, in the same order.
+/// * max_members: the maximum number of members of the dialect.
+/// * encrypted: whether the dialect is encrypted.
+/// * capacity: the capacity of the dialect's message buffer.
+fn init_dialect(
+    dialect: &AccountLoader<DialectAccount>,
+    owner: &Pubkey,
+    member_keys: &[Pubkey],
+    scopes: &[[bool; 2]],
+    max_members: u8,
+    encrypted: bool,
+    capacity: u16,
+) -> Result<()> {
+    if !member_keys
+        .iter()
+        .zip(scopes)
+        .any(|(public_key, scopes)| public_key == owner && scopes[0])
+    {
+        return err!(ErrorCode::DialectOwnerIsNotAdmin);
+    }
+    let now = Clock::get()?.unix_timestamp as u32;
+    {
+        let mut dialect = dialect.load_init()?;
+        dialect.owner = *owner;
+        dialect.version = DIALECT_ACCOUNT_VERSION;
+        dialect.max_members = max_members;
+        dialect.members_count = member_keys.len() as u8;
+        dialect.messages = CyclicByteBuffer {
+            read_offset: 0,
+            write_offset: 0,
+            items_count: 0,
+            capacity,
+        };
+        dialect.last_message_timestamp = now;
+        dialect.encrypted = encrypted;
+        dialect.max_message_length = dialect.max_message_length_limit();
+        dialect.edit_window = DEFAULT_EDIT_WINDOW;
+    }
+    let mut data = dialect.as_ref().try_borrow_mut_data()?;
+    let member_slots: &mut [Member] = bytemuck::cast_slice_mut(
+        &mut data[DIALECT_ACCOUNT_SIZE..][..member_keys.len() * MEMBER_SIZE],
+    );
+    for (idx, member_slot) in member_slots.iter_mut().enumerate() {
+        *member_slot = Member {
+            public_key: member_keys[idx],
+            scopes: scopes[idx],
+            removed: false,
+            read_until: 0,
+        };
+    }
+    Ok(())
+}
+
+/// This function returns the current members of a dialect, i.e. the members in its member slots
+/// who were not removed.
+///
+/// ### Arguments
+///
+/// * member_slots: the dialect's member slots.
+fn current_members(member_slots: &[Member]) -> impl Iterator<Item = &Member> {
+    member_slots.iter().filter(|m| !m.removed)
+}
+
+/// This function returns the index of the member slot of a current member of a dialect.
+///
+/// ### Arguments
+///
+/// * member_slots: the dialect's member slots.
+/// * public_key: the public key of the member.
+fn find_member_slot(member_slots: &[Member], public_key: &Pubkey) -> Result<usize> {
+    match member_slots
+        .iter()
+        .position(|m| m.public_key == *public_key && !m.removed)
+    {
+        Some(idx) => Ok(idx),
+        None => err!(ErrorCode::MemberNotFound),
+    }
+}
+
+/// This function returns the index of the member slot of a message's sender, who must be a
+/// current member of the dialect.
+///
+/// ### Arguments
+///
+/// * member_slots: the dialect's member slots.
+/// * sender: the public key of the sender of the message.
+fn find_sender_slot(member_slots: &[Member], sender: &Pubkey) -> Result<u8> {
+    match member_slots
+        .iter()
+        .position(|m| m.public_key == *sender && !m.removed)
+    {
+        Some(idx) => Ok(idx as u8),
+        None => err!(ErrorCode::SenderNotMember),
+    }
+}
+
+/// This function returns whether the member in the given slot is the only admin left in a dialect.
+///
+/// ### Arguments
+///
+/// * member_slots: the dialect's member slots.
+/// * slot_idx: the index of the member's slot.
+fn is_last_admin(member_slots: &[Member], slot_idx: usize) -> bool {
+    member_slots[slot_idx].is_admin()
+        && current_members(member_slots)
+            .filter(|m| m.is_admin())
+            .count()
+            == 1
+}
+
+/// This function returns the id of a dialect's pinned message, if any.
+///
+/// ### Arguments
+///
+/// * pinned_message: the copy of the dialect's pinned message, empty if no message is pinned.
+fn pinned_message_id(pinned_message: &[u8]) -> Option<u64> {
+    match pinned_message.is_empty() {
+        true => None,
+        false => Some(u64::from_be_bytes(
+            pinned_message[3..11].try_into().unwrap(),
+        )),
+    }
+}
+
+/// This function loads a dialect account for reading, failing if it has the legacy layout, see
+/// migrate_dialect.
+///
+/// ### Arguments
+///
+/// * dialect: the dialect account's loader.
+fn load_dialect<'a>(dialect: &'a AccountLoader<DialectAccount>) -> Result<Ref<'a, DialectAccount>> {
+    let dialect = dialect.load()?;
+    if dialect.version != DIALECT_ACCOUNT_VERSION {
+        return err!(ErrorCode::LegacyDialectAccount);
+    }
+    Ok(dialect)
+}
+
+/// This function returns the current members of a dialect, for the constraints of the
+/// instructions' contexts.
+///
+/// ### Arguments
+///
+/// * dialect: the dialect account's loader.
+fn dialect_members(dialect: &AccountLoader<DialectAccount>) -> Result<Vec<Member>> {
+    let members_count = load_dialect(dialect)?.members_count as usize;
+    let data = dialect.as_ref().try_borrow_data()?;
+    let member_slots: &[Member] =
+        bytemuck::cast_slice(&data[DIALECT_ACCOUNT_SIZE..][..members_count * MEMBER_SIZE]);
+    Ok(current_members(member_slots).copied().collect())
+}
+
+/// A dialect account loaded for reading or writing, see load_dialect_mut: the DialectAccount
+/// struct, its member slots, the copy of its pinned message & its message buffer.
+type DialectData<'a> = (
+    RefMut<'a, DialectAccount>,
+    RefMut<'a, [Member]>,
+    RefMut<'a, [u8]>,
+    RefMut<'a, [u8]>,
+);
+
+/// This function loads a dialect account for reading or writing, along with its member slots,
+/// the copy of its pinned message & its message buffer, which are stored in this order in the
+/// account's data after the DialectAccount struct. Fails if the account has the legacy layout, see
+/// migrate_dialect.
+///
+/// ### Arguments
+///
+/// * dialect: the dialect account's loader.
+fn load_dialect_mut<'a>(dialect: &'a AccountLoader<DialectAccount>) -> Result<DialectData<'a>> {
+    // Checks the account's discriminator & that it is writable.
+    let (members_count, pinned_message_length) = {
+        let dialect = dialect.load_mut()?;
+        if dialect.version != DIALECT_ACCOUNT_VERSION {
+            return err!(ErrorCode::LegacyDialectAccount);
+        }
+        (
+            dialect.members_count as usize,
+            dialect.pinned_message_length as usize,
+        )
+    };
+    let data = dialect.as_ref().try_borrow_mut_data()?;
+    let (dialect_data, data) =
+        RefMut::map_split(data, |data| data.split_at_mut(DIALECT_ACCOUNT_SIZE));
+    let (member_slots, data) =
+        RefMut::map_split(data, |data| data.split_at_mut(members_count * MEMBER_SIZE));
+    let (pinned_message, buffer) =
+        RefMut::map_split(data, |data| data.split_at_mut(pinned_message_length));
+    let dialect = RefMut::map(dialect_data, |data| {
+        bytemuck::from_bytes_mut(&mut data[8..])
+    });
+    let member_slots = RefMut::map(member_slots, |data| bytemuck::cast_slice_mut(data));
+    Ok((dialect, member_slots, pinned_message, buffer))
+}
+
+/// This function resizes a region of a dialect account's data, i.e. its member slots or the copy
+/// of its pinned message, moving the regions after it. The bytes added at the end of the region
+/// are zeroed. Rent must be topped up beforehand, see top_up_rent.
+///
+/// ### Arguments
+///
+/// * dialect: the dialect account.
+/// * original_len: the length of the account's data when the instruction started.
+/// * region_offset: the offset of the region in the account's data.
+/// * old_region_len: the current length of the region.
+/// * new_region_len: the new length of the region.
+fn resize_dialect_region(
+    dialect: &AccountInfo,
+    original_len: usize,
+    region_offset: usize,
+    old_region_len: usize,
+    new_region_len: usize,
+) -> Result<()> {
+    let old_len = dialect.data_len();
+    let new_len = old_len + new_region_len - old_region_len;
+    let region_end = region_offset + old_region_len;
+    if new_region_len > old_region_len {
+        realloc_account(dialect, original_len, new_len)?;
+        let mut data = dialect.try_borrow_mut_data()?;
+        let new_region_end = region_offset + new_region_len;
+        data.copy_within(region_end..old_len, new_region_end);
+        data[region_end..new_region_end].fill(0);
+    } else if new_region_len < old_region_len {
+        dialect
+            .try_borrow_mut_data()?
+            .copy_within(region_end..old_len, region_offset + new_region_len);
+        realloc_account(dialect, original_len, new_len)?;
+    }
+    Ok(())
+}
+
+/// This function replaces the copy of a dialect's pinned message, resizing the dialect's pinned
+/// region to fit it. Rent must be topped up beforehand, see top_up_rent.
+///
+/// ### Arguments
+///
+/// * dialect: the dialect account's loader.
+/// * original_len: the length of the account's data when the instruction started.
+/// * message: the message to pin, in the same format as in the message buffer, or an empty slice
+///   to unpin the pinned message.
+fn set_pinned_message(
+    dialect: &AccountLoader<DialectAccount>,
+    original_len: usize,
+    message: &[u8],
+) -> Result<()> {
+    if message.len() > PINNED_MESSAGE_LENGTH {
+        return err!(ErrorCode::PinnedMessageTooLarge);
+    }
+    let (pinned_message_offset, pinned_message_length) = {
+        let dialect = load_dialect(dialect)?;
+        (
+            dialect.pinned_message_offset(),
+            dialect.pinned_message_length as usize,
+        )
+    };
+    resize_dialect_region(
+        &dialect.to_account_info(),
+        original_len,
+        pinned_message_offset,
+        pinned_message_length,
+        message.len(),
+    )?;
+    dialect.load_mut()?.pinned_message_length = message.len() as u16;
+    let (_, _, mut pinned_message, _) = load_dialect_mut(dialect)?;
+    pinned_message.copy_from_slice(message);
+    Ok(())
+}
+
+/// This function transfers lamports from a payer to an account, so that it stays rent-exempt once
+/// resized. It must be called before the account is resized, while its data is not borrowed.
+///
+/// ### Arguments
+///
+/// * account: the account to be resized.
+/// * payer: the signer paying for the additional rent.
+/// * system_program: the system program.
+/// * rent: the rent sysvar.
+/// * new_len: the new length of the account's data.
+fn top_up_rent<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    rent: &Rent,
+    new_len: usize,
+) -> Result<()> {
+    let rent_exempt_lamports = rent.minimum_balance(new_len);
+    if rent_exempt_lamports > account.lamports() {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            rent_exempt_lamports - account.lamports(),
+        )?;
+    }
+    Ok(())
+}
+
+/// This function loads a broadcast channel account for reading or writing, along with its message
+/// buffer, which is stored in the account's data after the BroadcastChannel struct.
+///
+/// ### Arguments
+///
+/// * channel: the broadcast channel account's loader.
+fn load_broadcast_channel_mut<'a>(
+    channel: &'a AccountLoader<BroadcastChannel>,
+) -> Result<(RefMut<'a, BroadcastChannel>, RefMut<'a, [u8]>)> {
+    // Checks the account's discriminator & that it is writable.
+    drop(channel.load_mut()?);
+    let data = channel.as_ref().try_borrow_mut_data()?;
+    let (channel_data, buffer) = RefMut::map_split(data, |data| {
+        data.split_at_mut(BROADCAST_CHANNEL_ACCOUNT_SIZE)
+    });
+    let channel = RefMut::map(channel_data, |data| {
+        bytemuck::from_bytes_mut(&mut data[8..])
+    });
+    Ok((channel, buffer))
+}
+
+/// This function resizes an account's data to new_len bytes, zeroing the bytes added at its end.
+///
+/// It mirrors AccountInfo::realloc from later versions of solana-program: the runtime serializes
+/// the length of an account's data in the 8 bytes before it, and reserves
+/// MAX_PERMITTED_DATA_INCREASE bytes after its original length, once per instruction. Since the
+/// AccountInfo of solana-program 1.8 doesn't record the original length, the caller passes it, so
+/// that successive reallocations within an instruction can't write past the reserved bytes.
+///
+/// ### Arguments
+///
+/// * account: the account to resize, owned by this program.
+/// * original_len: the length of the account's data when the instruction started.
+/// * new_len: the new length of the account's data.
+fn realloc_account(account: &AccountInfo, original_len: usize, new_len: usize) -> Result<()> {
+    let mut data = account.try_borrow_mut_data()?;
+    let old_len = data.len();
+    if new_len > original_len + MAX_PERMITTED_DATA_INCREASE {
+        return err!(ErrorCode::InvalidMessageBufferCapacity);
+    }
+    unsafe {
+        let data_ptr = data.as_mut_ptr();
+        *(data_ptr.offset(-8) as *mut u64) = new_len as u64;
+        *data = std::slice::from_raw_parts_mut(data_ptr, new_len);
+    }
+    if new_len > old_len {
+        data[old_len..].fill(0);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::CyclicByteBuffer;
@@ -6,6 +6,8 @@ import { sleep, waitForFinality, Wallet_ } from '../utils';
 import { ENCRYPTION_OVERHEAD_BYTES } from '../utils/ecdh-encryption';
 import { CyclicByteBuffer } from '../utils/cyclic-bytebuffer';
 import ByteBuffer from 'bytebuffer';
+import bs58 from 'bs58';
+import { sha256 } from 'js-sha256';
 import { EncryptionProps, TextSerdeFactory } from './text-serde';
 import type { Wallet } from '../utils/Wallet';
 
@@ -20,11 +22,37 @@ export const DEVICE_TOKEN_PAYLOAD_LENGTH = 128;
 export const DEVICE_TOKEN_PADDING_LENGTH =
   DEVICE_TOKEN_PAYLOAD_LENGTH - DEVICE_TOKEN_LENGTH - ENCRYPTION_OVERHEAD_BYTES;
 
+// The max number of members of the group dialects created by createGroupDialect, by default
+export const DEFAULT_MAX_DIALECT_MEMBERS = 8;
+export const MIN_MESSAGE_BUFFER_LENGTH = 1024;
+export const MAX_MESSAGE_BUFFER_LENGTH = 16 * 1024;
+export const DEFAULT_MESSAGE_BUFFER_LENGTH = 8192;
+export const DEFAULT_EDIT_WINDOW = 15 * 60;
+export const MAX_TOPICS = 64;
+// The topics of subscriptions without a subscription topics account
+export const ALL_TOPICS = new anchor.BN(0).notn(MAX_TOPICS);
+
 const ACCOUNT_DESCRIPTOR_SIZE = 8;
-const DIALECT_ACCOUNT_MEMBER_SIZE = 34;
-const DIALECT_ACCOUNT_MEMBER0_OFFSET = ACCOUNT_DESCRIPTOR_SIZE;
-const DIALECT_ACCOUNT_MEMBER1_OFFSET =
-  DIALECT_ACCOUNT_MEMBER0_OFFSET + DIALECT_ACCOUNT_MEMBER_SIZE;
+// The layout version of dialect accounts, stored after their owner, see migrateDialect
+const DIALECT_ACCOUNT_VERSION = 2;
+const DIALECT_ACCOUNT_VERSION_OFFSET = ACCOUNT_DESCRIPTOR_SIZE + 32;
+const DIALECT_ACCOUNT_MEMBERS_COUNT_OFFSET = DIALECT_ACCOUNT_VERSION_OFFSET + 2;
+// The member slots, pinned message & message buffer follow the account struct
+const DIALECT_ACCOUNT_SIZE = 76;
+const DIALECT_ACCOUNT_MEMBER_SIZE = 43;
+// Dialect accounts created before the layout version was introduced
+const LEGACY_DIALECT_ACCOUNT_SIZE = 8279;
+const LEGACY_DIALECT_ACCOUNT_MEMBER_SIZE = 34;
+const LEGACY_MESSAGE_BUFFER_LENGTH = 8192;
+
+const MESSAGE_HISTORY_PAGE_LENGTH = 10240 - (8 + 32 + 2 + 2 + 2);
+// Shrinking a message buffer from MAX_MESSAGE_BUFFER_LENGTH to
+// MIN_MESSAGE_BUFFER_LENGTH archives its messages in at most this many pages
+const ARCHIVE_PAGES_COUNT =
+  Math.ceil(
+    (MAX_MESSAGE_BUFFER_LENGTH - MIN_MESSAGE_BUFFER_LENGTH) /
+      MESSAGE_HISTORY_PAGE_LENGTH,
+  ) + 1;
 
 export type Subscription = {
   pubkey: PublicKey;
@@ -32,21 +60,63 @@ export type Subscription = {
 };
 
 type RawDialect = {
-  members: Member[];
+  owner: PublicKey;
+  version: number;
+  maxMembers: number;
+  membersCount: number;
   messages: RawCyclicByteBuffer;
   lastMessageTimestamp: number;
   encrypted: boolean;
+  maxMessageLength: number;
+  archiveEnabled: boolean;
+  historyPagesCount: number;
+  nextMessageId: anchor.BN;
+  emitMessagePayloads: boolean;
+  editWindow: number;
+  pinnedMessageLength: number;
+};
+
+type RawBroadcastChannel = {
+  publisher: PublicKey;
+  messages: RawCyclicByteBuffer;
+  lastMessageTimestamp: number;
+  nextMessageId: anchor.BN;
 };
 
+// The underlying buffer is stored at the end of the dialect account's data, see parseRawDialect
 type RawCyclicByteBuffer = {
   readOffset: number;
   writeOffset: number;
   itemsCount: number;
-  buffer: Uint8Array;
+  capacity: number;
+};
+
+export enum SubscriptionPolicy {
+  MembersOnly = 0,
+  Open = 1,
+  OwnerApproval = 2,
+}
+
+// The content type of a message's text, validated on-chain unless the dialect is encrypted
+export enum ContentType {
+  TextPlain = 0,
+  Markdown = 1,
+  Json = 2,
+  // The SHA-256 hash of an attachment stored off-chain, followed by its URI
+  AttachmentReference = 3,
+}
+
+const contentTypeArgs = {
+  [ContentType.TextPlain]: { textPlain: {} },
+  [ContentType.Markdown]: { markdown: {} },
+  [ContentType.Json]: { json: {} },
+  [ContentType.AttachmentReference]: { attachmentReference: {} },
 };
 
 export type Metadata = {
   subscriptions: Subscription[];
+  subscriptionPolicy: SubscriptionPolicy;
+  invites: PublicKey[];
 };
 
 export type DialectAccount = {
@@ -54,20 +124,82 @@ export type DialectAccount = {
   publicKey: PublicKey;
 };
 
+export type BroadcastChannelAccount = {
+  channel: BroadcastChannel;
+  publicKey: PublicKey;
+};
+
 export type Dialect = {
   members: Member[];
   messages: Message[];
   nextMessageIdx: number;
   lastMessageTimestamp: number;
   encrypted: boolean;
+  owner: PublicKey;
+  maxMessageLength: number;
+  messageBufferCapacity: number;
+  archiveEnabled: boolean;
+  historyPagesCount: number;
+  nextMessageId: number;
+  emitMessagePayloads: boolean;
+  editWindow: number;
+  maxMembers: number;
+  // A copy of the pinned message with its latest text, kept even if the message was evicted
+  pinnedMessage?: Message;
+  // Legacy dialects must be migrated before they can be used, see migrateDialect
+  legacy?: boolean;
+};
+
+export type BroadcastChannel = {
+  publisher: PublicKey;
+  messages: Message[];
+  lastMessageTimestamp: number;
+  nextMessageId: number;
+  messageBufferCapacity: number;
 };
 
 export type Message = {
+  id: number;
   owner: PublicKey;
   text: string;
   timestamp: number;
+  deleted: boolean;
+  edited: boolean;
+  // Only for edits, which are folded into the edited message, see foldItems
+  editedMessageId?: number;
+  // The id of the message replied to, if the message is a reply
+  replyTo?: number;
+  reactions: Reaction[];
+  contentType: ContentType;
+  // Only for expiring messages, see sendMessage
+  expiresAt?: number;
+  // Only for broadcast messages with a topic, see broadcastMessage
+  topic?: number;
+};
+
+export type Reaction = {
+  owner: PublicKey;
+  // e.g. an emoji's Unicode code point
+  reaction: number;
+};
+
+type ReactionItem = Reaction & {
+  messageId: number;
 };
 
+// The types of the items stored in the message buffer
+const ITEM_TYPE_REACTION = 1;
+const ITEM_TYPE_TOMBSTONE = 3;
+// The flag of edits appended by editMessage, which reference the edited message
+const MESSAGE_FLAG_EDIT = 1;
+// The flag of replies, which reference the message replied to
+const MESSAGE_FLAG_REPLY = 2;
+// The flag of expiring messages, which store their time-to-live after the
+// reference, and of the reactions to them
+const MESSAGE_FLAG_TTL = 4;
+// The flag of broadcast messages with a topic, which store it after the ttl
+const MESSAGE_FLAG_TOPIC = 8;
+
 export type FindDialectQuery = {
   userPk?: anchor.web3.PublicKey;
 };
@@ -81,6 +213,18 @@ export function isDialectAdmin(
   );
 }
 
+// Edits have message ids too, so they are counted as unread messages
+export function getUnreadMessagesCount(
+  dialect: DialectAccount,
+  user: anchor.web3.PublicKey,
+): number {
+  const member = dialect.dialect.members.find((m) => m.publicKey.equals(user));
+  if (!member) {
+    return 0;
+  }
+  return dialect.dialect.nextMessageId - (member.readUntil ?? 0);
+}
+
 export async function accountInfoGet(
   connection: Connection,
   publicKey: PublicKey,
@@ -115,6 +259,16 @@ export async function getMetadataProgramAddress(
   );
 }
 
+export async function getMetadataPolicyProgramAddress(
+  program: anchor.Program,
+  user: PublicKey,
+): Promise<[anchor.web3.PublicKey, number]> {
+  return await anchor.web3.PublicKey.findProgramAddress(
+    [Buffer.from('metadata_policy'), user.toBuffer()],
+    program.programId,
+  );
+}
+
 // TODO: Simplify this function further now that we're no longer decrypting the device token.
 export async function getMetadata(
   program: anchor.Program,
@@ -151,15 +305,241 @@ export async function getMetadata(
     userIsKeypair ? (user as Keypair).publicKey : (user as PublicKey),
   );
   const metadata = await program.account.metadataAccount.fetch(metadataAddress);
+  const pageSubscriptions = await getMetadataPagesSubscriptions(
+    program,
+    userIsKeypair ? (user as Keypair).publicKey : (user as PublicKey),
+  );
+  const [metadataPolicyAddress] = await getMetadataPolicyProgramAddress(
+    program,
+    userIsKeypair ? (user as Keypair).publicKey : (user as PublicKey),
+  );
+  // Users without a metadata policy account have the members only policy
+  const metadataPolicy =
+    await program.account.metadataPolicyAccount.fetchNullable(
+      metadataPolicyAddress,
+    );
 
   // TODO RM this code chunk and change function signature
   return {
-    subscriptions: metadata.subscriptions.filter(
-      (s: Subscription) => !s.pubkey.equals(anchor.web3.PublicKey.default),
+    subscriptions: [
+      ...metadata.subscriptions.filter(
+        (s: Subscription) => !s.pubkey.equals(anchor.web3.PublicKey.default),
+      ),
+      ...pageSubscriptions,
+    ],
+    subscriptionPolicy: metadataPolicy
+      ? (metadataPolicy.subscriptionPolicy as SubscriptionPolicy)
+      : SubscriptionPolicy.MembersOnly,
+    invites: (metadataPolicy?.invites ?? []).filter(
+      (i: PublicKey) => !i.equals(anchor.web3.PublicKey.default),
     ),
   };
 }
 
+// Creates the user's metadata policy account if it doesn't exist yet
+export async function setSubscriptionPolicy(
+  program: anchor.Program,
+  user: anchor.web3.Keypair | Wallet,
+  policy: SubscriptionPolicy,
+): Promise<Metadata> {
+  const [metadataPolicy] = await getMetadataPolicyProgramAddress(
+    program,
+    user.publicKey,
+  );
+  const policyArgs = {
+    [SubscriptionPolicy.MembersOnly]: { membersOnly: {} },
+    [SubscriptionPolicy.Open]: { open: {} },
+    [SubscriptionPolicy.OwnerApproval]: { ownerApproval: {} },
+  };
+  const metadataPolicyAccount =
+    await program.provider.connection.getAccountInfo(metadataPolicy);
+  const tx = metadataPolicyAccount
+    ? await program.rpc.setSubscriptionPolicy(policyArgs[policy], {
+        accounts: {
+          user: user.publicKey,
+          metadataPolicy,
+        },
+        signers: 'secretKey' in user ? [user] : [],
+      })
+    : await program.rpc.createMetadataPolicy(policyArgs[policy], {
+        accounts: {
+          user: user.publicKey,
+          metadataPolicy,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        signers: 'secretKey' in user ? [user] : [],
+      });
+  await waitForFinality(program, tx);
+  return await getMetadata(program, user.publicKey);
+}
+
+// The user then has the members only policy, and pending invites are lost
+export async function deleteMetadataPolicy(
+  program: anchor.Program,
+  user: anchor.web3.Keypair | Wallet,
+): Promise<void> {
+  const [metadataPolicy] = await getMetadataPolicyProgramAddress(
+    program,
+    user.publicKey,
+  );
+  await program.rpc.closeMetadataPolicy({
+    accounts: {
+      user: user.publicKey,
+      metadataPolicy,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    },
+    signers: 'secretKey' in user ? [user] : [],
+  });
+}
+
+export async function getMetadataPageProgramAddress(
+  program: anchor.Program,
+  user: PublicKey,
+  pageIndex: number,
+): Promise<[anchor.web3.PublicKey, number]> {
+  return await anchor.web3.PublicKey.findProgramAddress(
+    [Buffer.from('metadata'), user.toBuffer(), Buffer.from([pageIndex])],
+    program.programId,
+  );
+}
+
+// The bitmap of the user's existing metadata pages is stored after the metadata
+// account struct, once the user creates a page: bit i is set if page i exists
+const METADATA_ACCOUNT_SIZE = 1096;
+const METADATA_PAGES_LENGTH = 32;
+const METADATA_PAGES_BATCH_SIZE = 100;
+
+type MetadataPages = {
+  // The addresses of the user's existing pages, in index order
+  addresses: PublicKey[];
+  subscriptions: Subscription[];
+};
+
+// Pages are looked up from the bitmap in the user's metadata account, so gaps
+// left by closed pages don't hide the pages after them
+async function getMetadataPages(
+  program: anchor.Program,
+  user: PublicKey,
+): Promise<MetadataPages> {
+  const [metadataAddress] = await getMetadataProgramAddress(program, user);
+  const metadataAccount = await program.provider.connection.getAccountInfo(
+    metadataAddress,
+  );
+  const bitmap = (metadataAccount?.data ?? Buffer.alloc(0)).subarray(
+    METADATA_ACCOUNT_SIZE,
+    METADATA_ACCOUNT_SIZE + METADATA_PAGES_LENGTH,
+  );
+  const pageIndexes = Array.from(
+    { length: bitmap.length * 8 },
+    (_, idx) => idx,
+  ).filter((idx) => bitmap[idx >> 3] & (1 << (idx & 7)));
+  const addresses = await Promise.all(
+    pageIndexes.map((idx) =>
+      getMetadataPageProgramAddress(program, user, idx).then(
+        ([address]) => address,
+      ),
+    ),
+  );
+  const subscriptions: Subscription[] = [];
+  for (
+    let batchStart = 0;
+    batchStart < addresses.length;
+    batchStart += METADATA_PAGES_BATCH_SIZE
+  ) {
+    const accountInfos =
+      await program.provider.connection.getMultipleAccountsInfo(
+        addresses.slice(batchStart, batchStart + METADATA_PAGES_BATCH_SIZE),
+      );
+    for (let idx = 0; idx < accountInfos.length; idx++) {
+      const accountInfo = accountInfos[idx];
+      if (!accountInfo) {
+        continue;
+      }
+      const page = program.coder.accounts.decode(
+        'MetadataPage',
+        accountInfo.data,
+      );
+      subscriptions.push(
+        ...page.subscriptions.filter(
+          (s: Subscription) => !s.pubkey.equals(anchor.web3.PublicKey.default),
+        ),
+      );
+    }
+  }
+  return { addresses, subscriptions };
+}
+
+async function getMetadataPagesSubscriptions(
+  program: anchor.Program,
+  user: PublicKey,
+): Promise<Subscription[]> {
+  return (await getMetadataPages(program, user)).subscriptions;
+}
+
+// The program checks the user's existing pages, passed in index order, so that
+// the user can't be subscribed to a dialect twice
+async function getMetadataPagesAccounts(
+  program: anchor.Program,
+  user: PublicKey,
+): Promise<anchor.web3.AccountMeta[]> {
+  const { addresses } = await getMetadataPages(program, user);
+  return addresses.map((pubkey) => ({
+    pubkey,
+    isWritable: false,
+    isSigner: false,
+  }));
+}
+
+export async function createMetadataPage(
+  program: anchor.Program,
+  user: anchor.web3.Keypair | Wallet,
+  pageIndex: number,
+): Promise<Metadata> {
+  const [metadataPage] = await getMetadataPageProgramAddress(
+    program,
+    user.publicKey,
+    pageIndex,
+  );
+  const [metadata] = await getMetadataProgramAddress(program, user.publicKey);
+  const tx = await program.rpc.createMetadataPage(pageIndex, {
+    accounts: {
+      user: user.publicKey,
+      metadata,
+      metadataPage,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    },
+    signers: 'secretKey' in user ? [user] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getMetadata(program, user.publicKey);
+}
+
+export async function deleteMetadataPage(
+  program: anchor.Program,
+  user: anchor.web3.Keypair | Wallet,
+  pageIndex: number,
+): Promise<void> {
+  const [metadataPage] = await getMetadataPageProgramAddress(
+    program,
+    user.publicKey,
+    pageIndex,
+  );
+  const [metadata] = await getMetadataProgramAddress(program, user.publicKey);
+  await program.rpc.closeMetadataPage(pageIndex, {
+    accounts: {
+      user: user.publicKey,
+      metadata,
+      metadataPage,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    },
+    signers: 'secretKey' in user ? [user] : [],
+  });
+}
+
 export async function createMetadata(
   program: anchor.Program,
   user: anchor.web3.Keypair | Wallet,
@@ -206,26 +586,26 @@ export async function subscribeUser(
   user: PublicKey,
   signer: Keypair,
 ): Promise<Metadata> {
-  const [publicKey, nonce] = await getDialectProgramAddress(
-    program,
-    dialect.dialect.members,
-  );
   const [metadata, metadataNonce] = await getMetadataProgramAddress(
     program,
     user,
   );
+  const [metadataPolicy] = await getMetadataPolicyProgramAddress(program, user);
+  // The dialect nonce is not used, since members can change after the dialect is created
   const tx = await program.rpc.subscribeUser(
-    new anchor.BN(nonce),
+    new anchor.BN(0),
     new anchor.BN(metadataNonce),
     {
       accounts: {
-        dialect: publicKey,
+        dialect: dialect.publicKey,
         signer: signer.publicKey,
         user: user,
         metadata,
+        metadataPolicy,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
         systemProgram: anchor.web3.SystemProgram.programId,
       },
+      remainingAccounts: await getMetadataPagesAccounts(program, user),
       signers: [signer],
     },
   );
@@ -233,108 +613,582 @@ export async function subscribeUser(
   return await getMetadata(program, user);
 }
 
-/*
-Dialect
-*/
-
-export async function getDialectProgramAddress(
-  programOrProgramAddress: PublicKey | anchor.Program,
-  membersOrMemberPubKeys: (Member | PublicKey)[],
-): Promise<[anchor.web3.PublicKey, number]> {
-  const programAddress =
-    'programId' in programOrProgramAddress
-      ? programOrProgramAddress.programId
-      : programOrProgramAddress;
-  return await anchor.web3.PublicKey.findProgramAddress(
-    [
-      Buffer.from('dialect'),
-      ...membersOrMemberPubKeys // sort for deterministic PDA
-        .map((m) => ('publicKey' in m ? m.publicKey.toBuffer() : m.toBuffer()))
-        .sort((a, b) => a.compare(b)), // TODO: test that buffers sort as expected
-    ],
-    programAddress,
+export async function acceptInvite(
+  program: anchor.Program,
+  dialect: DialectAccount,
+  user: anchor.web3.Keypair | Wallet,
+): Promise<Metadata> {
+  const [metadata, metadataNonce] = await getMetadataProgramAddress(
+    program,
+    user.publicKey,
   );
-}
-
-function parseMessages(
-  { messages: rawMessagesBuffer, members, encrypted }: RawDialect,
-  encryptionProps?: EncryptionProps | null,
-) {
-  if (encrypted && !encryptionProps) {
-    return [];
-  }
-  const messagesBuffer = new CyclicByteBuffer(
-    rawMessagesBuffer.readOffset,
-    rawMessagesBuffer.writeOffset,
-    rawMessagesBuffer.itemsCount,
-    rawMessagesBuffer.buffer,
+  const [metadataPolicy] = await getMetadataPolicyProgramAddress(
+    program,
+    user.publicKey,
   );
-  const textSerde = TextSerdeFactory.create(
-    {
-      encrypted,
-      memberPubKeys: members.map((it) => it.publicKey),
+  const tx = await program.rpc.acceptInvite(new anchor.BN(metadataNonce), {
+    accounts: {
+      user: user.publicKey,
+      metadata,
+      metadataPolicy,
+      dialect: dialect.publicKey,
     },
-    encryptionProps,
-  );
-  const allMessages: Message[] = messagesBuffer.items().map(({ buffer }) => {
-    const byteBuffer = new ByteBuffer(buffer.length).append(buffer).flip();
-    const ownerMemberIndex = byteBuffer.readByte();
-    const messageOwner = members[ownerMemberIndex];
-    const timestamp = byteBuffer.readUint32() * 1000;
-    const serializedText = new Uint8Array(byteBuffer.toBuffer(true));
-    const text = textSerde.deserialize(serializedText);
-    return {
-      owner: messageOwner.publicKey,
-      text,
-      timestamp: timestamp,
-    };
+    remainingAccounts: await getMetadataPagesAccounts(program, user.publicKey),
+    signers: 'secretKey' in user ? [user] : [],
   });
-  return allMessages.reverse();
+  await waitForFinality(program, tx);
+  return await getMetadata(program, user.publicKey);
 }
 
-function parseRawDialect(
-  rawDialect: RawDialect,
-  encryptionProps?: EncryptionProps | null,
-) {
-  return {
-    encrypted: rawDialect.encrypted,
-    members: rawDialect.members,
-    nextMessageIdx: rawDialect.messages.writeOffset,
-    lastMessageTimestamp: rawDialect.lastMessageTimestamp * 1000,
-    messages: parseMessages(rawDialect, encryptionProps),
-  };
+export async function rejectInvite(
+  program: anchor.Program,
+  dialect: DialectAccount,
+  user: anchor.web3.Keypair | Wallet,
+): Promise<Metadata> {
+  const [metadataPolicy] = await getMetadataPolicyProgramAddress(
+    program,
+    user.publicKey,
+  );
+  const tx = await program.rpc.rejectInvite({
+    accounts: {
+      user: user.publicKey,
+      metadataPolicy,
+      dialect: dialect.publicKey,
+    },
+    signers: 'secretKey' in user ? [user] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getMetadata(program, user.publicKey);
 }
 
-export async function getDialect(
+// Also unsubscribes users from broadcast channels
+export async function unsubscribeUser(
   program: anchor.Program,
-  publicKey: PublicKey,
-  encryptionProps?: EncryptionProps | null,
-): Promise<DialectAccount> {
-  const rawDialect = (await program.account.dialectAccount.fetch(
-    publicKey,
-  )) as RawDialect;
-  const account = await program.provider.connection.getAccountInfo(publicKey);
-  const dialect = parseRawDialect(rawDialect, encryptionProps);
-  return {
-    ...account,
-    publicKey: publicKey,
-    dialect,
-  } as DialectAccount;
+  dialect: DialectAccount | BroadcastChannelAccount,
+  user: anchor.web3.Keypair | Wallet,
+): Promise<Metadata> {
+  const [metadata, metadataNonce] = await getMetadataProgramAddress(
+    program,
+    user.publicKey,
+  );
+  const tx = await program.rpc.unsubscribeUser(new anchor.BN(metadataNonce), {
+    accounts: {
+      user: user.publicKey,
+      metadata,
+      dialect: dialect.publicKey,
+    },
+    signers: 'secretKey' in user ? [user] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getMetadata(program, user.publicKey);
 }
 
-export async function getDialects(
+export async function setSubscriptionEnabled(
   program: anchor.Program,
+  dialect: DialectAccount,
   user: anchor.web3.Keypair | Wallet,
-  encryptionProps?: EncryptionProps | null,
-): Promise<DialectAccount[]> {
-  const metadata = await getMetadata(program, user.publicKey);
-  const enabledSubscriptions = metadata.subscriptions.filter(
-    (it) => it.enabled,
+  enabled: boolean,
+): Promise<Metadata> {
+  const [metadata, metadataNonce] = await getMetadataProgramAddress(
+    program,
+    user.publicKey,
   );
-  return Promise.all(
-    enabledSubscriptions.map(async ({ pubkey }) =>
-      getDialect(program, pubkey, encryptionProps),
+  const tx = await program.rpc.setSubscriptionEnabled(
+    new anchor.BN(metadataNonce),
+    enabled,
+    {
+      accounts: {
+        user: user.publicKey,
+        metadata,
+        dialect: dialect.publicKey,
+      },
+      signers: 'secretKey' in user ? [user] : [],
+    },
+  );
+  await waitForFinality(program, tx);
+  return await getMetadata(program, user.publicKey);
+}
+
+export async function subscribeUserToPage(
+  program: anchor.Program,
+  dialect: DialectAccount,
+  user: PublicKey,
+  signer: Keypair,
+  pageIndex: number,
+): Promise<Metadata> {
+  const [metadata, metadataNonce] = await getMetadataProgramAddress(
+    program,
+    user,
+  );
+  const [metadataPage] = await getMetadataPageProgramAddress(
+    program,
+    user,
+    pageIndex,
+  );
+  const [metadataPolicy] = await getMetadataPolicyProgramAddress(program, user);
+  const tx = await program.rpc.subscribeUserToPage(
+    new anchor.BN(metadataNonce),
+    pageIndex,
+    {
+      accounts: {
+        dialect: dialect.publicKey,
+        signer: signer.publicKey,
+        user: user,
+        metadata,
+        metadataPage,
+        metadataPolicy,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      remainingAccounts: await getMetadataPagesAccounts(program, user),
+      signers: [signer],
+    },
+  );
+  await waitForFinality(program, tx);
+  return await getMetadata(program, user);
+}
+
+export async function unsubscribeUserFromPage(
+  program: anchor.Program,
+  dialect: DialectAccount,
+  user: anchor.web3.Keypair | Wallet,
+  pageIndex: number,
+): Promise<Metadata> {
+  const [metadataPage] = await getMetadataPageProgramAddress(
+    program,
+    user.publicKey,
+    pageIndex,
+  );
+  const tx = await program.rpc.unsubscribeUserFromPage(pageIndex, {
+    accounts: {
+      user: user.publicKey,
+      metadataPage,
+      dialect: dialect.publicKey,
+    },
+    signers: 'secretKey' in user ? [user] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getMetadata(program, user.publicKey);
+}
+
+export async function setPageSubscriptionEnabled(
+  program: anchor.Program,
+  dialect: DialectAccount,
+  user: anchor.web3.Keypair | Wallet,
+  pageIndex: number,
+  enabled: boolean,
+): Promise<Metadata> {
+  const [metadataPage] = await getMetadataPageProgramAddress(
+    program,
+    user.publicKey,
+    pageIndex,
+  );
+  const tx = await program.rpc.setPageSubscriptionEnabled(
+    pageIndex,
+    enabled,
+    {
+      accounts: {
+        user: user.publicKey,
+        metadataPage,
+        dialect: dialect.publicKey,
+      },
+      signers: 'secretKey' in user ? [user] : [],
+    },
+  );
+  await waitForFinality(program, tx);
+  return await getMetadata(program, user.publicKey);
+}
+
+/*
+Dialect
+*/
+
+export async function getDialectProgramAddress(
+  programOrProgramAddress: PublicKey | anchor.Program,
+  membersOrMemberPubKeys: (Member | PublicKey)[],
+): Promise<[anchor.web3.PublicKey, number]> {
+  if (membersOrMemberPubKeys.length > 2) {
+    return getGroupDialectProgramAddress(
+      programOrProgramAddress,
+      membersOrMemberPubKeys,
+    );
+  }
+  const programAddress =
+    'programId' in programOrProgramAddress
+      ? programOrProgramAddress.programId
+      : programOrProgramAddress;
+  return await anchor.web3.PublicKey.findProgramAddress(
+    [
+      Buffer.from('dialect'),
+      ...membersOrMemberPubKeys // sort for deterministic PDA
+        .map((m) => ('publicKey' in m ? m.publicKey.toBuffer() : m.toBuffer()))
+        .sort((a, b) => a.compare(b)), // TODO: test that buffers sort as expected
+    ],
+    programAddress,
+  );
+}
+
+export function hashMembers(
+  membersOrMemberPubKeys: (Member | PublicKey)[],
+): Buffer {
+  const sortedMemberBuffers = membersOrMemberPubKeys
+    .map((m) => ('publicKey' in m ? m.publicKey.toBuffer() : m.toBuffer()))
+    .sort((a, b) => a.compare(b));
+  return Buffer.from(sha256.array(Buffer.concat(sortedMemberBuffers)));
+}
+
+// Group dialects are seeded by a hash of their sorted members, see create_group_dialect
+export async function getGroupDialectProgramAddress(
+  programOrProgramAddress: PublicKey | anchor.Program,
+  membersOrMemberPubKeys: (Member | PublicKey)[],
+): Promise<[anchor.web3.PublicKey, number]> {
+  const programAddress =
+    'programId' in programOrProgramAddress
+      ? programOrProgramAddress.programId
+      : programOrProgramAddress;
+  return await anchor.web3.PublicKey.findProgramAddress(
+    [Buffer.from('dialect'), hashMembers(membersOrMemberPubKeys)],
+    programAddress,
+  );
+}
+
+function parseMessages(
+  {
+    messages: rawMessagesBuffer,
+    members,
+    encrypted,
+  }: Pick<RawDialect, 'messages' | 'encrypted'> & { members: Member[] },
+  buffer: Uint8Array,
+  encryptionProps?: EncryptionProps | null,
+) {
+  if (encrypted && !encryptionProps) {
+    return [];
+  }
+  const messagesBuffer = new CyclicByteBuffer(
+    rawMessagesBuffer.readOffset,
+    rawMessagesBuffer.writeOffset,
+    rawMessagesBuffer.itemsCount,
+    buffer,
+  );
+  const textSerde = TextSerdeFactory.create(
+    {
+      encrypted,
+      memberPubKeys: members.map((it) => it.publicKey),
+    },
+    encryptionProps,
+  );
+  const items = messagesBuffer
+    .items()
+    .map(({ buffer }) => parseItem(buffer, members, textSerde));
+  // Expired messages are hidden until they are pruned, see pruneExpired
+  const now = Date.now();
+  return foldItems(items)
+    .filter(({ expiresAt }) => expiresAt === undefined || expiresAt > now)
+    .reverse();
+}
+
+// The pinned message is stored outside the message buffer, in the same format, see pinMessage
+function parsePinnedMessage(
+  { members, encrypted }: Pick<RawDialect, 'encrypted'> & { members: Member[] },
+  pinnedMessage: Uint8Array,
+  encryptionProps?: EncryptionProps | null,
+): Message | undefined {
+  if (pinnedMessage.length === 0 || (encrypted && !encryptionProps)) {
+    return undefined;
+  }
+  const textSerde = TextSerdeFactory.create(
+    {
+      encrypted,
+      memberPubKeys: members.map((it) => it.publicKey),
+    },
+    encryptionProps,
+  );
+  return parseItem(
+    new Uint8Array(pinnedMessage),
+    members,
+    textSerde,
+  ) as Message;
+}
+
+// Edits replace the text of the edited message, if it was retrieved along with them and neither
+// is deleted, and reactions are added to the message reacted to, if it was retrieved along with them.
+// Edits of messages that were not retrieved are skipped, since their text is only meaningful as
+// an edit. Items are ordered oldest first, so that the latest edit wins.
+function foldItems(items: (Message | ReactionItem)[]): Message[] {
+  const messagesById = new Map<number, Message>();
+  return items.filter((message): message is Message => {
+    if ('messageId' in message) {
+      const { messageId, ...reaction } = message;
+      messagesById.get(messageId)?.reactions.push(reaction);
+      return false;
+    }
+    if (message.editedMessageId === undefined) {
+      messagesById.set(message.id, message);
+      return true;
+    }
+    const editedMessage = messagesById.get(message.editedMessageId);
+    if (!editedMessage) {
+      return false;
+    }
+    if (!editedMessage.deleted && !message.deleted) {
+      editedMessage.text = message.text;
+      editedMessage.edited = true;
+    }
+    return false;
+  });
+}
+
+// Items reference their sender by member slot index, see parseRawDialect
+function parseItem(
+  buffer: Uint8Array,
+  members: Member[],
+  textSerde: ReturnType<typeof TextSerdeFactory.create>,
+): Message | ReactionItem {
+  const byteBuffer = new ByteBuffer(buffer.length).append(buffer).flip();
+  const itemType = byteBuffer.readUint8();
+  const ownerMemberIndex = byteBuffer.readByte();
+  const messageOwner = members[ownerMemberIndex];
+  const flags = byteBuffer.readUint8();
+  if (itemType === ITEM_TYPE_REACTION) {
+    return {
+      owner: messageOwner.publicKey,
+      messageId: byteBuffer.readUint64().toNumber(),
+      reaction: byteBuffer.readUint32(),
+    };
+  }
+  const id = byteBuffer.readUint64().toNumber();
+  const timestamp = byteBuffer.readUint32() * 1000;
+  const deleted = itemType === ITEM_TYPE_TOMBSTONE;
+  const edited = (flags & MESSAGE_FLAG_EDIT) !== 0;
+  const isReply = (flags & MESSAGE_FLAG_REPLY) !== 0;
+  // Edits & replies store the id of the message they reference after the header
+  const referencedMessageId =
+    edited || isReply ? byteBuffer.readUint64().toNumber() : undefined;
+  const ttl =
+    (flags & MESSAGE_FLAG_TTL) !== 0 ? byteBuffer.readUint32() : undefined;
+  const topic =
+    (flags & MESSAGE_FLAG_TOPIC) !== 0 ? byteBuffer.readUint8() : undefined;
+  // The message envelope, see MESSAGE_ENVELOPE_VERSION
+  byteBuffer.readUint8(); // envelope version
+  const contentType = byteBuffer.readUint8() as ContentType;
+  const serializedText = new Uint8Array(byteBuffer.toBuffer(true));
+  const text = deleted ? '' : textSerde.deserialize(serializedText);
+  return {
+    id,
+    owner: messageOwner.publicKey,
+    text,
+    timestamp: timestamp,
+    deleted,
+    edited,
+    editedMessageId: edited ? referencedMessageId : undefined,
+    replyTo: isReply ? referencedMessageId : undefined,
+    reactions: [],
+    contentType,
+    expiresAt: ttl === undefined ? undefined : timestamp + ttl * 1000,
+    topic,
+  };
+}
+
+// The member slots follow the account struct, removed members keep their slot
+function parseMemberSlots(
+  { membersCount }: RawDialect,
+  accountData: Buffer,
+): Member[] {
+  return Array.from({ length: membersCount }, (_, idx): Member => {
+    const offset = DIALECT_ACCOUNT_SIZE + idx * DIALECT_ACCOUNT_MEMBER_SIZE;
+    const slot = accountData.subarray(
+      offset,
+      offset + DIALECT_ACCOUNT_MEMBER_SIZE,
+    );
+    return {
+      publicKey: new anchor.web3.PublicKey(slot.subarray(0, 32)),
+      scopes: [slot[32] !== 0, slot[33] !== 0],
+      removed: slot[34] !== 0,
+      readUntil: new anchor.BN(slot.subarray(35), 'le').toNumber(),
+    };
+  });
+}
+
+function parseRawDialect(
+  rawDialect: RawDialect,
+  accountData: Buffer,
+  encryptionProps?: EncryptionProps | null,
+): Dialect {
+  const memberSlots = parseMemberSlots(rawDialect, accountData);
+  const members = memberSlots.filter((m) => !m.removed);
+  // The pinned message follows the member slots, sized to fit it
+  const pinnedMessageOffset =
+    DIALECT_ACCOUNT_SIZE + memberSlots.length * DIALECT_ACCOUNT_MEMBER_SIZE;
+  const pinnedMessage = accountData.subarray(
+    pinnedMessageOffset,
+    pinnedMessageOffset + rawDialect.pinnedMessageLength,
+  );
+  // The message buffer is stored at the end of the account's data, so that it can be resized
+  const buffer = accountData.subarray(
+    accountData.length - rawDialect.messages.capacity,
+  );
+  return {
+    encrypted: rawDialect.encrypted,
+    members,
+    nextMessageIdx: rawDialect.messages.writeOffset,
+    lastMessageTimestamp: rawDialect.lastMessageTimestamp * 1000,
+    owner: rawDialect.owner,
+    maxMessageLength: rawDialect.maxMessageLength,
+    messageBufferCapacity: rawDialect.messages.capacity,
+    archiveEnabled: rawDialect.archiveEnabled,
+    historyPagesCount: rawDialect.historyPagesCount,
+    nextMessageId: rawDialect.nextMessageId.toNumber(),
+    emitMessagePayloads: rawDialect.emitMessagePayloads,
+    editWindow: rawDialect.editWindow,
+    maxMembers: rawDialect.maxMembers,
+    pinnedMessage: parsePinnedMessage(
+      { ...rawDialect, members: memberSlots },
+      pinnedMessage,
+      encryptionProps,
+    ),
+    messages: parseMessages(
+      { ...rawDialect, members: memberSlots },
+      buffer,
+      encryptionProps,
     ),
+  };
+}
+
+// Dialect accounts created before the layout version was introduced store two member slots &
+// a message buffer in place, and their messages as [sender, timestamp, text], see migrateDialect
+function parseLegacyDialect(
+  accountData: Buffer,
+  encryptionProps?: EncryptionProps | null,
+): Dialect {
+  const members = [0, 1].map((idx): Member => {
+    const offset =
+      ACCOUNT_DESCRIPTOR_SIZE + idx * LEGACY_DIALECT_ACCOUNT_MEMBER_SIZE;
+    return {
+      publicKey: new anchor.web3.PublicKey(
+        accountData.subarray(offset, offset + 32),
+      ),
+      scopes: [accountData[offset + 32] !== 0, accountData[offset + 33] !== 0],
+    };
+  });
+  const messagesOffset =
+    ACCOUNT_DESCRIPTOR_SIZE + 2 * LEGACY_DIALECT_ACCOUNT_MEMBER_SIZE;
+  const bufferOffset = messagesOffset + 6;
+  const bufferEnd = bufferOffset + LEGACY_MESSAGE_BUFFER_LENGTH;
+  const messagesBuffer = new CyclicByteBuffer(
+    accountData.readUInt16LE(messagesOffset),
+    accountData.readUInt16LE(messagesOffset + 2),
+    accountData.readUInt16LE(messagesOffset + 4),
+    accountData.subarray(bufferOffset, bufferEnd),
+  );
+  const encrypted = accountData[bufferEnd + 4] !== 0;
+  const textSerde = TextSerdeFactory.create(
+    {
+      encrypted,
+      memberPubKeys: members.map((it) => it.publicKey),
+    },
+    encryptionProps,
+  );
+  // Legacy messages get ids in the order they were sent, as when they are migrated
+  const messages: Message[] =
+    encrypted && !encryptionProps
+      ? []
+      : messagesBuffer.items().map(({ buffer }, id) => {
+          const byteBuffer = new ByteBuffer(buffer.length)
+            .append(buffer)
+            .flip();
+          const owner = members[byteBuffer.readByte()].publicKey;
+          const timestamp = byteBuffer.readUint32() * 1000;
+          const serializedText = new Uint8Array(byteBuffer.toBuffer(true));
+          return {
+            id,
+            owner,
+            text: textSerde.deserialize(serializedText),
+            timestamp,
+            deleted: false,
+            edited: false,
+            reactions: [],
+            contentType: ContentType.TextPlain,
+          };
+        });
+  return {
+    encrypted,
+    members,
+    nextMessageIdx: messagesBuffer.writeOffset,
+    lastMessageTimestamp: accountData.readUInt32LE(bufferEnd) * 1000,
+    owner: (members.find((m) => m.scopes[0]) ?? members[0]).publicKey,
+    // Messages can't be sent until the dialect is migrated
+    maxMessageLength: 0,
+    messageBufferCapacity: LEGACY_MESSAGE_BUFFER_LENGTH,
+    archiveEnabled: false,
+    historyPagesCount: 0,
+    nextMessageId: messagesBuffer.itemsCount,
+    emitMessagePayloads: false,
+    editWindow: 0,
+    maxMembers: 2,
+    messages: messages.reverse(),
+    legacy: true,
+  };
+}
+
+// Legacy dialect accounts store the first member's admin scope, 0 or 1, at the version's offset
+function isLegacyDialectAccountData(accountData: Buffer): boolean {
+  return accountData[DIALECT_ACCOUNT_VERSION_OFFSET] < DIALECT_ACCOUNT_VERSION;
+}
+
+function parseDialectAccountData(
+  program: anchor.Program,
+  accountData: Buffer,
+  encryptionProps?: EncryptionProps | null,
+): Dialect {
+  if (isLegacyDialectAccountData(accountData)) {
+    return parseLegacyDialect(accountData, encryptionProps);
+  }
+  const rawDialect = program.coder.accounts.decode(
+    'DialectAccount',
+    accountData,
+  ) as RawDialect;
+  return parseRawDialect(rawDialect, accountData, encryptionProps);
+}
+
+export async function getDialect(
+  program: anchor.Program,
+  publicKey: PublicKey,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const account = await program.provider.connection.getAccountInfo(publicKey);
+  if (!account) {
+    throw new Error(`Dialect account ${publicKey.toBase58()} does not exist`);
+  }
+  const dialect = parseDialectAccountData(
+    program,
+    account.data,
+    encryptionProps,
+  );
+  return {
+    ...account,
+    publicKey: publicKey,
+    dialect,
+  } as DialectAccount;
+}
+
+export async function getDialects(
+  program: anchor.Program,
+  user: anchor.web3.Keypair | Wallet,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount[]> {
+  const metadata = await getMetadata(program, user.publicKey);
+  const enabledSubscriptions = metadata.subscriptions.filter(
+    (it) => it.enabled,
+  );
+  // Users also subscribe to broadcast channels, see getBroadcastChannels
+  const channels = await filterBroadcastChannels(
+    program,
+    enabledSubscriptions.map((it) => it.pubkey),
+  );
+  return Promise.all(
+    enabledSubscriptions
+      .filter(({ pubkey }) => !channels.some((it) => it.equals(pubkey)))
+      .map(async ({ pubkey }) => getDialect(program, pubkey, encryptionProps)),
   ).then((dialects) =>
     dialects.sort(
       ({ dialect: d1 }, { dialect: d2 }) =>
@@ -359,42 +1213,85 @@ export async function findDialects(
   program: anchor.Program,
   { userPk }: FindDialectQuery,
 ): Promise<DialectAccount[]> {
-  const memberFilters = userPk
-    ? [
-        {
-          memcmp: {
-            offset: DIALECT_ACCOUNT_MEMBER0_OFFSET,
-            bytes: userPk.toBase58(),
-          },
+  if (!userPk) {
+    return [];
+  }
+  const connection = program.provider.connection;
+  const discriminatorFilter = {
+    memcmp: {
+      offset: 0,
+      bytes: bs58.encode(
+        anchor.BorshAccountsCoder.accountDiscriminator('DialectAccount'),
+      ),
+    },
+  };
+  const versionFilter = {
+    memcmp: {
+      offset: DIALECT_ACCOUNT_VERSION_OFFSET,
+      bytes: bs58.encode(Buffer.from([DIALECT_ACCOUNT_VERSION])),
+    },
+  };
+  // Member slots are sized from the number of members, so the user's slot index is bounded by
+  // the largest number of member slots among dialects
+  const membersCounts = await connection.getProgramAccounts(program.programId, {
+    dataSlice: { offset: DIALECT_ACCOUNT_MEMBERS_COUNT_OFFSET, length: 1 },
+    filters: [discriminatorFilter, versionFilter],
+  });
+  const maxMembersCount = Math.max(
+    0,
+    ...membersCounts.map(({ account }) => account.data[0]),
+  );
+  const memberFilters = [
+    ...Array.from({ length: maxMembersCount }, (_, idx) => [
+      discriminatorFilter,
+      versionFilter,
+      {
+        memcmp: {
+          offset: DIALECT_ACCOUNT_SIZE + idx * DIALECT_ACCOUNT_MEMBER_SIZE,
+          bytes: userPk.toBase58(),
         },
-        {
-          memcmp: {
-            offset: DIALECT_ACCOUNT_MEMBER1_OFFSET,
-            bytes: userPk.toBase58(),
-          },
+      },
+    ]),
+    // Legacy dialects store their two member slots after the descriptor
+    ...[0, 1].map((idx) => [
+      discriminatorFilter,
+      { dataSize: LEGACY_DIALECT_ACCOUNT_SIZE },
+      {
+        memcmp: {
+          offset:
+            ACCOUNT_DESCRIPTOR_SIZE + idx * LEGACY_DIALECT_ACCOUNT_MEMBER_SIZE,
+          bytes: userPk.toBase58(),
         },
-      ]
-    : [];
-  return Promise.all(
-    memberFilters.map((it) => program.account.dialectAccount.all([it])),
-  )
-    .then((it) =>
-      it.flat().map((a) => {
-        const rawDialect = a.account as RawDialect;
-        const dialectAccount: DialectAccount = {
-          publicKey: a.publicKey,
-          dialect: parseRawDialect(rawDialect),
-        };
-        return dialectAccount;
-      }),
-    )
-    .then((dialects) =>
-      dialects.sort(
-        ({ dialect: d1 }, { dialect: d2 }) =>
-          d2.lastMessageTimestamp - d1.lastMessageTimestamp, // descending
-      ),
-    );
-}
+      },
+    ]),
+  ];
+  const accounts = await Promise.all(
+    memberFilters.map((filters) =>
+      connection.getProgramAccounts(program.programId, { filters }),
+    ),
+  );
+  // A dialect can match the filters of slots past its own, which hold its pinned message & messages
+  const accountsByAddress = new Map(
+    accounts.flat().map((it) => [it.pubkey.toBase58(), it]),
+  );
+  return Array.from(accountsByAddress.values())
+    .flatMap(({ pubkey, account }) => {
+      const dialect = parseDialectAccountData(program, account.data);
+      // Removed members still occupy a member slot
+      if (!dialect.members.some((m) => m.publicKey.equals(userPk))) {
+        return [];
+      }
+      const dialectAccount: DialectAccount = {
+        publicKey: pubkey,
+        dialect,
+      };
+      return [dialectAccount];
+    })
+    .sort(
+      ({ dialect: d1 }, { dialect: d2 }) =>
+        d2.lastMessageTimestamp - d1.lastMessageTimestamp, // descending
+    );
+}
 
 export async function createDialect(
   program: anchor.Program,
@@ -402,6 +1299,7 @@ export async function createDialect(
   members: Member[],
   encrypted = false,
   encryptionProps?: EncryptionProps | null,
+  capacity = DEFAULT_MESSAGE_BUFFER_LENGTH,
 ): Promise<DialectAccount> {
   const sortedMembers = members.sort((a, b) =>
     a.publicKey.toBuffer().compare(b.publicKey.toBuffer()),
@@ -410,99 +1308,955 @@ export async function createDialect(
     program,
     sortedMembers,
   );
-  // TODO: assert owner in members
-  const keyedMembers = sortedMembers.reduce(
-    (ms, m, idx) => ({ ...ms, [`member${idx}`]: m.publicKey }),
-    {},
+  // TODO: assert owner in members
+  const keyedMembers = sortedMembers.reduce(
+    (ms, m, idx) => ({ ...ms, [`member${idx}`]: m.publicKey }),
+    {},
+  );
+  const tx = await program.rpc.createDialect(
+    new anchor.BN(nonce),
+    encrypted,
+    sortedMembers.map((m) => m.scopes),
+    capacity,
+    {
+      accounts: {
+        dialect: publicKey,
+        owner: owner.publicKey,
+        ...keyedMembers,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      signers: 'secretKey' in owner ? [owner] : [],
+    },
+  );
+  await waitForFinality(program, tx);
+  return await getDialectForMembers(program, members, encryptionProps);
+}
+
+export async function createGroupDialect(
+  program: anchor.Program,
+  owner: anchor.web3.Keypair | Wallet,
+  members: Member[],
+  encrypted = false,
+  encryptionProps?: EncryptionProps | null,
+  capacity = DEFAULT_MESSAGE_BUFFER_LENGTH,
+  maxMembers = DEFAULT_MAX_DIALECT_MEMBERS,
+): Promise<DialectAccount> {
+  const sortedMembers = members.sort((a, b) =>
+    a.publicKey.toBuffer().compare(b.publicKey.toBuffer()),
+  );
+  const [publicKey] = await getGroupDialectProgramAddress(
+    program,
+    sortedMembers,
+  );
+  const tx = await program.rpc.createGroupDialect(
+    [...hashMembers(sortedMembers)],
+    encrypted,
+    sortedMembers.map((m) => m.scopes),
+    capacity,
+    maxMembers,
+    {
+      accounts: {
+        dialect: publicKey,
+        owner: owner.publicKey,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      remainingAccounts: sortedMembers.map((m) => ({
+        pubkey: m.publicKey,
+        isWritable: false,
+        isSigner: false,
+      })),
+      signers: 'secretKey' in owner ? [owner] : [],
+    },
+  );
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+export async function deleteDialect(
+  program: anchor.Program,
+  { dialect, publicKey }: DialectAccount,
+  admin: anchor.web3.Keypair | Wallet,
+): Promise<void> {
+  // The dialect nonce is not used, since members can change after the dialect is created
+  await program.rpc.closeDialect(new anchor.BN(0), {
+    accounts: {
+      admin: admin.publicKey,
+      owner: dialect.owner, // the rent is refunded to the dialect's recorded owner
+      dialect: publicKey,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    },
+    signers: 'secretKey' in admin ? [admin] : [],
+  });
+}
+
+export async function setMaxMessageLength(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  admin: anchor.web3.Keypair | Wallet,
+  maxMessageLength: number,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.setMaxMessageLength(maxMessageLength, {
+    accounts: {
+      admin: admin.publicKey,
+      dialect: publicKey,
+    },
+    signers: 'secretKey' in admin ? [admin] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+// The edit window is in seconds, 0 disables edits
+export async function setEditWindow(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  admin: anchor.web3.Keypair | Wallet,
+  editWindow: number,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.setEditWindow(editWindow, {
+    accounts: {
+      admin: admin.publicKey,
+      dialect: publicKey,
+    },
+    signers: 'secretKey' in admin ? [admin] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+export async function resizeDialect(
+  program: anchor.Program,
+  { dialect, publicKey }: DialectAccount,
+  admin: anchor.web3.Keypair | Wallet,
+  capacity: number,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.resizeDialect(capacity, {
+    accounts: {
+      admin: admin.publicKey,
+      dialect: publicKey,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    },
+    remainingAccounts: await getArchiveAccounts(program, dialect, publicKey),
+    signers: 'secretKey' in admin ? [admin] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+export async function setEmitMessagePayloads(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  admin: anchor.web3.Keypair | Wallet,
+  enabled: boolean,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.setEmitMessagePayloads(enabled, {
+    accounts: {
+      admin: admin.publicKey,
+      dialect: publicKey,
+    },
+    signers: 'secretKey' in admin ? [admin] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+export async function setArchiveEnabled(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  admin: anchor.web3.Keypair | Wallet,
+  enabled: boolean,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.setArchiveEnabled(enabled, {
+    accounts: {
+      admin: admin.publicKey,
+      dialect: publicKey,
+    },
+    signers: 'secretKey' in admin ? [admin] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+export async function transferDialectOwnership(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  owner: anchor.web3.Keypair | Wallet,
+  newOwner: PublicKey,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.transferDialectOwnership({
+    accounts: {
+      owner: owner.publicKey,
+      newOwner,
+      dialect: publicKey,
+    },
+    signers: 'secretKey' in owner ? [owner] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+// Migrates a legacy dialect to the current layout, the admin becomes its owner and tops up its rent
+export async function migrateDialect(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  admin: anchor.web3.Keypair | Wallet,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.migrateDialect({
+    accounts: {
+      admin: admin.publicKey,
+      dialect: publicKey,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    },
+    signers: 'secretKey' in admin ? [admin] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+/*
+Members
+*/
+
+export type Member = {
+  publicKey: anchor.web3.PublicKey;
+  scopes: [boolean, boolean];
+  removed?: boolean;
+  // The messages with an id lower than readUntil were read by the member, see markRead
+  readUntil?: number;
+};
+
+export async function addMember(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  admin: anchor.web3.Keypair | Wallet,
+  member: Member,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.addMember(member.scopes, {
+    accounts: {
+      admin: admin.publicKey,
+      dialect: publicKey,
+      member: member.publicKey,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    },
+    signers: 'secretKey' in admin ? [admin] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+export async function updateMemberScopes(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  admin: anchor.web3.Keypair | Wallet,
+  member: PublicKey,
+  scopes: [boolean, boolean],
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.updateMemberScopes(scopes, {
+    accounts: {
+      admin: admin.publicKey,
+      dialect: publicKey,
+      member,
+    },
+    signers: 'secretKey' in admin ? [admin] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+export async function removeMember(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  admin: anchor.web3.Keypair | Wallet,
+  member: PublicKey,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.removeMember({
+    accounts: {
+      admin: admin.publicKey,
+      dialect: publicKey,
+      member,
+    },
+    signers: 'secretKey' in admin ? [admin] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+// The max number of members can't be lower than the number of member slots in use
+export async function setMaxMembers(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  admin: anchor.web3.Keypair | Wallet,
+  maxMembers: number,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.setMaxMembers(maxMembers, {
+    accounts: {
+      admin: admin.publicKey,
+      dialect: publicKey,
+    },
+    signers: 'secretKey' in admin ? [admin] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+/*
+Messages
+*/
+
+export async function sendMessage(
+  program: anchor.Program,
+  { dialect, publicKey }: DialectAccount,
+  sender: anchor.web3.Keypair | Wallet,
+  text: string,
+  encryptionProps?: EncryptionProps | null,
+  replyTo?: number,
+  contentType = ContentType.TextPlain,
+  // The time-to-live of the message in seconds, if it should expire
+  ttl?: number,
+): Promise<Message> {
+  const textSerde = TextSerdeFactory.create(
+    {
+      encrypted: dialect.encrypted,
+      memberPubKeys: dialect.members.map((it) => it.publicKey),
+    },
+    encryptionProps,
+  );
+  const serializedText = textSerde.serialize(text);
+  // The dialect nonce is not used, since members can change after the dialect is created
+  await program.rpc.sendMessage(
+    new anchor.BN(0),
+    Buffer.from(serializedText),
+    replyTo === undefined ? null : new anchor.BN(replyTo),
+    contentTypeArgs[contentType],
+    ttl ?? null,
+    {
+      accounts: {
+        dialect: publicKey,
+        sender: sender ? sender.publicKey : program.provider.wallet.publicKey,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      remainingAccounts: await getArchiveAccounts(program, dialect, publicKey),
+      signers: sender && 'secretKey' in sender ? [sender] : [],
+    },
+  );
+  const d = await getDialect(program, publicKey, encryptionProps);
+  return d.dialect.messages[0]; // TODO: Support ring
+}
+
+// Only the message's sender can edit it, within the dialect's edit window
+export async function editMessage(
+  program: anchor.Program,
+  { dialect, publicKey }: DialectAccount,
+  sender: anchor.web3.Keypair | Wallet,
+  messageId: number,
+  text: string,
+  encryptionProps?: EncryptionProps | null,
+  contentType = ContentType.TextPlain,
+): Promise<DialectAccount> {
+  const textSerde = TextSerdeFactory.create(
+    {
+      encrypted: dialect.encrypted,
+      memberPubKeys: dialect.members.map((it) => it.publicKey),
+    },
+    encryptionProps,
+  );
+  const serializedText = textSerde.serialize(text);
+  const tx = await program.rpc.editMessage(
+    new anchor.BN(messageId),
+    Buffer.from(serializedText),
+    contentTypeArgs[contentType],
+    {
+      accounts: {
+        sender: sender.publicKey,
+        dialect: publicKey,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      remainingAccounts: await getArchiveAccounts(program, dialect, publicKey),
+      signers: 'secretKey' in sender ? [sender] : [],
+    },
+  );
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+// Anyone can erase the expired messages from the front of the message buffer
+export async function pruneExpired(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.pruneExpired({
+    accounts: {
+      dialect: publicKey,
+    },
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+// Reactions are stored as compact items and added to the message reacted to by readers
+export async function react(
+  program: anchor.Program,
+  { dialect, publicKey }: DialectAccount,
+  sender: anchor.web3.Keypair | Wallet,
+  messageId: number,
+  reaction: number,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.react(new anchor.BN(messageId), reaction, {
+    accounts: {
+      sender: sender.publicKey,
+      dialect: publicKey,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    },
+    remainingAccounts: await getArchiveAccounts(program, dialect, publicKey),
+    signers: 'secretKey' in sender ? [sender] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+// Marks the messages up to the message as read for the member, read pointers never move backwards
+export async function markRead(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  member: anchor.web3.Keypair | Wallet,
+  messageId: number,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.markRead(new anchor.BN(messageId), {
+    accounts: {
+      member: member.publicKey,
+      dialect: publicKey,
+    },
+    signers: 'secretKey' in member ? [member] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+// Only the message's sender or an admin can delete it, the message is replaced by a tombstone
+export async function deleteMessage(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  signer: anchor.web3.Keypair | Wallet,
+  messageId: number,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.deleteMessage(new anchor.BN(messageId), {
+    accounts: {
+      signer: signer.publicKey,
+      dialect: publicKey,
+    },
+    signers: 'secretKey' in signer ? [signer] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+// Only admins can pin a message, which replaces the previously pinned message
+export async function pinMessage(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  admin: anchor.web3.Keypair | Wallet,
+  messageId: number,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.pinMessage(new anchor.BN(messageId), {
+    accounts: {
+      admin: admin.publicKey,
+      dialect: publicKey,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    },
+    signers: 'secretKey' in admin ? [admin] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+export async function unpinMessage(
+  program: anchor.Program,
+  { publicKey }: DialectAccount,
+  admin: anchor.web3.Keypair | Wallet,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const tx = await program.rpc.unpinMessage({
+    accounts: {
+      admin: admin.publicKey,
+      dialect: publicKey,
+    },
+    signers: 'secretKey' in admin ? [admin] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+/*
+Message history
+*/
+
+// Evicted messages are archived in the dialect's last message history pages,
+// spilling over to the next ones, see send_message & resize_dialect
+async function getArchiveAccounts(
+  program: anchor.Program,
+  dialect: Dialect,
+  publicKey: PublicKey,
+): Promise<anchor.web3.AccountMeta[]> {
+  if (!dialect.archiveEnabled) {
+    return [];
+  }
+  const firstPageIndex = Math.max(
+    dialect.historyPagesCount - ARCHIVE_PAGES_COUNT,
+    0,
+  );
+  const accounts: anchor.web3.AccountMeta[] = [];
+  for (
+    let pageIndex = firstPageIndex;
+    pageIndex < dialect.historyPagesCount;
+    pageIndex++
+  ) {
+    const [historyPage] = await getMessageHistoryPageProgramAddress(
+      program,
+      publicKey,
+      pageIndex,
+    );
+    accounts.push({ pubkey: historyPage, isWritable: true, isSigner: false });
+  }
+  return accounts;
+}
+
+export async function getMessageHistoryPageProgramAddress(
+  program: anchor.Program,
+  dialect: PublicKey,
+  pageIndex: number,
+): Promise<[anchor.web3.PublicKey, number]> {
+  const pageIndexBuffer = Buffer.alloc(2);
+  pageIndexBuffer.writeUInt16LE(pageIndex);
+  return await anchor.web3.PublicKey.findProgramAddress(
+    [Buffer.from('history'), dialect.toBuffer(), pageIndexBuffer],
+    program.programId,
+  );
+}
+
+// Creates the dialect's next message history page, paid by the member
+export async function createMessageHistoryPage(
+  program: anchor.Program,
+  { dialect, publicKey }: DialectAccount,
+  payer: anchor.web3.Keypair | Wallet,
+  encryptionProps?: EncryptionProps | null,
+): Promise<DialectAccount> {
+  const pageIndex = dialect.historyPagesCount;
+  const [historyPage] = await getMessageHistoryPageProgramAddress(
+    program,
+    publicKey,
+    pageIndex,
+  );
+  const tx = await program.rpc.createMessageHistoryPage(pageIndex, {
+    accounts: {
+      payer: payer.publicKey,
+      dialect: publicKey,
+      historyPage,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    },
+    signers: 'secretKey' in payer ? [payer] : [],
+  });
+  await waitForFinality(program, tx);
+  return await getDialect(program, publicKey, encryptionProps);
+}
+
+// Returns the messages archived in the dialect's message history pages, latest first
+export async function getMessageHistory(
+  program: anchor.Program,
+  publicKey: PublicKey,
+  encryptionProps?: EncryptionProps | null,
+): Promise<Message[]> {
+  const account = await program.provider.connection.getAccountInfo(publicKey);
+  if (!account) {
+    throw new Error(`Dialect account ${publicKey.toBase58()} does not exist`);
+  }
+  // Legacy dialects have no message history
+  if (isLegacyDialectAccountData(account.data)) {
+    return [];
+  }
+  const rawDialect = program.coder.accounts.decode(
+    'DialectAccount',
+    account.data,
+  ) as RawDialect;
+  if (rawDialect.encrypted && !encryptionProps) {
+    return [];
+  }
+  const memberSlots = parseMemberSlots(rawDialect, account.data);
+  const textSerde = TextSerdeFactory.create(
+    {
+      encrypted: rawDialect.encrypted,
+      memberPubKeys: memberSlots.map((it) => it.publicKey),
+    },
+    encryptionProps,
+  );
+  const items: (Message | ReactionItem)[] = [];
+  for (
+    let pageIndex = 0;
+    pageIndex < rawDialect.historyPagesCount;
+    pageIndex++
+  ) {
+    const [pageAddress] = await getMessageHistoryPageProgramAddress(
+      program,
+      publicKey,
+      pageIndex,
+    );
+    const page = await program.account.messageHistoryPage.fetch(pageAddress);
+    const buffer = Buffer.from(page.buffer as number[]);
+    // Archived messages are stored one after another, each prefixed with its size
+    let offset = 0;
+    for (let i = 0; i < page.itemsCount; i++) {
+      const size = buffer.readUInt16BE(offset);
+      const item = buffer.subarray(offset + 2, offset + 2 + size);
+      items.push(parseItem(item, memberSlots, textSerde));
+      offset += 2 + size;
+    }
+  }
+  return foldItems(items).reverse();
+}
+
+/*
+Broadcast channels
+*/
+
+export async function getBroadcastChannelProgramAddress(
+  program: anchor.Program,
+  publisher: PublicKey,
+): Promise<[anchor.web3.PublicKey, number]> {
+  return await anchor.web3.PublicKey.findProgramAddress(
+    [Buffer.from('broadcast_channel'), publisher.toBuffer()],
+    program.programId,
+  );
+}
+
+// Returns the addresses of the broadcast channels among the subscriptions' addresses
+async function filterBroadcastChannels(
+  program: anchor.Program,
+  publicKeys: PublicKey[],
+): Promise<PublicKey[]> {
+  if (publicKeys.length === 0) {
+    return [];
+  }
+  const discriminator =
+    anchor.BorshAccountsCoder.accountDiscriminator('BroadcastChannel');
+  const accountInfos =
+    await program.provider.connection.getMultipleAccountsInfo(publicKeys);
+  return publicKeys.filter((_, idx) =>
+    accountInfos[idx]?.data.subarray(0, 8).equals(discriminator),
+  );
+}
+
+export async function getBroadcastChannel(
+  program: anchor.Program,
+  publicKey: PublicKey,
+): Promise<BroadcastChannelAccount> {
+  const account = await program.provider.connection.getAccountInfo(publicKey);
+  if (!account) {
+    throw new Error(
+      `Broadcast channel account ${publicKey.toBase58()} does not exist`,
+    );
+  }
+  const rawChannel = program.coder.accounts.decode(
+    'BroadcastChannel',
+    account.data,
+  ) as RawBroadcastChannel;
+  // Broadcast messages are stored as dialect messages sent by member slot 0
+  const publisher: Member = {
+    publicKey: rawChannel.publisher,
+    scopes: [true, true],
+  };
+  const buffer = account.data.subarray(
+    account.data.length - rawChannel.messages.capacity,
+  );
+  return {
+    publicKey,
+    channel: {
+      publisher: rawChannel.publisher,
+      messages: parseMessages(
+        {
+          messages: rawChannel.messages,
+          members: [publisher],
+          encrypted: false,
+        },
+        buffer,
+      ),
+      lastMessageTimestamp: rawChannel.lastMessageTimestamp * 1000,
+      nextMessageId: rawChannel.nextMessageId.toNumber(),
+      messageBufferCapacity: rawChannel.messages.capacity,
+    },
+  };
+}
+
+// Returns the broadcast channels the user is subscribed to, see subscribeToBroadcastChannel
+export async function getBroadcastChannels(
+  program: anchor.Program,
+  user: PublicKey,
+): Promise<BroadcastChannelAccount[]> {
+  const metadata = await getMetadata(program, user);
+  const subscriptions = metadata.subscriptions.filter((it) => it.enabled);
+  const channels = await filterBroadcastChannels(
+    program,
+    subscriptions.map((it) => it.pubkey),
+  );
+  return Promise.all(
+    subscriptions
+      .filter(({ pubkey }) => channels.some((it) => it.equals(pubkey)))
+      .map(async (subscription) => {
+        const account = await getBroadcastChannel(program, subscription.pubkey);
+        const topics = await getSubscriptionTopics(program, account, user);
+        // Only the messages of the topics the user is subscribed to are kept
+        account.channel.messages = account.channel.messages.filter(
+          ({ topic }) => isSubscribedToTopic(topics, topic),
+        );
+        return account;
+      }),
+  ).then((channels) =>
+    channels.sort(
+      ({ channel: c1 }, { channel: c2 }) =>
+        c2.lastMessageTimestamp - c1.lastMessageTimestamp,
+    ),
+  );
+}
+
+// A publisher has a single broadcast channel, in which only they broadcast messages
+export async function createBroadcastChannel(
+  program: anchor.Program,
+  publisher: anchor.web3.Keypair | Wallet,
+  capacity = DEFAULT_MESSAGE_BUFFER_LENGTH,
+): Promise<BroadcastChannelAccount> {
+  const [publicKey, nonce] = await getBroadcastChannelProgramAddress(
+    program,
+    publisher.publicKey,
   );
-  const tx = await program.rpc.createDialect(
+  const tx = await program.rpc.createBroadcastChannel(
     new anchor.BN(nonce),
-    encrypted,
-    sortedMembers.map((m) => m.scopes),
+    capacity,
     {
       accounts: {
-        dialect: publicKey,
-        owner: owner.publicKey,
-        ...keyedMembers,
+        publisher: publisher.publicKey,
+        channel: publicKey,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
         systemProgram: anchor.web3.SystemProgram.programId,
       },
-      signers: 'secretKey' in owner ? [owner] : [],
+      signers: 'secretKey' in publisher ? [publisher] : [],
     },
   );
   await waitForFinality(program, tx);
-  return await getDialectForMembers(program, members, encryptionProps);
+  return await getBroadcastChannel(program, publicKey);
 }
 
-export async function deleteDialect(
+export async function closeBroadcastChannel(
   program: anchor.Program,
-  { dialect }: DialectAccount,
-  owner: anchor.web3.Keypair | Wallet,
+  { publicKey }: BroadcastChannelAccount,
+  publisher: anchor.web3.Keypair | Wallet,
 ): Promise<void> {
-  const [dialectPublicKey, nonce] = await getDialectProgramAddress(
+  const [, nonce] = await getBroadcastChannelProgramAddress(
     program,
-    dialect.members,
+    publisher.publicKey,
   );
-  await program.rpc.closeDialect(new anchor.BN(nonce), {
+  const tx = await program.rpc.closeBroadcastChannel(new anchor.BN(nonce), {
     accounts: {
-      dialect: dialectPublicKey,
-      owner: owner.publicKey,
+      publisher: publisher.publicKey,
+      channel: publicKey,
       rent: anchor.web3.SYSVAR_RENT_PUBKEY,
       systemProgram: anchor.web3.SystemProgram.programId,
     },
-    signers: 'secretKey' in owner ? [owner] : [],
+    signers: 'secretKey' in publisher ? [publisher] : [],
   });
+  await waitForFinality(program, tx);
 }
 
-/*
-Members
-*/
-
-export type Member = {
-  publicKey: anchor.web3.PublicKey;
-  scopes: [boolean, boolean];
-};
-
-/*
-Messages
-*/
-
-export async function sendMessage(
+export async function broadcastMessage(
   program: anchor.Program,
-  { dialect, publicKey }: DialectAccount,
-  sender: anchor.web3.Keypair | Wallet,
+  { publicKey }: BroadcastChannelAccount,
+  publisher: anchor.web3.Keypair | Wallet,
   text: string,
-  encryptionProps?: EncryptionProps | null,
+  contentType = ContentType.TextPlain,
+  topic?: number,
 ): Promise<Message> {
-  const [dialectPublicKey, nonce] = await getDialectProgramAddress(
+  const [, nonce] = await getBroadcastChannelProgramAddress(
     program,
-    dialect.members,
+    publisher.publicKey,
   );
-  const textSerde = TextSerdeFactory.create(
+  // Broadcast messages are public, so they are never encrypted
+  const textSerde = TextSerdeFactory.create({
+    encrypted: false,
+    memberPubKeys: [publisher.publicKey],
+  });
+  const tx = await program.rpc.broadcastMessage(
+    new anchor.BN(nonce),
+    Buffer.from(textSerde.serialize(text)),
+    contentTypeArgs[contentType],
+    topic ?? null,
     {
-      encrypted: dialect.encrypted,
-      memberPubKeys: dialect.members.map((it) => it.publicKey),
+      accounts: {
+        publisher: publisher.publicKey,
+        channel: publicKey,
+      },
+      signers: 'secretKey' in publisher ? [publisher] : [],
     },
-    encryptionProps,
   );
-  const serializedText = textSerde.serialize(text);
-  await program.rpc.sendMessage(
-    new anchor.BN(nonce),
-    Buffer.from(serializedText),
+  await waitForFinality(program, tx);
+  const { channel } = await getBroadcastChannel(program, publicKey);
+  return channel.messages[0];
+}
+
+// Users subscribe themselves to broadcast channels, and unsubscribe with unsubscribeUser
+export async function subscribeToBroadcastChannel(
+  program: anchor.Program,
+  { publicKey }: BroadcastChannelAccount,
+  user: anchor.web3.Keypair | Wallet,
+): Promise<Metadata> {
+  const [metadata, metadataNonce] = await getMetadataProgramAddress(
+    program,
+    user.publicKey,
+  );
+  const tx = await program.rpc.subscribeToBroadcastChannel(
+    new anchor.BN(metadataNonce),
     {
       accounts: {
-        dialect: dialectPublicKey,
-        sender: sender ? sender.publicKey : program.provider.wallet.publicKey,
-        member0: dialect.members[0].publicKey,
-        member1: dialect.members[1].publicKey,
-        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
-        systemProgram: anchor.web3.SystemProgram.programId,
+        user: user.publicKey,
+        metadata,
+        channel: publicKey,
       },
-      signers: sender && 'secretKey' in sender ? [sender] : [],
+      remainingAccounts: await getMetadataPagesAccounts(
+        program,
+        user.publicKey,
+      ),
+      signers: 'secretKey' in user ? [user] : [],
     },
   );
-  const d = await getDialect(program, publicKey, encryptionProps);
-  return d.dialect.messages[0]; // TODO: Support ring
+  await waitForFinality(program, tx);
+  return await getMetadata(program, user.publicKey);
+}
+
+export async function getSubscriptionTopicsProgramAddress(
+  program: anchor.Program,
+  user: PublicKey,
+  channel: PublicKey,
+): Promise<[anchor.web3.PublicKey, number]> {
+  return await anchor.web3.PublicKey.findProgramAddress(
+    [Buffer.from('subscription_topics'), user.toBuffer(), channel.toBuffer()],
+    program.programId,
+  );
+}
+
+// Returns the topics of the user's subscription to the channel, bit i for
+// topic i. Users without a subscription topics account have all topics.
+export async function getSubscriptionTopics(
+  program: anchor.Program,
+  { publicKey }: BroadcastChannelAccount,
+  user: PublicKey,
+): Promise<anchor.BN> {
+  const [address] = await getSubscriptionTopicsProgramAddress(
+    program,
+    user,
+    publicKey,
+  );
+  const subscriptionTopics =
+    await program.account.subscriptionTopics.fetchNullable(address);
+  return subscriptionTopics
+    ? (subscriptionTopics.topics as anchor.BN)
+    : ALL_TOPICS;
+}
+
+// Messages without a topic are delivered to all the subscribers
+export function isSubscribedToTopic(
+  topics: anchor.BN,
+  topic?: number,
+): boolean {
+  return topic === undefined || topics.testn(topic);
+}
+
+// Subscribers only receive the broadcast messages of the given topics, and
+// those without a topic. Creates the user's subscription topics account if it
+// doesn't exist yet.
+export async function setSubscriptionTopics(
+  program: anchor.Program,
+  channel: BroadcastChannelAccount,
+  user: anchor.web3.Keypair | Wallet,
+  topics: number[],
+): Promise<anchor.BN> {
+  const [subscriptionTopics] = await getSubscriptionTopicsProgramAddress(
+    program,
+    user.publicKey,
+    channel.publicKey,
+  );
+  const mask = topics.reduce(
+    (mask, topic) => mask.or(new anchor.BN(1).shln(topic)),
+    new anchor.BN(0),
+  );
+  const subscriptionTopicsAccount =
+    await program.provider.connection.getAccountInfo(subscriptionTopics);
+  const tx = subscriptionTopicsAccount
+    ? await program.rpc.setSubscriptionTopics(mask, {
+        accounts: {
+          user: user.publicKey,
+          subscriptionTopics,
+          channel: channel.publicKey,
+        },
+        signers: 'secretKey' in user ? [user] : [],
+      })
+    : await program.rpc.createSubscriptionTopics(mask, {
+        accounts: {
+          user: user.publicKey,
+          subscriptionTopics,
+          channel: channel.publicKey,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        signers: 'secretKey' in user ? [user] : [],
+      });
+  await waitForFinality(program, tx);
+  return await getSubscriptionTopics(program, channel, user.publicKey);
+}
+
+// The user's subscription then includes all topics again
+export async function deleteSubscriptionTopics(
+  program: anchor.Program,
+  { publicKey }: BroadcastChannelAccount,
+  user: anchor.web3.Keypair | Wallet,
+): Promise<void> {
+  const [subscriptionTopics] = await getSubscriptionTopicsProgramAddress(
+    program,
+    user.publicKey,
+    publicKey,
+  );
+  await program.rpc.closeSubscriptionTopics({
+    accounts: {
+      user: user.publicKey,
+      subscriptionTopics,
+      channel: publicKey,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    },
+    signers: 'secretKey' in user ? [user] : [],
+  });
 }
 
 // Events
@@ -510,10 +2264,29 @@ export async function sendMessage(
 export type Event =
   | DialectCreatedEvent
   | DialectDeletedEvent
+  | DialectOwnershipTransferredEvent
+  | MemberAddedEvent
+  | MemberRemovedEvent
+  | MemberScopesUpdatedEvent
   | MetadataCreatedEvent
   | MetadataDeletedEvent
   | MessageSentEvent
-  | UserSubscribedEvent;
+  | MessageEditedEvent
+  | MessageReactedEvent
+  | MessageDeletedEvent
+  | MessagesReadEvent
+  | MessagesPrunedEvent
+  | MessagePinnedEvent
+  | MessageUnpinnedEvent
+  | BroadcastChannelCreatedEvent
+  | BroadcastChannelDeletedEvent
+  | BroadcastMessageSentEvent
+  | UserSubscribedEvent
+  | UserUnsubscribedEvent
+  | SubscriptionEnabledUpdatedEvent
+  | SubscriptionTopicsUpdatedEvent
+  | UserInvitedEvent
+  | InviteRejectedEvent;
 
 export interface DialectCreatedEvent {
   type: 'dialect-created';
@@ -527,6 +2300,33 @@ export interface DialectDeletedEvent {
   members: PublicKey[];
 }
 
+export interface DialectOwnershipTransferredEvent {
+  type: 'dialect-ownership-transferred';
+  dialect: PublicKey;
+  previousOwner: PublicKey;
+  newOwner: PublicKey;
+}
+
+export interface MemberAddedEvent {
+  type: 'member-added';
+  dialect: PublicKey;
+  member: PublicKey;
+  scopes: [boolean, boolean];
+}
+
+export interface MemberRemovedEvent {
+  type: 'member-removed';
+  dialect: PublicKey;
+  member: PublicKey;
+}
+
+export interface MemberScopesUpdatedEvent {
+  type: 'member-scopes-updated';
+  dialect: PublicKey;
+  member: PublicKey;
+  scopes: [boolean, boolean];
+}
+
 export interface MetadataCreatedEvent {
   type: 'metadata-created';
   metadata: PublicKey;
@@ -543,14 +2343,132 @@ export interface MessageSentEvent {
   type: 'message-sent';
   dialect: PublicKey;
   sender: PublicKey;
+  id: number;
+  timestamp: number;
+  length: number;
+  payloadHash: Uint8Array;
+  // Only if the dialect emits message payloads, see setEmitMessagePayloads
+  payload: Uint8Array | null;
+  replyTo: number | null;
+  contentType: ContentType;
+  ttl: number | null;
+}
+
+export interface MessageEditedEvent {
+  type: 'message-edited';
+  dialect: PublicKey;
+  sender: PublicKey;
+  id: number;
+  editedMessageId: number;
+}
+
+export interface MessageReactedEvent {
+  type: 'message-reacted';
+  dialect: PublicKey;
+  sender: PublicKey;
+  messageId: number;
+  reaction: number;
+}
+
+export interface MessageDeletedEvent {
+  type: 'message-deleted';
+  dialect: PublicKey;
+  id: number;
+  deletedBy: PublicKey;
+}
+
+export interface MessagesReadEvent {
+  type: 'messages-read';
+  dialect: PublicKey;
+  member: PublicKey;
+  readUntil: number;
+}
+
+export interface MessagesPrunedEvent {
+  type: 'messages-pruned';
+  dialect: PublicKey;
+  prunedItemsCount: number;
+}
+
+export interface MessagePinnedEvent {
+  type: 'message-pinned';
+  dialect: PublicKey;
+  messageId: number;
+  pinnedBy: PublicKey;
+}
+
+export interface MessageUnpinnedEvent {
+  type: 'message-unpinned';
+  dialect: PublicKey;
+  messageId: number;
+  unpinnedBy: PublicKey;
+}
+
+export interface BroadcastChannelCreatedEvent {
+  type: 'broadcast-channel-created';
+  channel: PublicKey;
+  publisher: PublicKey;
+}
+
+export interface BroadcastChannelDeletedEvent {
+  type: 'broadcast-channel-deleted';
+  channel: PublicKey;
+  publisher: PublicKey;
 }
 
+export interface BroadcastMessageSentEvent {
+  type: 'broadcast-message-sent';
+  channel: PublicKey;
+  publisher: PublicKey;
+  id: number;
+  timestamp: number;
+  length: number;
+  payloadHash: Uint8Array;
+  payload: Uint8Array;
+  contentType: ContentType;
+  topic: number | null;
+}
+
+// Also fired when users subscribe to broadcast channels, whose address is then the dialect
 export interface UserSubscribedEvent {
   type: 'user-subscribed';
   metadata: PublicKey;
   dialect: PublicKey;
 }
 
+export interface UserUnsubscribedEvent {
+  type: 'user-unsubscribed';
+  metadata: PublicKey;
+  dialect: PublicKey;
+}
+
+export interface SubscriptionEnabledUpdatedEvent {
+  type: 'subscription-enabled-updated';
+  metadata: PublicKey;
+  dialect: PublicKey;
+  enabled: boolean;
+}
+
+export interface SubscriptionTopicsUpdatedEvent {
+  type: 'subscription-topics-updated';
+  subscriptionTopics: PublicKey;
+  channel: PublicKey;
+  topics: anchor.BN;
+}
+
+export interface UserInvitedEvent {
+  type: 'user-invited';
+  metadataPolicy: PublicKey;
+  dialect: PublicKey;
+  inviter: PublicKey;
+}
+
+export interface InviteRejectedEvent {
+  type: 'invite-rejected';
+  metadataPolicy: PublicKey;
+  dialect: PublicKey;
+}
+
 export type EventHandler = (event: Event) => Promise<any>;
 
 export interface EventSubscription {
@@ -600,11 +2518,145 @@ class DefaultSubscription implements EventSubscription {
                   members: event.data.members as PublicKey[],
                 });
                 break;
+              case 'DialectOwnershipTransferredEvent':
+                this.eventHandler({
+                  type: 'dialect-ownership-transferred',
+                  dialect: event.data.dialect as PublicKey,
+                  previousOwner: event.data.previousOwner as PublicKey,
+                  newOwner: event.data.newOwner as PublicKey,
+                });
+                break;
+              case 'MemberAddedEvent':
+                this.eventHandler({
+                  type: 'member-added',
+                  dialect: event.data.dialect as PublicKey,
+                  member: event.data.member as PublicKey,
+                  scopes: event.data.scopes as [boolean, boolean],
+                });
+                break;
+              case 'MemberRemovedEvent':
+                this.eventHandler({
+                  type: 'member-removed',
+                  dialect: event.data.dialect as PublicKey,
+                  member: event.data.member as PublicKey,
+                });
+                break;
+              case 'MemberScopesUpdatedEvent':
+                this.eventHandler({
+                  type: 'member-scopes-updated',
+                  dialect: event.data.dialect as PublicKey,
+                  member: event.data.member as PublicKey,
+                  scopes: event.data.scopes as [boolean, boolean],
+                });
+                break;
               case 'MessageSentEvent':
                 this.eventHandler({
                   type: 'message-sent',
                   dialect: event.data.dialect as PublicKey,
                   sender: event.data.sender as PublicKey,
+                  id: (event.data.id as anchor.BN).toNumber(),
+                  timestamp: (event.data.timestamp as number) * 1000,
+                  length: event.data.length as number,
+                  payloadHash: new Uint8Array(
+                    event.data.payloadHash as number[],
+                  ),
+                  payload: event.data.payload
+                    ? new Uint8Array(event.data.payload as Buffer)
+                    : null,
+                  replyTo: event.data.replyTo
+                    ? (event.data.replyTo as anchor.BN).toNumber()
+                    : null,
+                  contentType: event.data.contentType as ContentType,
+                  ttl: (event.data.ttl as number | null) ?? null,
+                });
+                break;
+              case 'MessageEditedEvent':
+                this.eventHandler({
+                  type: 'message-edited',
+                  dialect: event.data.dialect as PublicKey,
+                  sender: event.data.sender as PublicKey,
+                  id: (event.data.id as anchor.BN).toNumber(),
+                  editedMessageId: (
+                    event.data.editedMessageId as anchor.BN
+                  ).toNumber(),
+                });
+                break;
+              case 'MessageReactedEvent':
+                this.eventHandler({
+                  type: 'message-reacted',
+                  dialect: event.data.dialect as PublicKey,
+                  sender: event.data.sender as PublicKey,
+                  messageId: (event.data.messageId as anchor.BN).toNumber(),
+                  reaction: event.data.reaction as number,
+                });
+                break;
+              case 'MessageDeletedEvent':
+                this.eventHandler({
+                  type: 'message-deleted',
+                  dialect: event.data.dialect as PublicKey,
+                  id: (event.data.id as anchor.BN).toNumber(),
+                  deletedBy: event.data.deletedBy as PublicKey,
+                });
+                break;
+              case 'MessagesReadEvent':
+                this.eventHandler({
+                  type: 'messages-read',
+                  dialect: event.data.dialect as PublicKey,
+                  member: event.data.member as PublicKey,
+                  readUntil: (event.data.readUntil as anchor.BN).toNumber(),
+                });
+                break;
+              case 'MessagesPrunedEvent':
+                this.eventHandler({
+                  type: 'messages-pruned',
+                  dialect: event.data.dialect as PublicKey,
+                  prunedItemsCount: event.data.prunedItemsCount as number,
+                });
+                break;
+              case 'MessagePinnedEvent':
+                this.eventHandler({
+                  type: 'message-pinned',
+                  dialect: event.data.dialect as PublicKey,
+                  messageId: (event.data.messageId as anchor.BN).toNumber(),
+                  pinnedBy: event.data.pinnedBy as PublicKey,
+                });
+                break;
+              case 'MessageUnpinnedEvent':
+                this.eventHandler({
+                  type: 'message-unpinned',
+                  dialect: event.data.dialect as PublicKey,
+                  messageId: (event.data.messageId as anchor.BN).toNumber(),
+                  unpinnedBy: event.data.unpinnedBy as PublicKey,
+                });
+                break;
+              case 'BroadcastChannelCreatedEvent':
+                this.eventHandler({
+                  type: 'broadcast-channel-created',
+                  channel: event.data.channel as PublicKey,
+                  publisher: event.data.publisher as PublicKey,
+                });
+                break;
+              case 'BroadcastChannelDeletedEvent':
+                this.eventHandler({
+                  type: 'broadcast-channel-deleted',
+                  channel: event.data.channel as PublicKey,
+                  publisher: event.data.publisher as PublicKey,
+                });
+                break;
+              case 'BroadcastMessageSentEvent':
+                this.eventHandler({
+                  type: 'broadcast-message-sent',
+                  channel: event.data.channel as PublicKey,
+                  publisher: event.data.publisher as PublicKey,
+                  id: (event.data.id as anchor.BN).toNumber(),
+                  timestamp: (event.data.timestamp as number) * 1000,
+                  length: event.data.length as number,
+                  payloadHash: new Uint8Array(
+                    event.data.payloadHash as number[],
+                  ),
+                  payload: new Uint8Array(event.data.payload as Buffer),
+                  contentType: event.data.contentType as ContentType,
+                  topic: (event.data.topic as number | null) ?? null,
                 });
                 break;
               case 'UserSubscribedEvent':
@@ -614,6 +2666,45 @@ class DefaultSubscription implements EventSubscription {
                   dialect: event.data.dialect as PublicKey,
                 });
                 break;
+              case 'UserUnsubscribedEvent':
+                this.eventHandler({
+                  type: 'user-unsubscribed',
+                  metadata: event.data.metadata as PublicKey,
+                  dialect: event.data.dialect as PublicKey,
+                });
+                break;
+              case 'SubscriptionEnabledUpdatedEvent':
+                this.eventHandler({
+                  type: 'subscription-enabled-updated',
+                  metadata: event.data.metadata as PublicKey,
+                  dialect: event.data.dialect as PublicKey,
+                  enabled: event.data.enabled as boolean,
+                });
+                break;
+              case 'SubscriptionTopicsUpdatedEvent':
+                this.eventHandler({
+                  type: 'subscription-topics-updated',
+                  subscriptionTopics: event.data
+                    .subscriptionTopics as PublicKey,
+                  channel: event.data.channel as PublicKey,
+                  topics: event.data.topics as anchor.BN,
+                });
+                break;
+              case 'UserInvitedEvent':
+                this.eventHandler({
+                  type: 'user-invited',
+                  metadataPolicy: event.data.metadataPolicy as PublicKey,
+                  dialect: event.data.dialect as PublicKey,
+                  inviter: event.data.inviter as PublicKey,
+                });
+                break;
+              case 'InviteRejectedEvent':
+                this.eventHandler({
+                  type: 'invite-rejected',
+                  metadataPolicy: event.data.metadataPolicy as PublicKey,
+                  dialect: event.data.dialect as PublicKey,
+                });
+                break;
               case 'MetadataCreatedEvent':
                 this.eventHandler({
                   type: 'metadata-created',
@@ -4,22 +4,73 @@ import * as web3 from '@solana/web3.js';
 import chai, { expect } from 'chai';
 import chaiAsPromised from 'chai-as-promised';
 import {
+  acceptInvite,
+  addMember,
+  BroadcastChannelAccount,
+  broadcastMessage,
+  closeBroadcastChannel,
+  ContentType,
+  createBroadcastChannel,
   createDialect,
+  createGroupDialect,
   createMetadata,
+  createMessageHistoryPage,
+  createMetadataPage,
+  DEFAULT_MAX_DIALECT_MEMBERS,
+  DEFAULT_MESSAGE_BUFFER_LENGTH,
   deleteDialect,
+  deleteMessage,
   deleteMetadata,
+  deleteMetadataPage,
+  deleteSubscriptionTopics,
+  DEFAULT_EDIT_WINDOW,
   DialectAccount,
+  editMessage,
   Event,
   findDialects,
+  getBroadcastChannel,
+  getBroadcastChannels,
   getDialect,
   getDialectForMembers,
   getDialectProgramAddress,
   getDialects,
+  getMessageHistory,
+  getMessageHistoryPageProgramAddress,
   getMetadata,
+  getSubscriptionTopics,
+  getUnreadMessagesCount,
+  hashMembers,
+  markRead,
+  MAX_MESSAGE_BUFFER_LENGTH,
+  MAX_TOPICS,
+  MIN_MESSAGE_BUFFER_LENGTH,
   Member,
+  MessageSentEvent,
+  pinMessage,
+  pruneExpired,
+  react,
+  rejectInvite,
+  removeMember,
+  resizeDialect,
   sendMessage,
+  setArchiveEnabled,
+  setEditWindow,
+  setEmitMessagePayloads,
+  setMaxMembers,
+  setMaxMessageLength,
+  setSubscriptionEnabled,
+  setSubscriptionPolicy,
+  setSubscriptionTopics,
+  subscribeToBroadcastChannel,
   subscribeToEvents,
   subscribeUser,
+  subscribeUserToPage,
+  SubscriptionPolicy,
+  transferDialectOwnership,
+  unpinMessage,
+  unsubscribeUser,
+  unsubscribeUserFromPage,
+  updateMemberScopes,
 } from '../src/api';
 import { sleep } from '../src/utils';
 import { ITEM_METADATA_OVERHEAD } from '../src/utils/cyclic-bytebuffer';
@@ -29,6 +80,7 @@ import {
 } from '../src/utils/ecdh-encryption';
 import { NONCE_SIZE_BYTES } from '../src/utils/nonce-generator';
 import { randomInt } from 'crypto';
+import { sha256 } from 'js-sha256';
 import { CountDownLatch } from '../src/utils/countdown-latch';
 import { EncryptionProps } from '../src/api/text-serde';
 
@@ -206,6 +258,55 @@ describe('Protocol v1 test', () => {
       expect(dialectAccount.dialect.encrypted).to.be.false;
     });
 
+    it('Create a dialect with a small message buffer', async () => {
+      const dialect = await createDialect(
+        program,
+        owner,
+        members,
+        false,
+        null,
+        MIN_MESSAGE_BUFFER_LENGTH,
+      );
+      chai
+        .expect(dialect.dialect.messageBufferCapacity)
+        .to.be.eq(MIN_MESSAGE_BUFFER_LENGTH);
+      const text = generateRandomText(256);
+      await sendMessage(program, dialect, writer, text);
+      const d = await getDialect(program, dialect.publicKey);
+      chai.expect(d.dialect.messages.map((m) => m.text)).to.be.deep.eq([text]);
+    });
+
+    it('Fail to create a dialect with a message buffer capacity out of bounds', async () => {
+      await chai
+        .expect(
+          createDialect(
+            program,
+            owner,
+            members,
+            false,
+            null,
+            MIN_MESSAGE_BUFFER_LENGTH - 1,
+          ),
+        )
+        .to.eventually.be.rejectedWith(
+          'The message buffer capacity is out of bounds',
+        );
+      await chai
+        .expect(
+          createDialect(
+            program,
+            owner,
+            members,
+            false,
+            null,
+            MAX_MESSAGE_BUFFER_LENGTH,
+          ),
+        )
+        .to.eventually.be.rejectedWith(
+          'The message buffer capacity is out of bounds',
+        );
+    });
+
     it('Fail to create a second dialect for the same members', async () => {
       chai
         .expect(createDialect(program, owner, members))
@@ -333,6 +434,40 @@ describe('Protocol v1 test', () => {
         .to.eventually.be.rejectedWith(Error);
     });
 
+    it('Creator is recorded as the dialect owner', async () => {
+      const dialect = await createDialect(program, owner, members);
+      chai.expect(dialect.dialect.owner).to.be.deep.eq(owner.publicKey);
+    });
+
+    it('Rent is refunded to the recorded owner after the ownership is transferred', async () => {
+      const dialect = await createDialect(program, owner, members);
+      await chai
+        .expect(
+          transferDialectOwnership(
+            program,
+            dialect,
+            writer,
+            nonmember.publicKey,
+          ),
+        )
+        .to.eventually.be.rejectedWith(
+          'The account is not the recorded owner of the dialect',
+        );
+      const transferred = await transferDialectOwnership(
+        program,
+        dialect,
+        owner,
+        nonmember.publicKey,
+      );
+      chai.expect(transferred.dialect.owner).to.be.deep.eq(nonmember.publicKey);
+      const connection = program.provider.connection;
+      const rent = await connection.getBalance(dialect.publicKey);
+      const balanceBefore = await connection.getBalance(nonmember.publicKey);
+      await deleteDialect(program, transferred, owner);
+      const balanceAfter = await connection.getBalance(nonmember.publicKey);
+      chai.expect(balanceAfter - balanceBefore).to.be.eq(rent);
+    });
+
     it('Owner deletes the dialect', async () => {
       const dialect = await createDialect(program, owner, members);
       await deleteDialect(program, dialect, owner);
@@ -367,6 +502,361 @@ describe('Protocol v1 test', () => {
     });
   });
 
+  describe('Subscription management tests', () => {
+    let owner: web3.Keypair;
+    let writer: web3.Keypair;
+
+    beforeEach(async () => {
+      owner = await createUser({
+        requestAirdrop: true,
+        createMeta: true,
+      }).then((it) => it.user);
+      writer = await createUser({
+        requestAirdrop: true,
+        createMeta: true,
+      }).then((it) => it.user);
+    });
+
+    it('User unsubscribes from a dialect, remaining subscriptions are kept in order', async () => {
+      // given
+      const [user1, user2] = await Promise.all([
+        createUser({
+          requestAirdrop: true,
+          createMeta: false,
+        }).then((it) => it.user),
+        createUser({
+          requestAirdrop: true,
+          createMeta: false,
+        }).then((it) => it.user),
+      ]);
+      const dialect1 = await createDialectAndSubscribeAllMembers(
+        program,
+        owner,
+        writer,
+        false,
+      );
+      const dialect2 = await createDialect(program, owner, [
+        { publicKey: owner.publicKey, scopes: [true, true] },
+        { publicKey: user1.publicKey, scopes: [false, true] },
+      ]);
+      await subscribeUser(program, dialect2, owner.publicKey, owner);
+      const dialect3 = await createDialect(program, owner, [
+        { publicKey: owner.publicKey, scopes: [true, true] },
+        { publicKey: user2.publicKey, scopes: [false, true] },
+      ]);
+      await subscribeUser(program, dialect3, owner.publicKey, owner);
+      // when
+      const metadata = await unsubscribeUser(program, dialect2, owner);
+      // then
+      chai
+        .expect(metadata.subscriptions.map((s) => s.pubkey))
+        .to.be.deep.eq([dialect1.publicKey, dialect3.publicKey]);
+      // and the freed slot can be reused
+      const resubscribedMetadata = await subscribeUser(
+        program,
+        dialect2,
+        owner.publicKey,
+        owner,
+      );
+      chai
+        .expect(resubscribedMetadata.subscriptions.map((s) => s.pubkey))
+        .to.be.deep.eq([
+          dialect1.publicKey,
+          dialect3.publicKey,
+          dialect2.publicKey,
+        ]);
+    });
+
+    it('User can unsubscribe from a deleted dialect', async () => {
+      const dialect = await createDialectAndSubscribeAllMembers(
+        program,
+        owner,
+        writer,
+        false,
+      );
+      await deleteDialect(program, dialect, owner);
+      const metadata = await unsubscribeUser(program, dialect, writer);
+      chai.expect(metadata.subscriptions.length).to.be.eq(0);
+    });
+
+    it('User mutes and unmutes a dialect without removing the subscription', async () => {
+      const dialect = await createDialectAndSubscribeAllMembers(
+        program,
+        owner,
+        writer,
+        false,
+      );
+      // when
+      const mutedMetadata = await setSubscriptionEnabled(
+        program,
+        dialect,
+        writer,
+        false,
+      );
+      const dialectsWhenMuted = await getDialects(program, writer);
+      const unmutedMetadata = await setSubscriptionEnabled(
+        program,
+        dialect,
+        writer,
+        true,
+      );
+      const dialectsWhenUnmuted = await getDialects(program, writer);
+      // then
+      chai.expect(mutedMetadata.subscriptions.length).to.be.eq(1);
+      chai.expect(mutedMetadata.subscriptions[0].enabled).to.be.false;
+      chai.expect(dialectsWhenMuted.length).to.be.eq(0);
+      chai.expect(unmutedMetadata.subscriptions[0].enabled).to.be.true;
+      chai
+        .expect(dialectsWhenUnmuted.map((it) => it.publicKey))
+        .to.be.deep.eq([dialect.publicKey]);
+    });
+
+    it('Fail to subscribe when all subscription slots are taken, then subscribe using a metadata page', async () => {
+      // given
+      const dialects: DialectAccount[] = [];
+      for (let idx = 0; idx < 33; idx++) {
+        dialects.push(
+          await createDialect(program, owner, [
+            { publicKey: owner.publicKey, scopes: [true, true] },
+            {
+              publicKey: web3.Keypair.generate().publicKey,
+              scopes: [false, true],
+            },
+          ]),
+        );
+      }
+      for (const dialect of dialects.slice(0, 32)) {
+        await subscribeUser(program, dialect, owner.publicKey, owner);
+      }
+      // when / then
+      await chai
+        .expect(subscribeUser(program, dialects[32], owner.publicKey, owner))
+        .to.eventually.be.rejectedWith(
+          'No subscription slots left, use a metadata page for additional subscriptions',
+        );
+      await createMetadataPage(program, owner, 0);
+      const metadata = await subscribeUserToPage(
+        program,
+        dialects[32],
+        owner.publicKey,
+        owner,
+        0,
+      );
+      chai
+        .expect(metadata.subscriptions.map((s) => s.pubkey))
+        .to.be.deep.eq(dialects.map((d) => d.publicKey));
+      await unsubscribeUser(program, dialects[0], owner);
+      await chai
+        .expect(subscribeUser(program, dialects[32], owner.publicKey, owner))
+        .to.eventually.be.rejectedWith(
+          'The user is already subscribed to the dialect',
+        );
+      await subscribeUser(program, dialects[0], owner.publicKey, owner);
+      const metadataAfterUnsubscribe = await unsubscribeUserFromPage(
+        program,
+        dialects[32],
+        owner,
+        0,
+      );
+      chai.expect(metadataAfterUnsubscribe.subscriptions.length).to.be.eq(32);
+    });
+
+    it('Subscriptions in the metadata pages after a closed page are still checked', async () => {
+      // given
+      const dialects: DialectAccount[] = [];
+      for (let idx = 0; idx < 33; idx++) {
+        dialects.push(
+          await createDialect(program, owner, [
+            { publicKey: owner.publicKey, scopes: [true, true] },
+            {
+              publicKey: web3.Keypair.generate().publicKey,
+              scopes: [false, true],
+            },
+          ]),
+        );
+      }
+      for (const dialect of dialects.slice(0, 32)) {
+        await subscribeUser(program, dialect, owner.publicKey, owner);
+      }
+      await createMetadataPage(program, owner, 0);
+      await createMetadataPage(program, owner, 1);
+      await subscribeUserToPage(
+        program,
+        dialects[32],
+        owner.publicKey,
+        owner,
+        1,
+      );
+      // when
+      await deleteMetadataPage(program, owner, 0);
+      const metadata = await unsubscribeUser(program, dialects[0], owner);
+      // then
+      chai
+        .expect(metadata.subscriptions.map((s) => s.pubkey))
+        .to.be.deep.eq(dialects.slice(1).map((d) => d.publicKey));
+      await chai
+        .expect(subscribeUser(program, dialects[32], owner.publicKey, owner))
+        .to.eventually.be.rejectedWith(
+          'The user is already subscribed to the dialect',
+        );
+    });
+
+    it('Others cannot subscribe a user without a metadata policy account to dialects the user is not a member of', async () => {
+      // given
+      const dialect = await createDialect(program, owner, [
+        { publicKey: owner.publicKey, scopes: [true, true] },
+        {
+          publicKey: web3.Keypair.generate().publicKey,
+          scopes: [false, true],
+        },
+      ]);
+      // when / then
+      await chai
+        .expect(
+          subscribeUser(
+            program,
+            dialect,
+            writer.publicKey,
+            web3.Keypair.generate(),
+          ),
+        )
+        .to.eventually.be.rejectedWith(
+          'Only members of the dialect can invite users to it',
+        );
+      await chai
+        .expect(subscribeUser(program, dialect, writer.publicKey, owner))
+        .to.eventually.be.rejectedWith(
+          "The user's subscription policy does not allow the signer to subscribe them to the dialect",
+        );
+      const metadata = await getMetadata(program, writer.publicKey);
+      chai.expect(metadata.subscriptions.length).to.be.eq(0);
+      chai.expect(metadata.invites.length).to.be.eq(0);
+    });
+
+    it('Subscriptions by others to dialects the user is not a member of land in invites', async () => {
+      // given
+      const metadataBeforePolicy = await getMetadata(program, writer.publicKey);
+      chai
+        .expect(metadataBeforePolicy.subscriptionPolicy)
+        .to.be.eq(SubscriptionPolicy.MembersOnly);
+      await setSubscriptionPolicy(
+        program,
+        writer,
+        SubscriptionPolicy.MembersOnly,
+      );
+      const dialect = await createDialect(program, owner, [
+        { publicKey: owner.publicKey, scopes: [true, true] },
+        {
+          publicKey: web3.Keypair.generate().publicKey,
+          scopes: [false, true],
+        },
+      ]);
+      // when / then
+      await chai
+        .expect(
+          subscribeUser(
+            program,
+            dialect,
+            writer.publicKey,
+            web3.Keypair.generate(),
+          ),
+        )
+        .to.eventually.be.rejectedWith(
+          'Only members of the dialect can invite users to it',
+        );
+      const metadata = await subscribeUser(
+        program,
+        dialect,
+        writer.publicKey,
+        owner,
+      );
+      // then
+      chai
+        .expect(metadata.subscriptionPolicy)
+        .to.be.eq(SubscriptionPolicy.MembersOnly);
+      chai.expect(metadata.subscriptions.length).to.be.eq(0);
+      chai.expect(metadata.invites).to.be.deep.eq([dialect.publicKey]);
+      // and the user accepts the invite
+      const metadataAfterAccept = await acceptInvite(program, dialect, writer);
+      chai.expect(metadataAfterAccept.invites.length).to.be.eq(0);
+      chai
+        .expect(metadataAfterAccept.subscriptions.map((s) => s.pubkey))
+        .to.be.deep.eq([dialect.publicKey]);
+    });
+
+    it('All subscriptions by others land in invites with owner approval policy', async () => {
+      // given
+      await setSubscriptionPolicy(
+        program,
+        writer,
+        SubscriptionPolicy.OwnerApproval,
+      );
+      const dialect = await createDialect(program, owner, [
+        { publicKey: owner.publicKey, scopes: [true, true] },
+        { publicKey: writer.publicKey, scopes: [false, true] },
+      ]);
+      // when
+      const metadata = await subscribeUser(
+        program,
+        dialect,
+        writer.publicKey,
+        owner,
+      );
+      // then
+      chai.expect(metadata.subscriptions.length).to.be.eq(0);
+      chai.expect(metadata.invites).to.be.deep.eq([dialect.publicKey]);
+      // and the user rejects the invite
+      const metadataAfterReject = await rejectInvite(program, dialect, writer);
+      chai.expect(metadataAfterReject.invites.length).to.be.eq(0);
+      chai.expect(metadataAfterReject.subscriptions.length).to.be.eq(0);
+      // and a subscription by the user consumes a new invite
+      await subscribeUser(program, dialect, writer.publicKey, owner);
+      const metadataAfterSubscribe = await subscribeUser(
+        program,
+        dialect,
+        writer.publicKey,
+        writer,
+      );
+      chai.expect(metadataAfterSubscribe.invites.length).to.be.eq(0);
+      chai
+        .expect(metadataAfterSubscribe.subscriptions.map((s) => s.pubkey))
+        .to.be.deep.eq([dialect.publicKey]);
+    });
+
+    it('Others can subscribe the user to any dialect with open policy', async () => {
+      await setSubscriptionPolicy(program, writer, SubscriptionPolicy.Open);
+      const dialect = await createDialect(program, owner, [
+        { publicKey: owner.publicKey, scopes: [true, true] },
+        {
+          publicKey: web3.Keypair.generate().publicKey,
+          scopes: [false, true],
+        },
+      ]);
+      const metadata = await subscribeUser(
+        program,
+        dialect,
+        writer.publicKey,
+        owner,
+      );
+      chai.expect(metadata.invites.length).to.be.eq(0);
+      chai
+        .expect(metadata.subscriptions.map((s) => s.pubkey))
+        .to.be.deep.eq([dialect.publicKey]);
+    });
+
+    it('Fail to unsubscribe from a dialect the user is not subscribed to', async () => {
+      const dialect = await createDialect(program, owner, [
+        { publicKey: owner.publicKey, scopes: [true, true] },
+        { publicKey: writer.publicKey, scopes: [false, true] },
+      ]);
+      await chai
+        .expect(unsubscribeUser(program, dialect, writer))
+        .to.eventually.be.rejectedWith(
+          'The user is not subscribed to the dialect',
+        );
+    });
+  });
+
   describe('Find dialects', () => {
     it('Can find all dialects filtering by user public key', async () => {
       // given
@@ -456,146 +946,1091 @@ describe('Protocol v1 test', () => {
         user2User3Dialect.publicKey,
         user1User3Dialect.publicKey,
       ]);
-      expect(nonExistingUserDialects.length).to.be.eq(0);
+      expect(nonExistingUserDialects.length).to.be.eq(0);
+    });
+  });
+
+  describe('Group dialect tests', () => {
+    let owner: web3.Keypair;
+    let writer1: web3.Keypair;
+    let writer2: web3.Keypair;
+    let members: Member[] = [];
+
+    beforeEach(async () => {
+      [owner, writer1, writer2] = await Promise.all([
+        createUser({
+          requestAirdrop: true,
+          createMeta: false,
+        }).then((it) => it.user),
+        createUser({
+          requestAirdrop: true,
+          createMeta: false,
+        }).then((it) => it.user),
+        createUser({
+          requestAirdrop: true,
+          createMeta: false,
+        }).then((it) => it.user),
+      ]);
+      members = [
+        {
+          publicKey: owner.publicKey,
+          scopes: [true, true], // owner, read-write
+        },
+        {
+          publicKey: writer1.publicKey,
+          scopes: [false, true], // non-owner, read-write
+        },
+        {
+          publicKey: writer2.publicKey,
+          scopes: [false, true], // non-owner, read-write
+        },
+      ];
+    });
+
+    it('Create group dialect for 3 members', async () => {
+      const dialect = await createGroupDialect(program, owner, members);
+      chai
+        .expect(dialect.dialect.members.map((m) => m.publicKey))
+        .to.be.deep.eq(members.map((m) => m.publicKey));
+      const [foundDialect] = await findDialects(program, {
+        userPk: writer2.publicKey,
+      });
+      chai.expect(foundDialect.publicKey).to.be.deep.eq(dialect.publicKey);
+    });
+
+    it('Fail to create a group dialect with more than max members', async () => {
+      const tooManyMembers: Member[] = [
+        ...members,
+        ...Array(DEFAULT_MAX_DIALECT_MEMBERS + 1 - members.length)
+          .fill(0)
+          .map(
+            () =>
+              ({
+                publicKey: web3.Keypair.generate().publicKey,
+                scopes: [false, true],
+              } as Member),
+          ),
+      ];
+      await chai
+        .expect(createGroupDialect(program, owner, tooManyMembers))
+        .to.eventually.be.rejectedWith(
+          'A dialect must have between 2 and its max number of members',
+        );
+    });
+
+    it('Fail to create a group dialect when the members hash does not match the members', async () => {
+      const otherMembers = [
+        ...members,
+        {
+          publicKey: web3.Keypair.generate().publicKey,
+          scopes: [false, true],
+        } as Member,
+      ];
+      await chai
+        .expect(
+          program.rpc.createGroupDialect(
+            [...hashMembers(otherMembers)],
+            false,
+            members.map((m) => m.scopes),
+            DEFAULT_MESSAGE_BUFFER_LENGTH,
+            DEFAULT_MAX_DIALECT_MEMBERS,
+            {
+              accounts: {
+                dialect: (
+                  await getDialectProgramAddress(program, otherMembers)
+                )[0],
+                owner: owner.publicKey,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+                systemProgram: anchor.web3.SystemProgram.programId,
+              },
+              remainingAccounts: members
+                .map((m) => m.publicKey)
+                .sort((a, b) => a.toBuffer().compare(b.toBuffer()))
+                .map((pubkey) => ({
+                  pubkey,
+                  isWritable: false,
+                  isSigner: false,
+                })),
+              signers: [owner],
+            },
+          ),
+        )
+        .to.eventually.be.rejectedWith(
+          'The members hash does not match the dialect members',
+        );
+    });
+
+    it('All group members can send messages, messages are attributed to their senders', async () => {
+      const dialect = await createGroupDialect(program, owner, members);
+      const senders = [owner, writer1, writer2];
+      const texts = senders.map(() => generateRandomText(64));
+      for (let idx = 0; idx < senders.length; idx++) {
+        await sendMessage(program, dialect, senders[idx], texts[idx]);
+      }
+      const d = await getDialect(program, dialect.publicKey);
+      const actualMessages = d.dialect.messages.reverse();
+      chai.expect(actualMessages.map((m) => m.text)).to.be.deep.eq(texts);
+      chai
+        .expect(actualMessages.map((m) => m.owner))
+        .to.be.deep.eq(senders.map((s) => s.publicKey));
+    });
+
+    it('Admin adds and removes members, messages of removed members are still attributed to them', async () => {
+      const dialect = await createGroupDialect(program, owner, members);
+      const newMember = (
+        await createUser({
+          requestAirdrop: true,
+          createMeta: false,
+        })
+      ).user;
+      const withNewMember = await addMember(program, dialect, owner, {
+        publicKey: newMember.publicKey,
+        scopes: [false, true],
+      });
+      chai
+        .expect(withNewMember.dialect.members.map((m) => m.publicKey))
+        .to.deep.contain(newMember.publicKey);
+      const text = generateRandomText(64);
+      await sendMessage(program, withNewMember, newMember, text);
+      const withoutNewMember = await removeMember(
+        program,
+        withNewMember,
+        owner,
+        newMember.publicKey,
+      );
+      chai
+        .expect(withoutNewMember.dialect.members.map((m) => m.publicKey))
+        .not.to.deep.contain(newMember.publicKey);
+      const [message] = withoutNewMember.dialect.messages;
+      chai.expect(message.text).to.be.eq(text);
+      chai.expect(message.owner).to.be.deep.eq(newMember.publicKey);
+      await chai
+        .expect(sendMessage(program, withoutNewMember, newMember, text))
+        .to.eventually.be.rejectedWith(
+          'The sender is not a member of the dialect',
+        );
+    });
+
+    it('Admin sets the max number of members, members beyond it fail to be added', async () => {
+      const dialect = await createGroupDialect(
+        program,
+        owner,
+        members,
+        false,
+        null,
+        DEFAULT_MESSAGE_BUFFER_LENGTH,
+        members.length,
+      );
+      chai.expect(dialect.dialect.maxMembers).to.be.eq(members.length);
+      const newMember: Member = {
+        publicKey: web3.Keypair.generate().publicKey,
+        scopes: [false, true],
+      };
+      await chai
+        .expect(addMember(program, dialect, owner, newMember))
+        .to.eventually.be.rejectedWith('No member slots left in the dialect');
+      await chai
+        .expect(setMaxMembers(program, dialect, owner, members.length - 1))
+        .to.eventually.be.rejectedWith(
+          'The max number of members must be at least 2 and the number of member slots',
+        );
+      const updated = await setMaxMembers(
+        program,
+        dialect,
+        owner,
+        members.length + 1,
+      );
+      chai.expect(updated.dialect.maxMembers).to.be.eq(members.length + 1);
+      const withNewMember = await addMember(program, updated, owner, newMember);
+      chai
+        .expect(withNewMember.dialect.members.map((m) => m.publicKey))
+        .to.deep.contain(newMember.publicKey);
+    });
+
+    it('Non-admins fail to add or remove members', async () => {
+      const dialect = await createGroupDialect(program, owner, members);
+      await chai
+        .expect(
+          addMember(program, dialect, writer1, {
+            publicKey: web3.Keypair.generate().publicKey,
+            scopes: [false, true],
+          }),
+        )
+        .to.eventually.be.rejectedWith('A raw constraint was violated');
+      await chai
+        .expect(removeMember(program, dialect, writer1, writer2.publicKey))
+        .to.eventually.be.rejectedWith('A raw constraint was violated');
+    });
+
+    it('Fail to remove the last admin of a dialect', async () => {
+      const dialect = await createGroupDialect(program, owner, members);
+      await chai
+        .expect(removeMember(program, dialect, owner, owner.publicKey))
+        .to.eventually.be.rejectedWith(
+          'The last admin of a dialect cannot be removed or demoted',
+        );
+    });
+
+    it('Admin promotes a writer to admin and demotes a member to read-only', async () => {
+      const dialect = await createGroupDialect(program, owner, members);
+      await updateMemberScopes(program, dialect, owner, writer1.publicKey, [
+        true,
+        true,
+      ]);
+      const updated = await updateMemberScopes(
+        program,
+        dialect,
+        writer1,
+        writer2.publicKey,
+        [false, false],
+      );
+      const scopesByMember = updated.dialect.members.map((m) => [
+        m.publicKey,
+        m.scopes,
+      ]);
+      chai
+        .expect(scopesByMember)
+        .to.deep.contain([writer1.publicKey, [true, true]]);
+      chai
+        .expect(scopesByMember)
+        .to.deep.contain([writer2.publicKey, [false, false]]);
+      await chai
+        .expect(sendMessage(program, updated, writer2, generateRandomText(8)))
+        .to.eventually.be.rejectedWith('A raw constraint was violated');
+    });
+
+    it('Fail to demote the last admin of a dialect', async () => {
+      const dialect = await createGroupDialect(program, owner, members);
+      await chai
+        .expect(
+          updateMemberScopes(program, dialect, owner, owner.publicKey, [
+            false,
+            true,
+          ]),
+        )
+        .to.eventually.be.rejectedWith(
+          'The last admin of a dialect cannot be removed or demoted',
+        );
+    });
+
+    it('Group owner deletes the dialect', async () => {
+      const dialect = await createGroupDialect(program, owner, members);
+      await chai
+        .expect(deleteDialect(program, dialect, writer1))
+        .to.eventually.be.rejectedWith('A raw constraint was violated');
+      await deleteDialect(program, dialect, owner);
+      await chai
+        .expect(getDialect(program, dialect.publicKey))
+        .to.eventually.be.rejectedWith('does not exist');
+    });
+  });
+
+  describe('Unencrypted messaging tests', () => {
+    let owner: web3.Keypair;
+    let writer: web3.Keypair;
+    let nonmember: web3.Keypair;
+    let members: Member[] = [];
+    let dialect: DialectAccount;
+
+    beforeEach(async () => {
+      (owner = await createUser({
+        requestAirdrop: true,
+        createMeta: true,
+      }).then((it) => it.user)),
+        (writer = await createUser({
+          requestAirdrop: true,
+          createMeta: true,
+        }).then((it) => it.user)),
+        (nonmember = await createUser({
+          requestAirdrop: true,
+          createMeta: false,
+        }).then((it) => it.user)),
+        (members = [
+          {
+            publicKey: owner.publicKey,
+            scopes: [true, false], // owner, read-only
+          },
+          {
+            publicKey: writer.publicKey,
+            scopes: [false, true], // non-owner, read-write
+          },
+        ]);
+      dialect = await createDialect(program, owner, members, false);
+    });
+
+    it('Message sender and receiver can read the message text and time', async () => {
+      // given
+      const dialect = await getDialectForMembers(program, members);
+      const text = generateRandomText(256);
+      // when
+      await sendMessage(program, dialect, writer, text);
+      // then
+      const senderDialect = await getDialectForMembers(
+        program,
+        dialect.dialect.members,
+      );
+      const message = senderDialect.dialect.messages[0];
+      chai.expect(message.text).to.be.eq(text);
+      chai
+        .expect(senderDialect.dialect.lastMessageTimestamp)
+        .to.be.eq(message.timestamp);
+    });
+
+    it('Anonymous user can read any of the messages', async () => {
+      // given
+      const senderDialect = await getDialectForMembers(program, members);
+      const text = generateRandomText(256);
+      await sendMessage(program, senderDialect, writer, text);
+      // when / then
+      const nonMemberDialect = await getDialectForMembers(
+        program,
+        dialect.dialect.members,
+      );
+      const message = nonMemberDialect.dialect.messages[0];
+      chai.expect(message.text).to.be.eq(text);
+      chai.expect(message.owner).to.be.deep.eq(writer.publicKey);
+      chai
+        .expect(nonMemberDialect.dialect.lastMessageTimestamp)
+        .to.be.eq(message.timestamp);
+    });
+
+    it('New messages overwrite old, retrieved messages are in order.', async () => {
+      // emulate ideal message alignment withing buffer
+      const rawBufferSize = 8192;
+      const messagesPerDialect = 16;
+      const numMessages = messagesPerDialect * 2;
+      const salt = 3;
+      const targetRawMessageSize = rawBufferSize / messagesPerDialect - salt;
+      const timestampSize = 4;
+      const ownerMemberIdxSize = 1;
+      const messageSerializationOverhead =
+        ITEM_METADATA_OVERHEAD + timestampSize + ownerMemberIdxSize;
+      const targetTextSize =
+        targetRawMessageSize - messageSerializationOverhead;
+      const texts = Array(numMessages)
+        .fill(0)
+        .map(() => generateRandomText(targetTextSize));
+      for (let messageIdx = 0; messageIdx < numMessages; messageIdx++) {
+        // verify last last N messages look correct
+        const messageCounter = messageIdx + 1;
+        const text = texts[messageIdx];
+        const dialect = await getDialectForMembers(program, members);
+        console.log(
+          `Sending message ${messageCounter}/${texts.length}
+    len = ${text.length}
+    idx: ${dialect.dialect.nextMessageIdx}`,
+        );
+        await sendMessage(program, dialect, writer, text);
+        const sliceStart =
+          messageCounter <= messagesPerDialect
+            ? 0
+            : messageCounter - messagesPerDialect;
+        const expectedMessagesCount = Math.min(
+          messageCounter,
+          messagesPerDialect,
+        );
+        const sliceEnd = sliceStart + expectedMessagesCount;
+        const expectedMessages = texts.slice(sliceStart, sliceEnd).reverse();
+        const d = await getDialect(program, dialect.publicKey);
+        const actualMessages = d.dialect.messages.map((m) => m.text);
+        console.log(`  msgs count after send: ${actualMessages.length}\n`);
+        expect(actualMessages).to.be.deep.eq(expectedMessages);
+      }
+    });
+
+    it('Message text limit of 853 bytes can be sent/received', async () => {
+      const maxMessageSizeBytes = 853;
+      const texts = Array(30)
+        .fill(0)
+        .map(() => generateRandomText(maxMessageSizeBytes));
+      for (let messageIdx = 0; messageIdx < texts.length; messageIdx++) {
+        const text = texts[messageIdx];
+        const messageCounter = messageIdx + 1;
+        const dialect = await getDialectForMembers(program, members);
+        console.log(
+          `Sending message ${messageCounter}/${texts.length}
+  len = ${text.length}
+  idx: ${dialect.dialect.nextMessageIdx}`,
+        );
+        // when
+        await sendMessage(program, dialect, writer, text);
+        const d = await getDialect(program, dialect.publicKey);
+        const actualMessages = d.dialect.messages;
+        const lastMessage = actualMessages[0];
+        console.log(`  msgs count after send: ${actualMessages.length}\n`);
+        // then
+        expect(lastMessage.text).to.be.deep.eq(text);
+      }
+    });
+
+    it('Fail to send a message longer than the max message length', async () => {
+      const maxMessageLength = 32;
+      const updatedDialect = await setMaxMessageLength(
+        program,
+        dialect,
+        owner,
+        maxMessageLength,
+      );
+      chai
+        .expect(updatedDialect.dialect.maxMessageLength)
+        .to.be.eq(maxMessageLength);
+      try {
+        await sendMessage(
+          program,
+          updatedDialect,
+          writer,
+          generateRandomText(maxMessageLength + 1),
+        );
+        chai.assert(false, 'Sending an oversize message should fail.');
+      } catch (e) {
+        chai.assert(
+          (e as AnchorError).message.includes(
+            "The message is larger than the dialect's max message length.",
+          ),
+        );
+      }
+      const text = generateRandomText(maxMessageLength);
+      await sendMessage(program, updatedDialect, writer, text);
+      const d = await getDialect(program, dialect.publicKey);
+      chai.expect(d.dialect.messages.map((m) => m.text)).to.be.deep.eq([text]);
+    });
+
+    it('Sending fails with an error code when the sender is not a member or the message is too large', async () => {
+      await chai
+        .expect(sendMessage(program, dialect, nonmember, 'Hello'))
+        .to.eventually.be.rejectedWith(
+          'The sender is not a member of the dialect',
+        );
+      const updatedDialect = await setMaxMessageLength(
+        program,
+        dialect,
+        owner,
+        16,
+      );
+      await chai
+        .expect(
+          sendMessage(program, updatedDialect, writer, generateRandomText(17)),
+        )
+        .to.eventually.be.rejectedWith(
+          "The message is larger than the dialect's max message length",
+        );
+      // ClockUnavailable can't be triggered here: validators always provide the
+      // clock sysvar
+      const d = await getDialect(program, dialect.publicKey);
+      chai.expect(d.dialect.messages).to.be.empty;
+    });
+
+    it('Fail to set an invalid max message length, or as a non-admin', async () => {
+      await chai
+        .expect(setMaxMessageLength(program, dialect, owner, 0))
+        .to.eventually.be.rejectedWith(
+          'The max message length must be between 1 and the length of the largest message that fits in the message buffer',
+        );
+      await chai
+        .expect(setMaxMessageLength(program, dialect, owner, 8192))
+        .to.eventually.be.rejectedWith(
+          'The max message length must be between 1 and the length of the largest message that fits in the message buffer',
+        );
+      await chai
+        .expect(setMaxMessageLength(program, dialect, writer, 32))
+        .to.eventually.be.rejectedWith('A raw constraint was violated');
+    });
+
+    it('Admin grows the message buffer, messages are kept', async () => {
+      const texts = Array(3)
+        .fill(0)
+        .map(() => generateRandomText(256));
+      for (const text of texts) {
+        await sendMessage(program, dialect, writer, text);
+      }
+      const resized = await resizeDialect(
+        program,
+        dialect,
+        owner,
+        MAX_MESSAGE_BUFFER_LENGTH,
+      );
+      chai
+        .expect(resized.dialect.messageBufferCapacity)
+        .to.be.eq(MAX_MESSAGE_BUFFER_LENGTH);
+      chai
+        .expect(resized.dialect.messages.map((m) => m.text).reverse())
+        .to.be.deep.eq(texts);
+      const text = generateRandomText(256);
+      await sendMessage(program, resized, writer, text);
+      const d = await getDialect(program, dialect.publicKey);
+      chai.expect(d.dialect.messages[0].text).to.be.eq(text);
+    });
+
+    it('Admin shrinks the message buffer, the newest messages that fit are kept', async () => {
+      const texts = Array(8)
+        .fill(0)
+        .map(() => generateRandomText(256));
+      for (const text of texts) {
+        await sendMessage(program, dialect, writer, text);
+      }
+      const resized = await resizeDialect(
+        program,
+        dialect,
+        owner,
+        MIN_MESSAGE_BUFFER_LENGTH,
+      );
+      // item type, sender member index, flags, message id, timestamp & envelope
+      const messageHeaderLength = 1 + 1 + 1 + 8 + 4 + 2;
+      const messageSize = ITEM_METADATA_OVERHEAD + messageHeaderLength + 256;
+      const fittingMessagesCount = Math.floor(
+        MIN_MESSAGE_BUFFER_LENGTH / messageSize,
+      );
+      chai
+        .expect(resized.dialect.messages.map((m) => m.text).reverse())
+        .to.be.deep.eq(texts.slice(texts.length - fittingMessagesCount));
+      chai
+        .expect(resized.dialect.maxMessageLength)
+        .to.be.eq(
+          // leaves room for the id of the message edited by edits, and
+          // for the time-to-live of expiring messages
+          MIN_MESSAGE_BUFFER_LENGTH -
+            ITEM_METADATA_OVERHEAD -
+            messageHeaderLength -
+            8 -
+            4,
+        );
+    });
+
+    it('Messages have sequential ids, evicted messages leave a gap', async () => {
+      const resized = await resizeDialect(
+        program,
+        dialect,
+        owner,
+        MIN_MESSAGE_BUFFER_LENGTH,
+      );
+      for (let i = 0; i < 8; i++) {
+        await sendMessage(program, resized, writer, generateRandomText(256));
+      }
+      const d = await getDialect(program, dialect.publicKey);
+      chai.expect(d.dialect.nextMessageId).to.be.eq(8);
+      const ids = d.dialect.messages.map((m) => m.id);
+      chai
+        .expect(ids)
+        .to.be.deep.eq(ids.map((_, idx) => 7 - idx))
+        .and.to.not.include(0);
+    });
+
+    it('Sender and admin delete messages, other messages are kept', async () => {
+      const texts = Array(3)
+        .fill(0)
+        .map(() => generateRandomText(256));
+      for (const text of texts) {
+        await sendMessage(program, dialect, writer, text);
+      }
+      const sent = await getDialect(program, dialect.publicKey);
+      const [m2, m1, m0] = sent.dialect.messages;
+      await deleteMessage(program, dialect, writer, m0.id);
+      const d = await deleteMessage(program, dialect, owner, m2.id);
+      chai
+        .expect(d.dialect.messages.map((m) => [m.id, m.text, m.deleted]))
+        .to.be.deep.eq([
+          [m2.id, '', true],
+          [m1.id, texts[1], false],
+          [m0.id, '', true],
+        ]);
+      // Messages are appended after the tombstones
+      const text = generateRandomText(256);
+      await sendMessage(program, d, writer, text);
+      const d2 = await getDialect(program, dialect.publicKey);
+      chai.expect(d2.dialect.messages[0].text).to.be.eq(text);
+    });
+
+    it('Deleting a message also deletes its edits', async () => {
+      const m = await sendMessage(program, dialect, writer, 'hello');
+      const editText = generateRandomText(64);
+      await editMessage(program, dialect, writer, m.id, editText);
+      const d = await deleteMessage(program, dialect, writer, m.id);
+      chai
+        .expect(d.dialect.messages.map((it) => [it.id, it.text, it.deleted]))
+        .to.be.deep.eq([[m.id, '', true]]);
+      const account = await program.provider.connection.getAccountInfo(
+        dialect.publicKey,
+      );
+      chai.expect(account?.data.includes(Buffer.from(editText))).to.be.false;
+    });
+
+    it('Fail to delete a message of another member, or a deleted message', async () => {
+      const m = await sendMessage(program, dialect, writer, 'hello');
+      const otherWriter = (await createUser()).user;
+      const d = await addMember(program, dialect, owner, {
+        publicKey: otherWriter.publicKey,
+        scopes: [false, true],
+      });
+      await chai
+        .expect(deleteMessage(program, d, otherWriter, m.id))
+        .to.eventually.be.rejectedWith(
+          'The signer is not the sender of the message',
+        );
+      await deleteMessage(program, d, writer, m.id);
+      await chai
+        .expect(deleteMessage(program, d, writer, m.id))
+        .to.eventually.be.rejectedWith(
+          "The message was not found in the dialect's message buffer",
+        );
+    });
+
+    it('Sender edits a message within the edit window, readers see the latest edit', async () => {
+      chai.expect(dialect.dialect.editWindow).to.be.eq(DEFAULT_EDIT_WINDOW);
+      const m = await sendMessage(program, dialect, writer, 'helo');
+      await editMessage(program, dialect, writer, m.id, 'hell');
+      const d = await editMessage(program, dialect, writer, m.id, 'hello');
+      chai.expect(d.dialect.messages.length).to.be.eq(1);
+      chai
+        .expect(d.dialect.messages[0])
+        .to.deep.include({ id: m.id, text: 'hello', edited: true });
+      chai.expect(d.dialect.nextMessageId).to.be.eq(m.id + 3);
+    });
+
+    it('Fail to edit a message of another member, or after the edit window', async () => {
+      const m = await sendMessage(program, dialect, writer, 'hello');
+      await chai
+        .expect(editMessage(program, dialect, owner, m.id, 'hi'))
+        .to.eventually.be.rejectedWith('A raw constraint was violated');
+      await chai
+        .expect(setEditWindow(program, dialect, writer, 0))
+        .to.eventually.be.rejectedWith('A raw constraint was violated');
+      await setEditWindow(program, dialect, owner, 0);
+      await sleep(2000);
+      await chai
+        .expect(editMessage(program, dialect, writer, m.id, 'hi'))
+        .to.eventually.be.rejectedWith(
+          'The edit window of the message has expired',
+        );
+    });
+
+    it('Members reply to messages, replies reference the message replied to', async () => {
+      const m = await sendMessage(program, dialect, writer, 'gm');
+      const reply = await sendMessage(
+        program,
+        dialect,
+        writer,
+        'gm to you',
+        undefined,
+        m.id,
+      );
+      chai.expect(reply.replyTo).to.be.eq(m.id);
+      chai.expect(reply.text).to.be.eq('gm to you');
+      const d = await getDialect(program, dialect.publicKey);
+      chai.expect(d.dialect.messages[1].replyTo).to.be.undefined;
+      await chai
+        .expect(sendMessage(program, d, writer, 'hi', undefined, m.id + 2))
+        .to.eventually.be.rejectedWith(
+          "The message was not found in the dialect's message buffer",
+        );
+    });
+
+    it('Members react to messages, reactions are added to the message reacted to', async () => {
+      const m = await sendMessage(program, dialect, writer, 'gm');
+      const thumbsUp = 0x1f44d;
+      await react(program, dialect, writer, m.id, thumbsUp);
+      const d = await react(program, dialect, writer, m.id, thumbsUp);
+      chai.expect(d.dialect.messages.length).to.be.eq(1);
+      chai
+        .expect(d.dialect.messages[0].reactions)
+        .to.be.deep.eq([
+          { owner: writer.publicKey, reaction: thumbsUp },
+          { owner: writer.publicKey, reaction: thumbsUp },
+        ]);
+      await chai
+        .expect(react(program, d, writer, m.id + 1, thumbsUp))
+        .to.eventually.be.rejectedWith(
+          "The message was not found in the dialect's message buffer",
+        );
+      await chai
+        .expect(react(program, d, owner, m.id, thumbsUp))
+        .to.eventually.be.rejectedWith('A raw constraint was violated');
+    });
+
+    it('Messages have a content type, texts of known content types are validated', async () => {
+      const text = '{"alert": "liquidation"}';
+      const m = await sendMessage(
+        program,
+        dialect,
+        writer,
+        text,
+        undefined,
+        undefined,
+        ContentType.Json,
+      );
+      chai.expect(m.contentType).to.be.eq(ContentType.Json);
+      chai.expect(m.text).to.be.eq(text);
+      for (const invalidJson of ['{"alert": "liquidation"', '"liquidation"']) {
+        await chai
+          .expect(
+            sendMessage(
+              program,
+              dialect,
+              writer,
+              invalidJson,
+              undefined,
+              undefined,
+              ContentType.Json,
+            ),
+          )
+          .to.eventually.be.rejectedWith(
+            "The message's text is not valid for its content type",
+          );
+      }
+      await chai
+        .expect(
+          sendMessage(
+            program,
+            dialect,
+            writer,
+            'https://example.com/attachment',
+            undefined,
+            undefined,
+            ContentType.AttachmentReference,
+          ),
+        )
+        .to.eventually.be.rejectedWith(
+          "The message's text is not valid for its content type",
+        );
+    });
+
+    it('Expired messages are hidden, then erased from the front of the buffer', async () => {
+      const code1 = generateRandomText(16);
+      const code2 = generateRandomText(16);
+      const ttl = 3;
+      const m = await sendMessage(
+        program,
+        dialect,
+        writer,
+        code1,
+        null,
+        undefined,
+        ContentType.TextPlain,
+        ttl,
+      );
+      chai.expect(m.expiresAt).to.be.eq(m.timestamp + ttl * 1000);
+      await sendMessage(program, dialect, writer, 'hello');
+      await sendMessage(
+        program,
+        dialect,
+        writer,
+        code2,
+        null,
+        undefined,
+        ContentType.TextPlain,
+        ttl,
+      );
+      await sleep(4000);
+      const d = await getDialect(program, dialect.publicKey);
+      chai
+        .expect(d.dialect.messages.map((m) => m.text))
+        .to.be.deep.eq(['hello']);
+      await pruneExpired(program, dialect);
+      // Pruning stops at the first message that has not expired
+      const account = await program.provider.connection.getAccountInfo(
+        dialect.publicKey,
+      );
+      chai.expect(account?.data.includes(Buffer.from(code1))).to.be.false;
+      chai.expect(account?.data.includes(Buffer.from(code2))).to.be.true;
+      await chai
+        .expect(pinMessage(program, dialect, owner, m.id + 2))
+        .to.eventually.be.rejectedWith(
+          'Edits & expiring messages cannot be pinned',
+        );
+    });
+
+    it('Expiring messages and the reactions to them are never archived', async () => {
+      await chai
+        .expect(
+          sendMessage(
+            program,
+            dialect,
+            writer,
+            'hello',
+            null,
+            undefined,
+            ContentType.TextPlain,
+            0,
+          ),
+        )
+        .to.eventually.be.rejectedWith(
+          'The time-to-live of a message must be positive',
+        );
+      await resizeDialect(program, dialect, owner, MIN_MESSAGE_BUFFER_LENGTH);
+      await setArchiveEnabled(program, dialect, owner, true);
+      const archivingDialect = await createMessageHistoryPage(
+        program,
+        dialect,
+        writer,
+      );
+      const code = generateRandomText(16);
+      const m = await sendMessage(
+        program,
+        archivingDialect,
+        writer,
+        code,
+        null,
+        undefined,
+        ContentType.TextPlain,
+        3600,
+      );
+      const reaction = 0x1f525;
+      await react(program, archivingDialect, owner, m.id, reaction);
+      for (let i = 0; i < 8; i++) {
+        await sendMessage(
+          program,
+          archivingDialect,
+          writer,
+          generateRandomText(256),
+        );
+      }
+      const [historyPage] = await getMessageHistoryPageProgramAddress(
+        program,
+        dialect.publicKey,
+        0,
+      );
+      const page = await program.provider.connection.getAccountInfo(
+        historyPage,
+      );
+      // The id of the message reacted to, followed by the reaction code
+      const serializedReaction = Buffer.concat([
+        new anchor.BN(m.id).toArrayLike(Buffer, 'be', 8),
+        new anchor.BN(reaction).toArrayLike(Buffer, 'be', 4),
+      ]);
+      chai.expect(page?.data.includes(Buffer.from(code))).to.be.false;
+      chai.expect(page?.data.includes(serializedReaction)).to.be.false;
+      const history = await getMessageHistory(program, dialect.publicKey);
+      chai.expect(history.length).to.be.greaterThan(0);
     });
-  });
 
-  describe('Unencrypted messaging tests', () => {
-    let owner: web3.Keypair;
-    let writer: web3.Keypair;
-    let nonmember: web3.Keypair;
-    let members: Member[] = [];
-    let dialect: DialectAccount;
+    it('Admin pins a message, which is kept after it is evicted, then unpins it', async () => {
+      const resized = await resizeDialect(
+        program,
+        dialect,
+        owner,
+        MIN_MESSAGE_BUFFER_LENGTH,
+      );
+      const rules = await sendMessage(program, resized, writer, 'Be nice');
+      await chai
+        .expect(pinMessage(program, dialect, writer, rules.id))
+        .to.eventually.be.rejectedWith('A raw constraint was violated');
+      const pinned = await pinMessage(program, dialect, owner, rules.id);
+      chai
+        .expect(pinned.dialect.pinnedMessage)
+        .to.be.deep.eq(pinned.dialect.messages[0]);
+      for (let i = 0; i < 8; i++) {
+        await sendMessage(program, resized, writer, generateRandomText(256));
+      }
+      const d = await getDialect(program, dialect.publicKey);
+      chai.expect(d.dialect.messages.map((m) => m.id)).to.not.include(rules.id);
+      chai.expect(d.dialect.pinnedMessage?.id).to.be.eq(rules.id);
+      chai.expect(d.dialect.pinnedMessage?.text).to.be.eq('Be nice');
+      await chai
+        .expect(unpinMessage(program, dialect, writer))
+        .to.eventually.be.rejectedWith('A raw constraint was violated');
+      const unpinned = await unpinMessage(program, dialect, owner);
+      chai.expect(unpinned.dialect.pinnedMessage).to.be.undefined;
+      await chai
+        .expect(unpinMessage(program, dialect, owner))
+        .to.eventually.be.rejectedWith('The dialect has no pinned message');
+    });
 
-    beforeEach(async () => {
-      (owner = await createUser({
-        requestAirdrop: true,
-        createMeta: true,
-      }).then((it) => it.user)),
-        (writer = await createUser({
-          requestAirdrop: true,
-          createMeta: true,
-        }).then((it) => it.user)),
-        (nonmember = await createUser({
-          requestAirdrop: true,
-          createMeta: false,
-        }).then((it) => it.user)),
-        (members = [
-          {
-            publicKey: owner.publicKey,
-            scopes: [true, false], // owner, read-only
-          },
-          {
-            publicKey: writer.publicKey,
-            scopes: [false, true], // non-owner, read-write
-          },
-        ]);
-      dialect = await createDialect(program, owner, members, false);
+    it('The pinned message follows edits, and is unpinned when deleted', async () => {
+      const m = await sendMessage(program, dialect, writer, 'Be nic');
+      await editMessage(program, dialect, writer, m.id, 'Be nice');
+      const pinned = await pinMessage(program, dialect, owner, m.id);
+      chai.expect(pinned.dialect.pinnedMessage?.text).to.be.eq('Be nice');
+      await editMessage(program, dialect, writer, m.id, 'Be very nice');
+      const d = await getDialect(program, dialect.publicKey);
+      chai.expect(d.dialect.pinnedMessage?.text).to.be.eq('Be very nice');
+      const deleted = await deleteMessage(program, dialect, owner, m.id);
+      chai.expect(deleted.dialect.pinnedMessage).to.be.undefined;
     });
 
-    it('Message sender and receiver can read the message text and time', async () => {
-      // given
-      const dialect = await getDialectForMembers(program, members);
-      const text = generateRandomText(256);
-      // when
-      await sendMessage(program, dialect, writer, text);
-      // then
-      const senderDialect = await getDialectForMembers(
+    it('Members mark messages as read, unread counts are shared by all clients', async () => {
+      const texts = Array(3)
+        .fill(0)
+        .map(() => generateRandomText(16));
+      for (const text of texts) {
+        await sendMessage(program, dialect, writer, text);
+      }
+      const sent = await getDialect(program, dialect.publicKey);
+      const [m2, m1] = sent.dialect.messages;
+      chai.expect(getUnreadMessagesCount(sent, owner.publicKey)).to.be.eq(3);
+      const d = await markRead(program, dialect, owner, m1.id);
+      chai.expect(getUnreadMessagesCount(d, owner.publicKey)).to.be.eq(1);
+      chai.expect(getUnreadMessagesCount(d, writer.publicKey)).to.be.eq(0);
+      // Read pointers never move backwards
+      const d2 = await markRead(program, dialect, owner, 0);
+      chai.expect(getUnreadMessagesCount(d2, owner.publicKey)).to.be.eq(1);
+      await chai
+        .expect(markRead(program, dialect, owner, m2.id + 1))
+        .to.eventually.be.rejectedWith(
+          "The message was not found in the dialect's message buffer",
+        );
+      await chai
+        .expect(markRead(program, dialect, nonmember, m2.id))
+        .to.eventually.be.rejectedWith(
+          'The user is not a member of the dialect',
+        );
+    });
+
+    it('Senders read their own messages & edits, new members have no unread messages', async () => {
+      const m = await sendMessage(program, dialect, writer, 'helo');
+      const sent = await getDialect(program, dialect.publicKey);
+      chai.expect(getUnreadMessagesCount(sent, writer.publicKey)).to.be.eq(0);
+      chai.expect(getUnreadMessagesCount(sent, owner.publicKey)).to.be.eq(1);
+      const edited = await editMessage(program, dialect, writer, m.id, 'hello');
+      chai.expect(getUnreadMessagesCount(edited, writer.publicKey)).to.be.eq(0);
+      // Edits have message ids too, so they are counted as unread messages
+      chai.expect(getUnreadMessagesCount(edited, owner.publicKey)).to.be.eq(2);
+      const newMember = (await createUser()).user;
+      const withNewMember = await addMember(program, edited, owner, {
+        publicKey: newMember.publicKey,
+        scopes: [false, true],
+      });
+      chai
+        .expect(getUnreadMessagesCount(withNewMember, newMember.publicKey))
+        .to.be.eq(0);
+    });
+
+    it('Fail to resize the message buffer out of bounds, or as a non-admin', async () => {
+      await chai
+        .expect(
+          resizeDialect(program, dialect, owner, MIN_MESSAGE_BUFFER_LENGTH - 1),
+        )
+        .to.eventually.be.rejectedWith(
+          'The message buffer capacity is out of bounds',
+        );
+      await chai
+        .expect(
+          resizeDialect(program, dialect, owner, MAX_MESSAGE_BUFFER_LENGTH + 1),
+        )
+        .to.eventually.be.rejectedWith(
+          'The message buffer capacity is out of bounds',
+        );
+      await chai
+        .expect(
+          resizeDialect(program, dialect, writer, MAX_MESSAGE_BUFFER_LENGTH),
+        )
+        .to.eventually.be.rejectedWith('A raw constraint was violated');
+    });
+
+    it('Evicted messages are archived in message history pages paid by members', async () => {
+      await resizeDialect(program, dialect, owner, MIN_MESSAGE_BUFFER_LENGTH);
+      await setArchiveEnabled(program, dialect, owner, true);
+      const archivingDialect = await createMessageHistoryPage(
         program,
-        dialect.dialect.members,
+        dialect,
+        writer,
       );
-      const message = senderDialect.dialect.messages[0];
-      chai.expect(message.text).to.be.eq(text);
+      chai.expect(archivingDialect.dialect.archiveEnabled).to.be.true;
+      chai.expect(archivingDialect.dialect.historyPagesCount).to.be.eq(1);
+      const texts = Array(8)
+        .fill(0)
+        .map(() => generateRandomText(256));
+      for (const text of texts) {
+        await sendMessage(program, archivingDialect, writer, text);
+      }
+      const d = await getDialect(program, dialect.publicKey);
+      const history = await getMessageHistory(program, dialect.publicKey);
+      const allTexts = [...d.dialect.messages, ...history].map((m) => m.text);
+      chai.expect(allTexts.reverse()).to.be.deep.eq(texts);
       chai
-        .expect(senderDialect.dialect.lastMessageTimestamp)
-        .to.be.eq(message.timestamp);
+        .expect(history.map((m) => m.owner.toBase58()))
+        .to.be.deep.eq(history.map(() => writer.publicKey.toBase58()));
     });
 
-    it('Anonymous user can read any of the messages', async () => {
-      // given
-      const senderDialect = await getDialectForMembers(program, members);
-      const text = generateRandomText(256);
-      await sendMessage(program, senderDialect, writer, text);
-      // when / then
-      const nonMemberDialect = await getDialectForMembers(
+    it('Messages dropped by shrinking a dialect are archived', async () => {
+      await setArchiveEnabled(program, dialect, owner, true);
+      const archivingDialect = await createMessageHistoryPage(
         program,
-        dialect.dialect.members,
+        dialect,
+        writer,
       );
-      const message = nonMemberDialect.dialect.messages[0];
-      chai.expect(message.text).to.be.eq(text);
-      chai.expect(message.owner).to.be.deep.eq(writer.publicKey);
-      chai
-        .expect(nonMemberDialect.dialect.lastMessageTimestamp)
-        .to.be.eq(message.timestamp);
+      const texts = Array(8)
+        .fill(0)
+        .map(() => generateRandomText(256));
+      for (const text of texts) {
+        await sendMessage(program, archivingDialect, writer, text);
+      }
+      const resized = await resizeDialect(
+        program,
+        archivingDialect,
+        owner,
+        MIN_MESSAGE_BUFFER_LENGTH,
+      );
+      const history = await getMessageHistory(program, dialect.publicKey);
+      chai.expect(history.length).to.be.greaterThan(0);
+      const allTexts = [...resized.dialect.messages, ...history].map(
+        (m) => m.text,
+      );
+      chai.expect(allTexts.reverse()).to.be.deep.eq(texts);
     });
 
-    it('New messages overwrite old, retrieved messages are in order.', async () => {
-      // emulate ideal message alignment withing buffer
-      const rawBufferSize = 8192;
-      const messagesPerDialect = 16;
-      const numMessages = messagesPerDialect * 2;
-      const salt = 3;
-      const targetRawMessageSize = rawBufferSize / messagesPerDialect - salt;
-      const timestampSize = 4;
-      const ownerMemberIdxSize = 1;
-      const messageSerializationOverhead =
-        ITEM_METADATA_OVERHEAD + timestampSize + ownerMemberIdxSize;
-      const targetTextSize =
-        targetRawMessageSize - messageSerializationOverhead;
-      const texts = Array(numMessages)
+    it('Messages dropped by shrinking a full dialect by more than a page are archived across pages', async () => {
+      await resizeDialect(program, dialect, owner, MAX_MESSAGE_BUFFER_LENGTH);
+      await setArchiveEnabled(program, dialect, owner, true);
+      const withFirstPage = await createMessageHistoryPage(
+        program,
+        dialect,
+        writer,
+      );
+      const archivingDialect = await createMessageHistoryPage(
+        program,
+        withFirstPage,
+        writer,
+      );
+      const texts = Array(22)
         .fill(0)
-        .map(() => generateRandomText(targetTextSize));
-      for (let messageIdx = 0; messageIdx < numMessages; messageIdx++) {
-        // verify last last N messages look correct
-        const messageCounter = messageIdx + 1;
-        const text = texts[messageIdx];
-        const dialect = await getDialectForMembers(program, members);
-        console.log(
-          `Sending message ${messageCounter}/${texts.length}
-    len = ${text.length}
-    idx: ${dialect.dialect.nextMessageIdx}`,
-        );
-        await sendMessage(program, dialect, writer, text);
-        const sliceStart =
-          messageCounter <= messagesPerDialect
-            ? 0
-            : messageCounter - messagesPerDialect;
-        const expectedMessagesCount = Math.min(
-          messageCounter,
-          messagesPerDialect,
-        );
-        const sliceEnd = sliceStart + expectedMessagesCount;
-        const expectedMessages = texts.slice(sliceStart, sliceEnd).reverse();
-        const d = await getDialect(program, dialect.publicKey);
-        const actualMessages = d.dialect.messages.map((m) => m.text);
-        console.log(`  msgs count after send: ${actualMessages.length}\n`);
-        expect(actualMessages).to.be.deep.eq(expectedMessages);
+        .map(() => generateRandomText(768));
+      for (const text of texts) {
+        await sendMessage(program, archivingDialect, writer, text);
       }
+      const resized = await resizeDialect(
+        program,
+        archivingDialect,
+        owner,
+        MIN_MESSAGE_BUFFER_LENGTH,
+      );
+      const [lastPageAddress] = await getMessageHistoryPageProgramAddress(
+        program,
+        dialect.publicKey,
+        1,
+      );
+      const lastPage = await program.account.messageHistoryPage.fetch(
+        lastPageAddress,
+      );
+      chai.expect(lastPage.itemsCount).to.be.greaterThan(0);
+      const history = await getMessageHistory(program, dialect.publicKey);
+      const allTexts = [...resized.dialect.messages, ...history].map(
+        (m) => m.text,
+      );
+      chai.expect(allTexts.reverse()).to.be.deep.eq(texts);
     });
 
-    it('Message text limit of 853 bytes can be sent/received', async () => {
-      const maxMessageSizeBytes = 853;
-      const texts = Array(30)
+    it('Fail to evict messages without a message history page, or to enable archival as a non-admin', async () => {
+      await chai
+        .expect(setArchiveEnabled(program, dialect, writer, true))
+        .to.eventually.be.rejectedWith('A raw constraint was violated');
+      await resizeDialect(program, dialect, owner, MIN_MESSAGE_BUFFER_LENGTH);
+      const archivingDialect = await setArchiveEnabled(
+        program,
+        dialect,
+        owner,
+        true,
+      );
+      const texts = Array(3)
         .fill(0)
-        .map(() => generateRandomText(maxMessageSizeBytes));
-      for (let messageIdx = 0; messageIdx < texts.length; messageIdx++) {
-        const text = texts[messageIdx];
-        const messageCounter = messageIdx + 1;
-        const dialect = await getDialectForMembers(program, members);
-        console.log(
-          `Sending message ${messageCounter}/${texts.length}
-  len = ${text.length}
-  idx: ${dialect.dialect.nextMessageIdx}`,
-        );
-        // when
-        await sendMessage(program, dialect, writer, text);
-        const d = await getDialect(program, dialect.publicKey);
-        const actualMessages = d.dialect.messages;
-        const lastMessage = actualMessages[0];
-        console.log(`  msgs count after send: ${actualMessages.length}\n`);
-        // then
-        expect(lastMessage.text).to.be.deep.eq(text);
+        .map(() => generateRandomText(256));
+      for (const text of texts) {
+        await sendMessage(program, archivingDialect, writer, text);
       }
+      const text = generateRandomText(256);
+      await chai
+        .expect(sendMessage(program, archivingDialect, writer, text))
+        .to.eventually.be.rejectedWith(
+          "The dialect's last message history pages are required to archive evicted messages",
+        );
     });
   });
 
@@ -1027,6 +2462,168 @@ describe('Protocol v1 test', () => {
     });
   });
 
+  describe('Broadcast channel tests', () => {
+    let publisher: web3.Keypair;
+    let subscriber: web3.Keypair;
+    let channel: BroadcastChannelAccount;
+
+    beforeEach(async () => {
+      publisher = await createUser({
+        requestAirdrop: true,
+        createMeta: false,
+      }).then((it) => it.user);
+      subscriber = await createUser({
+        requestAirdrop: true,
+        createMeta: true,
+      }).then((it) => it.user);
+      channel = await createBroadcastChannel(program, publisher);
+    });
+
+    it('Publisher broadcasts messages that subscribers read', async () => {
+      chai.expect(channel.channel.publisher).to.be.deep.eq(publisher.publicKey);
+      chai.expect(channel.channel.messages).to.be.empty;
+      const texts = ['Price alert', 'Governance vote'];
+      for (const text of texts) {
+        await broadcastMessage(program, channel, publisher, text);
+      }
+      await subscribeToBroadcastChannel(program, channel, subscriber);
+      const [subscribed] = await getBroadcastChannels(
+        program,
+        subscriber.publicKey,
+      );
+      chai.expect(subscribed.publicKey).to.be.deep.eq(channel.publicKey);
+      chai
+        .expect(subscribed.channel.messages.map((m) => [m.id, m.text]))
+        .to.be.deep.eq([
+          [1, texts[1]],
+          [0, texts[0]],
+        ]);
+      chai
+        .expect(subscribed.channel.messages.map((m) => m.owner))
+        .to.be.deep.eq([publisher.publicKey, publisher.publicKey]);
+    });
+
+    it('Fail to broadcast a message as a non-publisher', async () => {
+      const otherChannel = await createBroadcastChannel(program, subscriber);
+      await chai
+        .expect(broadcastMessage(program, channel, subscriber, 'Phishing'))
+        .to.eventually.be.rejectedWith('A seeds constraint was violated');
+      await chai
+        .expect(broadcastMessage(program, otherChannel, publisher, 'Phishing'))
+        .to.eventually.be.rejectedWith('A seeds constraint was violated');
+    });
+
+    it('Broadcast channels are kept apart from dialects in subscriptions', async () => {
+      const dialect = await createDialect(program, subscriber, [
+        { publicKey: subscriber.publicKey, scopes: [true, true] },
+        { publicKey: publisher.publicKey, scopes: [false, true] },
+      ]);
+      await subscribeUser(program, dialect, subscriber.publicKey, subscriber);
+      const metadata = await subscribeToBroadcastChannel(
+        program,
+        channel,
+        subscriber,
+      );
+      chai
+        .expect(metadata.subscriptions.map((s) => s.pubkey))
+        .to.be.deep.eq([dialect.publicKey, channel.publicKey]);
+      const dialects = await getDialects(program, subscriber);
+      chai
+        .expect(dialects.map((d) => d.publicKey))
+        .to.be.deep.eq([dialect.publicKey]);
+      await chai
+        .expect(subscribeToBroadcastChannel(program, channel, subscriber))
+        .to.eventually.be.rejectedWith('A raw constraint was violated');
+      await unsubscribeUser(program, channel, subscriber);
+      const channels = await getBroadcastChannels(
+        program,
+        subscriber.publicKey,
+      );
+      chai.expect(channels).to.be.empty;
+    });
+
+    it('Subscribers only receive the broadcast messages of their topics', async () => {
+      await subscribeToBroadcastChannel(program, channel, subscriber);
+      const allTopics = await getSubscriptionTopics(
+        program,
+        channel,
+        subscriber.publicKey,
+      );
+      chai.expect(allTopics.toString(16)).to.be.eq('ffffffffffffffff');
+      const topics = await setSubscriptionTopics(
+        program,
+        channel,
+        subscriber,
+        [1, 63],
+      );
+      chai.expect(topics.toString(16)).to.be.eq('8000000000000002');
+      await broadcastMessage(program, channel, publisher, 'Announcement');
+      const news = await broadcastMessage(
+        program,
+        channel,
+        publisher,
+        'Liquidation warning',
+        ContentType.TextPlain,
+        1,
+      );
+      chai.expect(news.topic).to.be.eq(1);
+      await broadcastMessage(
+        program,
+        channel,
+        publisher,
+        'Marketing',
+        ContentType.TextPlain,
+        2,
+      );
+      await broadcastMessage(
+        program,
+        channel,
+        publisher,
+        'Maintenance',
+        ContentType.TextPlain,
+        63,
+      );
+      const [subscribed] = await getBroadcastChannels(
+        program,
+        subscriber.publicKey,
+      );
+      chai
+        .expect(subscribed.channel.messages.map((m) => [m.text, m.topic]))
+        .to.be.deep.eq([
+          ['Maintenance', 63],
+          ['Liquidation warning', 1],
+          ['Announcement', undefined],
+        ]);
+      await deleteSubscriptionTopics(program, channel, subscriber);
+      const [unfiltered] = await getBroadcastChannels(
+        program,
+        subscriber.publicKey,
+      );
+      chai.expect(unfiltered.channel.messages).to.have.length(4);
+      await chai
+        .expect(
+          broadcastMessage(
+            program,
+            channel,
+            publisher,
+            'Invalid',
+            ContentType.TextPlain,
+            MAX_TOPICS,
+          ),
+        )
+        .to.eventually.be.rejectedWith(
+          'Topic ids must be lower than MAX_TOPICS',
+        );
+    });
+
+    it('Publisher closes the broadcast channel', async () => {
+      await closeBroadcastChannel(program, channel, publisher);
+      await chai
+        .expect(getBroadcastChannel(program, channel.publicKey))
+        .to.eventually.be.rejectedWith('does not exist');
+    });
+  });
+
   describe('Subscription tests', () => {
     let owner: web3.Keypair;
     let writer: web3.Keypair;
@@ -1072,6 +2669,52 @@ describe('Protocol v1 test', () => {
       // then
       chai.expect(eventsAccumulator.length).to.be.eq(expectedEvents);
     });
+
+    it('Message sent events describe the message, with its payload only when enabled', async () => {
+      // given
+      const dialect = await createDialect(program, owner, [
+        { publicKey: owner.publicKey, scopes: [true, true] },
+        { publicKey: writer.publicKey, scopes: [false, true] },
+      ]);
+      const messageSentEvents: MessageSentEvent[] = [];
+      const countDownLatch = new CountDownLatch(2);
+      const subscription = await subscribeToEvents(program, async (it) => {
+        if (
+          it.type === 'message-sent' &&
+          it.dialect.equals(dialect.publicKey)
+        ) {
+          messageSentEvents.push(it);
+          countDownLatch.countDown();
+        }
+      });
+      // when
+      const text = generateRandomText(64);
+      await sendMessage(program, dialect, writer, text);
+      await setEmitMessagePayloads(program, dialect, owner, true);
+      await sendMessage(program, dialect, writer, text);
+      await countDownLatch.await(5000);
+      await subscription.unsubscribe();
+      // then
+      const d = await getDialect(program, dialect.publicKey);
+      const payload = new TextEncoder().encode(text);
+      chai
+        .expect(messageSentEvents.map((it) => it.id))
+        .to.be.deep.eq(d.dialect.messages.map((m) => m.id).reverse());
+      chai
+        .expect(messageSentEvents.map((it) => it.timestamp))
+        .to.be.deep.eq(d.dialect.messages.map((m) => m.timestamp).reverse());
+      messageSentEvents.forEach((it) => {
+        chai.expect(it.sender.equals(writer.publicKey)).to.be.true;
+        chai.expect(it.length).to.be.eq(payload.length);
+        chai
+          .expect(Buffer.from(it.payloadHash).toString('hex'))
+          .to.be.eq(sha256(payload));
+      });
+      chai.expect(messageSentEvents[0].payload).to.be.null;
+      chai
+        .expect(messageSentEvents[1].payload)
+        .to.be.deep.eq(new Uint8Array(payload));
+    });
   });
 
   async function createUser(